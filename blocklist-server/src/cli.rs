@@ -1,4 +1,5 @@
 use clap::Parser;
+use dns_smart_block_common::jetstream::StreamArgs;
 use dns_smart_block_common::logging::LoggingArgs;
 use std::path::PathBuf;
 
@@ -9,6 +10,9 @@ pub struct CliArgs {
   #[command(flatten)]
   pub logging: LoggingArgs,
 
+  #[command(flatten)]
+  pub stream: StreamArgs,
+
   /// PostgreSQL connection URL (without password if using password file)
   #[arg(long, env = "DATABASE_URL")]
   pub database_url: String,
@@ -36,4 +40,41 @@ pub struct CliArgs {
   /// NATS subject for the domain queue
   #[arg(long, env = "NATS_SUBJECT", default_value = "dns.domains")]
   pub nats_subject: String,
+
+  /// Confidence an LLM classification must reach to be served on
+  /// /blocklist, unless overridden per type or by the min_confidence query
+  /// parameter
+  #[arg(long, env = "MIN_CONFIDENCE", default_value_t = 0.8, value_parser = parse_confidence)]
+  pub min_confidence: f64,
+
+  /// Confidence threshold for one classification type as TYPE=VALUE, e.g.
+  /// gaming=0.7 (repeatable)
+  #[arg(
+    long,
+    env = "TYPE_MIN_CONFIDENCE",
+    value_delimiter = ',',
+    value_parser = parse_type_confidence
+  )]
+  pub type_min_confidence: Vec<(String, f64)>,
+}
+
+fn parse_confidence(value: &str) -> Result<f64, String> {
+  let confidence: f64 = value.parse().map_err(|e| format!("{}", e))?;
+  if !(0.0..=1.0).contains(&confidence) {
+    return Err(format!(
+      "confidence must be between 0.0 and 1.0, got {}",
+      confidence
+    ));
+  }
+  Ok(confidence)
+}
+
+fn parse_type_confidence(value: &str) -> Result<(String, f64), String> {
+  let (classification_type, confidence) = value
+    .split_once('=')
+    .ok_or_else(|| format!("expected TYPE=VALUE, got '{}'", value))?;
+  Ok((
+    classification_type.to_string(),
+    parse_confidence(confidence)?,
+  ))
 }
@@ -144,14 +144,17 @@ mod tests {
     .unwrap();
 
     // Query gaming domains
-    let gaming_domains =
-      get_blocked_domains(&pool, "gaming", None).await.unwrap();
+    let gaming_domains = get_blocked_domains(&pool, "gaming", None, None)
+      .await
+      .unwrap();
     assert_eq!(gaming_domains.len(), 2);
     assert!(gaming_domains.contains(&"gaming1.com".to_string()));
     assert!(gaming_domains.contains(&"gaming2.com".to_string()));
 
     // Query news domains
-    let news_domains = get_blocked_domains(&pool, "news", None).await.unwrap();
+    let news_domains = get_blocked_domains(&pool, "news", None, None)
+      .await
+      .unwrap();
     assert_eq!(news_domains.len(), 1);
     assert!(news_domains.contains(&"news1.com".to_string()));
   }
@@ -194,7 +197,9 @@ mod tests {
     .unwrap();
 
     // Should not return expired domain
-    let domains = get_blocked_domains(&pool, "gaming", None).await.unwrap();
+    let domains = get_blocked_domains(&pool, "gaming", None, None)
+      .await
+      .unwrap();
     assert_eq!(domains.len(), 0);
   }
 
@@ -237,7 +242,9 @@ mod tests {
     .unwrap();
 
     // Should not return at current time
-    let domains_now = get_blocked_domains(&pool, "gaming", None).await.unwrap();
+    let domains_now = get_blocked_domains(&pool, "gaming", None, None)
+      .await
+      .unwrap();
     assert_eq!(domains_now.len(), 0);
 
     // Should return at future time
@@ -245,6 +252,7 @@ mod tests {
       &pool,
       "gaming",
       Some(future_start + Duration::hours(1)),
+      None,
     )
     .await
     .unwrap();
@@ -1218,7 +1226,9 @@ mod tests {
     .await
     .unwrap();
 
-    let domains = get_blocked_domains(&pool, "gaming", None).await.unwrap();
+    let domains = get_blocked_domains(&pool, "gaming", None, None)
+      .await
+      .unwrap();
     assert!(
       domains.contains(&"example.com".to_string()),
       "domain with 'all' record should appear in gaming blocklist"
@@ -1283,7 +1293,9 @@ mod tests {
     .await
     .unwrap();
 
-    let domains = get_blocked_domains(&pool, "gaming", None).await.unwrap();
+    let domains = get_blocked_domains(&pool, "gaming", None, None)
+      .await
+      .unwrap();
     assert!(
       domains.contains(&"wins-true.com".to_string()),
       "per-type true should win over all false"
@@ -1348,7 +1360,9 @@ mod tests {
     .await
     .unwrap();
 
-    let domains = get_blocked_domains(&pool, "gaming", None).await.unwrap();
+    let domains = get_blocked_domains(&pool, "gaming", None, None)
+      .await
+      .unwrap();
     assert!(
       !domains.contains(&"wins-false.com".to_string()),
       "per-type false should win over all true"
@@ -1409,4 +1423,86 @@ mod tests {
 
     assert_eq!(count, 2, "two admin decisions → two source rows");
   }
+
+  #[tokio::test]
+  #[serial]
+
+  async fn test_get_blocked_domains_threshold_skips_only_llm_rows() {
+    let (_db, pool) = setup_test_db().await;
+
+    let llm_source =
+      ensure_test_source(&pool, "test prompt", "sha256:threshold").await;
+    let admin_source: i32 = sqlx::query_scalar(
+      "INSERT INTO classification_sources (source_type, user_id, created_at) \
+       VALUES ('admin', 1, NOW()) RETURNING id",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+
+    let now = Utc::now();
+    for (domain, classification_type, confidence, source_id) in [
+      ("confident.com", "gaming", 0.9_f32, llm_source),
+      ("borderline.com", "gaming", 0.7, llm_source),
+      ("admin.com", "gaming", 0.5, admin_source),
+      // A below-threshold per-type result falls back to the "all" record.
+      ("borderline.com", "all", 1.0, admin_source),
+    ] {
+      sqlx::query(
+        "INSERT INTO domains (domain, last_updated) VALUES ($1, NOW()) \
+         ON CONFLICT DO NOTHING",
+      )
+      .bind(domain)
+      .execute(&pool)
+      .await
+      .unwrap();
+      sqlx::query(
+        r#"
+        INSERT INTO domain_classifications (
+            domain, classification_type, confidence, valid_on, valid_until,
+            model, source_id, created_at
+        )
+        VALUES ($1, $2, $3, $4, $5, 'test-model', $6, NOW())
+        "#,
+      )
+      .bind(domain)
+      .bind(classification_type)
+      .bind(confidence)
+      .bind(now)
+      .bind(now + Duration::days(10))
+      .bind(source_id)
+      .execute(&pool)
+      .await
+      .unwrap();
+    }
+
+    let domains = get_blocked_domains(&pool, "gaming", None, Some(0.8))
+      .await
+      .unwrap();
+    assert_eq!(
+      domains,
+      vec!["admin.com", "borderline.com", "confident.com"]
+    );
+
+    // The all fallback does not apply while the per-type row counts, and a
+    // threshold equal to the stored confidence includes the row.
+    sqlx::query(
+      "UPDATE domain_classifications SET is_matching_site = false \
+       WHERE domain = 'borderline.com' AND classification_type = 'all'",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    let domains = get_blocked_domains(&pool, "gaming", None, Some(0.7))
+      .await
+      .unwrap();
+    assert_eq!(
+      domains,
+      vec!["admin.com", "borderline.com", "confident.com"]
+    );
+    let domains = get_blocked_domains(&pool, "gaming", None, Some(0.8))
+      .await
+      .unwrap();
+    assert_eq!(domains, vec!["admin.com", "confident.com"]);
+  }
 }
@@ -102,6 +102,23 @@ lazy_static! {
     Opts::new("dns_smart_block_recent_classifications_5m", "Classified events in the last 5 minutes by type"),
     &["classification_type"]
   ).unwrap();
+
+  // Queue-processor state recorded in the database.
+  pub static ref LLM_CIRCUIT_OPEN: IntGaugeVec = register_int_gauge_vec!(
+    Opts::new(
+      "dns_smart_block_llm_circuit_open",
+      "1 while the queue processor has paused consumption because the LLM backend is unreachable",
+    ),
+    &["backend"]
+  ).unwrap();
+
+  pub static ref PENDING_REVIEWS: IntGaugeVec = register_int_gauge_vec!(
+    Opts::new(
+      "dns_smart_block_pending_reviews",
+      "Low-confidence classifications awaiting human review by type",
+    ),
+    &["classification_type"]
+  ).unwrap();
 }
 
 /// Update all database-derived gauges from a freshly fetched `MetricsStats`.
@@ -146,4 +163,19 @@ pub fn update_from_stats(stats: &MetricsStats) {
       .with_label_values(&[classification_type])
       .set(*count);
   }
+
+  for (backend, open) in &stats.llm_circuit_open_by_backend {
+    LLM_CIRCUIT_OPEN
+      .with_label_values(&[backend])
+      .set(i64::from(*open));
+  }
+
+  // Reset first so a type whose backlog was cleared reports no series rather
+  // than its last non-zero count.
+  PENDING_REVIEWS.reset();
+  for (classification_type, count) in &stats.pending_reviews_by_type {
+    PENDING_REVIEWS
+      .with_label_values(&[classification_type])
+      .set(*count);
+  }
 }
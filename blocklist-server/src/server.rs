@@ -14,13 +14,16 @@ use dns_smart_block_blocklist_server::{
   CLASSIFICATIONS_CSS, CLASSIFICATIONS_HTML, ELM_JS,
 };
 use dns_smart_block_common::db::{
-  DomainExpire, DomainRequeue, ErroredClassification,
-  apply_admin_pattern_classification,
+  ClassificationReview, DomainExpire, DomainRequeue, ErroredClassification,
+  ReviewFilter, apply_admin_pattern_classification,
+};
+use dns_smart_block_common::jetstream::{
+  MSG_ID_HEADER, Origin, Priority, ensure_stream,
 };
 use prometheus::{Encoder, TextEncoder};
 use serde::Deserialize;
 use sqlx::PgPool;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use tower_http::trace::TraceLayer;
 use tracing::{error, info, warn};
 
@@ -31,22 +34,59 @@ pub struct NatsState {
   subject: String,
 }
 
+/// Confidence thresholds applied to LLM classifications on `/blocklist`.
+#[derive(Clone)]
+pub struct ConfidenceThresholds {
+  default: f64,
+  per_type: HashMap<String, f64>,
+}
+
+impl ConfidenceThresholds {
+  fn for_type(&self, classification_type: &str) -> f64 {
+    self
+      .per_type
+      .get(classification_type)
+      .copied()
+      .unwrap_or(self.default)
+  }
+}
+
 /// Shared application state injected into all HTTP handlers.
 #[derive(Clone)]
 pub struct AppState {
   pool: PgPool,
   nats: Option<NatsState>,
+  thresholds: ConfidenceThresholds,
 }
 
-async fn publish_to_nats(nats: &NatsState, domain: &str) -> Result<(), String> {
+/// Headers for publishing an admin requeue.  Requeues are deduplicated
+/// separately from DNS-driven messages, like refreshes, so a bulk requeue on
+/// the low lane never swallows an interactive message for the same domain.
+fn requeue_headers(domain: &str) -> async_nats::HeaderMap {
+  let mut headers = async_nats::HeaderMap::new();
+  headers.insert(MSG_ID_HEADER, format!("requeue:{}", domain).as_str());
+  headers
+}
+
+async fn publish_to_nats(
+  nats: &NatsState,
+  domain: &str,
+  priority: Priority,
+) -> Result<(), String> {
   let payload = serde_json::json!({
     "domain": domain,
-    "timestamp": Utc::now().timestamp()
+    "timestamp": Utc::now().timestamp(),
+    "priority": priority,
+    "origin": Origin::Requeue,
   });
   let bytes = serde_json::to_vec(&payload).map_err(|e| e.to_string())?;
   nats
     .client
-    .publish(nats.subject.clone(), bytes.into())
+    .publish_with_headers(
+      priority.subject(&nats.subject),
+      requeue_headers(domain),
+      bytes.into(),
+    )
     .await
     .map_err(|e| e.to_string())?;
   Ok(())
@@ -63,6 +103,11 @@ struct BlocklistParams {
   /// Optional time to check (ISO 8601 format).  Defaults to current time.
   #[serde(rename = "at", default)]
   at_time: Option<String>,
+
+  /// Optional confidence threshold for LLM classifications.  Defaults to the
+  /// configured threshold for the type.
+  #[serde(default)]
+  min_confidence: Option<f64>,
 }
 
 async fn get_blocklist(
@@ -87,21 +132,37 @@ async fn get_blocklist(
     None
   };
 
+  let min_confidence = match params.min_confidence {
+    Some(c) if !(0.0..=1.0).contains(&c) => {
+      metrics::BLOCKLIST_REQUESTS_TOTAL
+        .with_label_values(&[params.classification_type.as_str(), "error"])
+        .inc();
+      return (
+        StatusCode::BAD_REQUEST,
+        format!("min_confidence must be between 0.0 and 1.0, got {}", c),
+      );
+    }
+    Some(c) => c,
+    None => state.thresholds.for_type(&params.classification_type),
+  };
+
   match db::get_blocked_domains(
     &state.pool,
     &params.classification_type,
     check_time,
+    Some(min_confidence),
   )
   .await
   {
     Ok(domains) => {
       info!(
-        "Serving {} domains for classification type '{}' at time {:?}",
+        "Serving {} domains for classification type '{}' at time {:?} and confidence {}",
         domains.len(),
         params.classification_type,
         check_time
           .map(|t| t.to_rfc3339())
-          .unwrap_or_else(|| "now".to_string())
+          .unwrap_or_else(|| "now".to_string()),
+        min_confidence
       );
 
       metrics::BLOCKLIST_REQUESTS_TOTAL
@@ -344,10 +405,19 @@ async fn expire(
   }
 }
 
+/// Admin requeues are background work by default, so they never delay
+/// domains a client is waiting on.  Pass `priority=interactive` to jump the
+/// queue.
+fn default_requeue_priority() -> Priority {
+  Priority::Low
+}
+
 #[derive(Deserialize)]
 struct RequeueParams {
   domain: String,
   classification_type: String,
+  #[serde(default = "default_requeue_priority")]
+  priority: Priority,
 }
 
 async fn requeue(
@@ -428,7 +498,8 @@ async fn requeue(
     );
   }
 
-  if let Err(e) = publish_to_nats(&nats, &params.domain).await {
+  if let Err(e) = publish_to_nats(&nats, &params.domain, params.priority).await
+  {
     error!("Failed to publish domain to NATS: {}", e);
     return (
       StatusCode::INTERNAL_SERVER_ERROR,
@@ -452,6 +523,14 @@ async fn requeue(
 #[derive(Deserialize)]
 struct RequeueTypeParams {
   classification_type: String,
+  #[serde(default = "default_requeue_priority")]
+  priority: Priority,
+}
+
+#[derive(Deserialize)]
+struct RequeueAllParams {
+  #[serde(default = "default_requeue_priority")]
+  priority: Priority,
 }
 
 async fn requeue_type(
@@ -545,7 +624,7 @@ async fn requeue_type(
 
   let mut published = 0usize;
   for domain in &domains {
-    match publish_to_nats(&nats, domain).await {
+    match publish_to_nats(&nats, domain, params.priority).await {
       Ok(_) => published += 1,
       Err(e) => warn!("Failed to publish '{}' to NATS: {}", domain, e),
     }
@@ -564,7 +643,10 @@ async fn requeue_type(
   )
 }
 
-async fn requeue_all(State(state): State<AppState>) -> impl IntoResponse {
+async fn requeue_all(
+  State(state): State<AppState>,
+  Query(params): Query<RequeueAllParams>,
+) -> impl IntoResponse {
   let nats = match &state.nats {
     Some(n) => n.clone(),
     None => {
@@ -638,7 +720,7 @@ async fn requeue_all(State(state): State<AppState>) -> impl IntoResponse {
 
   let mut published = 0usize;
   for domain in &domains {
-    match publish_to_nats(&nats, domain).await {
+    match publish_to_nats(&nats, domain, params.priority).await {
       Ok(_) => published += 1,
       Err(e) => warn!("Failed to publish '{}' to NATS: {}", domain, e),
     }
@@ -800,6 +882,113 @@ async fn classify(
   }
 }
 
+async fn get_reviews(
+  State(state): State<AppState>,
+  Query(filter): Query<ReviewFilter>,
+) -> impl IntoResponse {
+  match ClassificationReview::find_pending(&state.pool, &filter).await {
+    Ok(reviews) => {
+      info!("Serving {} pending reviews", reviews.len());
+      (StatusCode::OK, axum::Json(reviews)).into_response()
+    }
+    Err(e) => {
+      error!("Database error fetching pending reviews: {}", e);
+      (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        axum::Json(Vec::<ClassificationReview>::new()),
+      )
+        .into_response()
+    }
+  }
+}
+
+#[derive(Deserialize)]
+struct ApproveReviewRequest {
+  id: i32,
+  /// TTL in days.  Omit (or pass null) for a classification that never expires.
+  ttl_days: Option<i64>,
+  #[serde(default = "default_classify_user_id")]
+  user_id: i32,
+}
+
+async fn approve_review(
+  State(state): State<AppState>,
+  axum::Json(req): axum::Json<ApproveReviewRequest>,
+) -> impl IntoResponse {
+  info!("Approving review {}", req.id);
+  match db::approve_review(&state.pool, req.id, req.user_id, req.ttl_days).await
+  {
+    Ok(Some(source_id)) => {
+      info!("Review {} approved, source_id={}", req.id, source_id);
+      (
+        StatusCode::OK,
+        format!("Review approved: source_id={}\n", source_id),
+      )
+    }
+    Ok(None) => (
+      StatusCode::NOT_FOUND,
+      format!("No pending review with id {}\n", req.id),
+    ),
+    Err(e) => {
+      error!("Approving review {} failed: {}", req.id, e);
+      (StatusCode::INTERNAL_SERVER_ERROR, format!("Error: {}\n", e))
+    }
+  }
+}
+
+#[derive(Deserialize)]
+struct RejectReviewRequest {
+  id: i32,
+  #[serde(default = "default_classify_user_id")]
+  user_id: i32,
+}
+
+async fn reject_review(
+  State(state): State<AppState>,
+  axum::Json(req): axum::Json<RejectReviewRequest>,
+) -> impl IntoResponse {
+  info!("Rejecting review {}", req.id);
+  match db::reject_review(&state.pool, req.id, req.user_id).await {
+    Ok(true) => (StatusCode::OK, "Review rejected\n".to_string()),
+    Ok(false) => (
+      StatusCode::NOT_FOUND,
+      format!("No pending review with id {}\n", req.id),
+    ),
+    Err(e) => {
+      error!("Rejecting review {} failed: {}", req.id, e);
+      (StatusCode::INTERNAL_SERVER_ERROR, format!("Error: {}\n", e))
+    }
+  }
+}
+
+#[derive(Deserialize)]
+struct ShadowCompareParams {
+  classification_type: String,
+}
+
+async fn compare_shadow(
+  State(state): State<AppState>,
+  Query(params): Query<ShadowCompareParams>,
+) -> impl IntoResponse {
+  match db::compare_shadow(&state.pool, &params.classification_type).await {
+    Ok(comparison) => {
+      info!(
+        "Shadow comparison for '{}': {} of {} domains agree",
+        params.classification_type, comparison.agreed, comparison.compared
+      );
+      (StatusCode::OK, axum::Json(comparison)).into_response()
+    }
+    Err(e) => {
+      error!(
+        "Database error comparing shadow for '{}': {}",
+        params.classification_type, e
+      );
+      (StatusCode::INTERNAL_SERVER_ERROR, format!("Error: {}\n", e))
+        .into_response()
+    }
+  }
+}
+
 // ── static asset handlers ─────────────────────────────────────────────────────
 
 async fn static_css() -> impl IntoResponse {
@@ -832,6 +1021,10 @@ fn admin_router(state: AppState) -> Router {
     .route("/requeue", post(requeue))
     .route("/requeue/type", post(requeue_type))
     .route("/requeue/all", post(requeue_all))
+    .route("/reviews", get(get_reviews))
+    .route("/reviews/approve", post(approve_review))
+    .route("/reviews/reject", post(reject_review))
+    .route("/shadow/compare", get(compare_shadow))
     .route("/static/classifications.css", get(static_css))
     .route("/static/elm.js", get(static_elm_js))
     .layer(TraceLayer::new_for_http())
@@ -885,10 +1078,22 @@ pub async fn run(args: CliArgs) -> Result<(), Box<dyn std::error::Error>> {
     match async_nats::connect(nats_url).await {
       Ok(client) => {
         info!("Connected to NATS successfully");
-        Some(NatsState {
-          client,
-          subject: args.nats_subject.clone(),
-        })
+        let jetstream = async_nats::jetstream::new(client.clone());
+        match ensure_stream(
+          &jetstream,
+          args.stream.stream_config(&args.nats_subject),
+        )
+        .await
+        {
+          Ok(_) => Some(NatsState {
+            client,
+            subject: args.nats_subject.clone(),
+          }),
+          Err(e) => {
+            error!("{}.  Requeue functionality will be disabled.", e);
+            None
+          }
+        }
       }
       Err(e) => {
         error!(
@@ -903,7 +1108,14 @@ pub async fn run(args: CliArgs) -> Result<(), Box<dyn std::error::Error>> {
     None
   };
 
-  let state = AppState { pool, nats };
+  let state = AppState {
+    pool,
+    nats,
+    thresholds: ConfidenceThresholds {
+      default: args.min_confidence,
+      per_type: args.type_min_confidence.iter().cloned().collect(),
+    },
+  };
 
   let public_listen = args
     .public_listen
@@ -1079,7 +1291,14 @@ mod tests {
   }
 
   fn test_state(pool: PgPool) -> AppState {
-    AppState { pool, nats: None }
+    AppState {
+      pool,
+      nats: None,
+      thresholds: ConfidenceThresholds {
+        default: 0.8,
+        per_type: HashMap::from([("video".to_string(), 0.5)]),
+      },
+    }
   }
 
   fn make_public_server(pool: PgPool) -> TestServer {
@@ -1135,6 +1354,58 @@ mod tests {
     assert!(!body.contains("safe.com"), "should not contain safe.com");
   }
 
+  #[tokio::test]
+  #[serial]
+  async fn test_blocklist_applies_confidence_threshold() {
+    let (_db, pool) = setup_test_db().await;
+    let source_id =
+      ensure_test_source(&pool, "test prompt", "sha256:http-threshold").await;
+
+    insert_classified_domain(&pool, "sure.com", "gaming", true, source_id)
+      .await;
+    insert_classified_domain(&pool, "unsure.com", "gaming", true, source_id)
+      .await;
+    insert_classified_domain(&pool, "clip.com", "video", true, source_id).await;
+    sqlx::query(
+      "UPDATE domain_classifications SET confidence = 0.6 \
+       WHERE domain IN ('unsure.com', 'clip.com')",
+    )
+    .execute(&pool)
+    .await
+    .expect("update confidence");
+
+    let server = make_public_server(pool);
+
+    // The default threshold is 0.8; "video" is configured at 0.5.
+    let body = server
+      .get("/blocklist")
+      .add_query_param("type", "gaming")
+      .await
+      .text();
+    assert_eq!(body, "sure.com");
+    let body = server
+      .get("/blocklist")
+      .add_query_param("type", "video")
+      .await
+      .text();
+    assert_eq!(body, "clip.com");
+
+    let body = server
+      .get("/blocklist")
+      .add_query_param("type", "gaming")
+      .add_query_param("min_confidence", "0.6")
+      .await
+      .text();
+    assert_eq!(body, "sure.com\nunsure.com");
+
+    let response = server
+      .get("/blocklist")
+      .add_query_param("type", "gaming")
+      .add_query_param("min_confidence", "1.5")
+      .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+  }
+
   #[tokio::test]
   #[serial]
   async fn test_blocklist_missing_type_param() {
@@ -1324,7 +1595,7 @@ mod tests {
     response.assert_status_ok();
 
     // Verify the domain no longer appears in blocklist.
-    let domains = db::get_blocked_domains(&pool, "gaming", None)
+    let domains = db::get_blocked_domains(&pool, "gaming", None, None)
       .await
       .unwrap();
     assert!(
@@ -1402,6 +1673,39 @@ mod tests {
     response.assert_status(StatusCode::SERVICE_UNAVAILABLE);
   }
 
+  #[tokio::test]
+  #[serial]
+  async fn test_requeue_rejects_unknown_priority() {
+    let (_db, pool) = setup_test_db().await;
+    let server = make_admin_server(pool);
+
+    let response = server
+      .post("/requeue/all")
+      .add_query_param("priority", "urgent")
+      .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+
+    let response = server
+      .post("/requeue/all")
+      .add_query_param("priority", "interactive")
+      .await;
+    response.assert_status(StatusCode::SERVICE_UNAVAILABLE);
+  }
+
+  #[test]
+  fn test_requeue_does_not_deduplicate_interactive_publishes() {
+    let id = |headers: async_nats::HeaderMap| {
+      headers.get(MSG_ID_HEADER).map(|v| v.as_str().to_string())
+    };
+    let requeued = id(requeue_headers("game.com"));
+    assert_eq!(requeued.as_deref(), Some("requeue:game.com"));
+    // A DNS hit published after the requeue gets a message ID of its own.
+    let interactive = id(dns_smart_block_common::jetstream::domain_headers(
+      "game.com",
+    ));
+    assert_ne!(requeued, interactive);
+  }
+
   // ── admin: POST /classify ────────────────────────────────────────────
 
   #[tokio::test]
@@ -1429,7 +1733,7 @@ mod tests {
     );
 
     // Verify it shows up in blocklist.
-    let domains = db::get_blocked_domains(&pool, "gaming", None)
+    let domains = db::get_blocked_domains(&pool, "gaming", None, None)
       .await
       .unwrap();
     assert!(domains.contains(&"admin-blocked.com".to_string()));
@@ -1538,6 +1842,155 @@ mod tests {
     response.assert_status_ok();
   }
 
+  // ── admin: /reviews ──────────────────────────────────────────────────
+
+  async fn insert_review(
+    pool: &PgPool,
+    domain: &str,
+    classification_type: &str,
+    confidence: f32,
+  ) -> i32 {
+    sqlx::query(
+      "INSERT INTO domains (domain) VALUES ($1) ON CONFLICT DO NOTHING",
+    )
+    .bind(domain)
+    .execute(pool)
+    .await
+    .expect("Failed to insert domain");
+    db::ReviewInsert {
+      domain: domain.to_string(),
+      classification_type: classification_type.to_string(),
+      is_matching_site: true,
+      confidence,
+      reasoning: Some("Looks like a game wiki".to_string()),
+      model: "test-model".to_string(),
+      source_id: None,
+    }
+    .upsert(pool)
+    .await
+    .expect("Failed to insert review")
+  }
+
+  #[tokio::test]
+  #[serial]
+  async fn test_reviews_filter_by_type_and_confidence() {
+    let (_db, pool) = setup_test_db().await;
+    insert_review(&pool, "low.com", "gaming", 0.3).await;
+    insert_review(&pool, "borderline.com", "gaming", 0.7).await;
+    insert_review(&pool, "news.com", "news", 0.7).await;
+
+    let server = make_admin_server(pool);
+    let response = server
+      .get("/reviews")
+      .add_query_param("classification_type", "gaming")
+      .add_query_param("min_confidence", "0.5")
+      .add_query_param("max_confidence", "0.7")
+      .await;
+
+    response.assert_status_ok();
+    let reviews: Vec<serde_json::Value> = response.json();
+    assert_eq!(reviews.len(), 1);
+    assert_eq!(reviews[0]["domain"], "borderline.com");
+  }
+
+  #[tokio::test]
+  #[serial]
+  async fn test_approve_review_applies_admin_classification() {
+    let (_db, pool) = setup_test_db().await;
+    let id = insert_review(&pool, "review-me.com", "gaming", 0.6).await;
+
+    let server = make_admin_server(pool.clone());
+    let response = server
+      .post("/reviews/approve")
+      .json(&serde_json::json!({ "id": id }))
+      .await;
+    response.assert_status_ok();
+
+    let domains = db::get_blocked_domains(&pool, "gaming", None, None)
+      .await
+      .unwrap();
+    assert!(domains.contains(&"review-me.com".to_string()));
+
+    // The review is no longer pending, so approving it again is a 404.
+    let response = server
+      .post("/reviews/approve")
+      .json(&serde_json::json!({ "id": id }))
+      .await;
+    response.assert_status(StatusCode::NOT_FOUND);
+  }
+
+  #[tokio::test]
+  #[serial]
+  async fn test_reject_review_discards_verdict() {
+    let (_db, pool) = setup_test_db().await;
+    let id = insert_review(&pool, "reject-me.com", "gaming", 0.6).await;
+
+    let server = make_admin_server(pool.clone());
+    let response = server
+      .post("/reviews/reject")
+      .json(&serde_json::json!({ "id": id }))
+      .await;
+    response.assert_status_ok();
+
+    let domains = db::get_blocked_domains(&pool, "gaming", None, None)
+      .await
+      .unwrap();
+    assert!(!domains.contains(&"reject-me.com".to_string()));
+
+    let reviews: Vec<serde_json::Value> = server.get("/reviews").await.json();
+    assert!(reviews.is_empty());
+  }
+
+  // ── admin: GET /shadow/compare ───────────────────────────────────────
+
+  async fn insert_classified_event(
+    pool: &PgPool,
+    domain: &str,
+    classification_type: &str,
+    is_matching_site: bool,
+  ) {
+    db::insert_event(
+      pool,
+      domain,
+      "classified",
+      serde_json::json!({
+        "classification_type": classification_type,
+        "is_matching_site": is_matching_site,
+        "confidence": 0.9,
+      }),
+      None,
+    )
+    .await
+    .expect("Failed to insert classified event");
+  }
+
+  #[tokio::test]
+  #[serial]
+  async fn test_shadow_compare_reports_disagreements() {
+    let (_db, pool) = setup_test_db().await;
+    insert_classified_event(&pool, "agree.com", "gaming", true).await;
+    insert_classified_event(&pool, "agree.com", "shadow:gaming", true).await;
+    insert_classified_event(&pool, "differ.com", "gaming", false).await;
+    insert_classified_event(&pool, "differ.com", "shadow:gaming", true).await;
+    // Only classified by the live classifier: not compared.
+    insert_classified_event(&pool, "live-only.com", "gaming", true).await;
+
+    let server = make_admin_server(pool);
+    let response = server
+      .get("/shadow/compare")
+      .add_query_param("classification_type", "gaming")
+      .await;
+
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["shadow_type"], "shadow:gaming");
+    assert_eq!(body["compared"], 2);
+    assert_eq!(body["agreed"], 1);
+    assert_eq!(body["agreement_rate"], 0.5);
+    assert_eq!(body["disagreements"][0]["domain"], "differ.com");
+    assert_eq!(body["disagreements"][0]["shadow_is_matching_site"], true);
+  }
+
   // ── admin: static assets ─────────────────────────────────────────────
 
   #[tokio::test]
@@ -0,0 +1,320 @@
+//! LLM backends the classifier can talk to.
+//!
+//! Every backend is asked for a JSON object matching a schema and returns it
+//! as text; the caller parses the classification out of it.  Ollama is
+//! supported through both its native generate and chat endpoints, and
+//! anything speaking the OpenAI chat completions API (llama.cpp server, vLLM,
+//! LocalAI, LM Studio) through `openai_chat`.
+
+use crate::error::{ClassificationIssue, ClassifierError};
+use crate::schema::strict_schema;
+use crate::{OllamaRequest, OllamaResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::time::Duration;
+use tracing::error;
+
+/// The API an LLM backend speaks.
+#[derive(
+  Debug,
+  Clone,
+  Copy,
+  Default,
+  PartialEq,
+  Eq,
+  Serialize,
+  Deserialize,
+  clap::ValueEnum,
+)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum BackendKind {
+  /// Ollama's `/api/generate`.
+  #[default]
+  OllamaGenerate,
+  /// Ollama's `/api/chat`.
+  OllamaChat,
+  /// The OpenAI-compatible `/v1/chat/completions`.
+  OpenaiChat,
+}
+
+impl BackendKind {
+  pub fn as_str(self) -> &'static str {
+    match self {
+      BackendKind::OllamaGenerate => "ollama_generate",
+      BackendKind::OllamaChat => "ollama_chat",
+      BackendKind::OpenaiChat => "openai_chat",
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChatMessage {
+  pub role: String,
+  pub content: String,
+}
+
+impl ChatMessage {
+  pub fn user(content: impl Into<String>) -> Self {
+    Self {
+      role: "user".to_string(),
+      content: content.into(),
+    }
+  }
+
+  pub fn assistant(content: impl Into<String>) -> Self {
+    Self {
+      role: "assistant".to_string(),
+      content: content.into(),
+    }
+  }
+}
+
+/// Request payload sent to Ollama's `/api/chat` endpoint.
+#[derive(Serialize, Debug, Clone)]
+pub struct OllamaChatRequest {
+  pub model: String,
+  pub messages: Vec<ChatMessage>,
+  /// JSON Schema the answer must match
+  pub format: Value,
+  pub stream: bool,
+}
+
+/// Response from Ollama's `/api/chat` endpoint.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OllamaChatResponse {
+  pub message: ChatMessage,
+}
+
+/// Request payload sent to an OpenAI-compatible `/v1/chat/completions`.
+#[derive(Serialize, Debug, Clone)]
+pub struct OpenAiChatRequest {
+  pub model: String,
+  pub messages: Vec<ChatMessage>,
+  /// A `json_schema` response format wrapping the answer's JSON Schema
+  pub response_format: Value,
+  pub stream: bool,
+}
+
+/// The assistant message of an OpenAI-compatible completion.  A model that
+/// refuses to answer sends a `refusal` instead of any content.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OpenAiMessage {
+  pub content: Option<String>,
+  #[serde(default)]
+  pub refusal: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OpenAiChoice {
+  pub message: OpenAiMessage,
+}
+
+/// Response from an OpenAI-compatible `/v1/chat/completions`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OpenAiChatResponse {
+  pub choices: Vec<OpenAiChoice>,
+}
+
+/// A configured LLM backend.
+#[derive(Debug, Clone)]
+pub struct LlmBackend {
+  pub kind: BackendKind,
+  /// Base URL, without the API path (e.g., "http://localhost:11434").
+  pub url: String,
+  /// Sent as a bearer token when set.
+  pub api_key: Option<String>,
+  /// Maximum time for a whole completion request, when set.
+  pub timeout: Option<Duration>,
+}
+
+/// Whether an error status from the backend is worth retrying later: it is
+/// overloaded, still loading the model, or behind a failing gateway.
+fn is_retryable_status(status: reqwest::StatusCode) -> bool {
+  matches!(status.as_u16(), 408 | 429 | 502 | 503 | 504)
+}
+
+/// Tell LLM call timeouts apart from other request errors.
+fn llm_error(e: reqwest::Error) -> ClassifierError {
+  if e.is_timeout() {
+    error!("LLM API timeout");
+    ClassifierError::LlmApiTimeout(e)
+  } else if e.is_decode() {
+    error!("Failed to decode LLM API response");
+    ClassifierError::LlmResponseDecode(e)
+  } else {
+    if e.is_connect() {
+      error!("Failed to connect to LLM API");
+    }
+    ClassifierError::HttpError(e)
+  }
+}
+
+impl LlmBackend {
+  pub fn new(kind: BackendKind, url: &str, api_key: Option<String>) -> Self {
+    Self {
+      kind,
+      url: url.trim_end_matches('/').to_string(),
+      api_key,
+      timeout: None,
+    }
+  }
+
+  /// Give up on completion requests after `timeout`.
+  pub fn with_timeout(self, timeout: Duration) -> Self {
+    Self {
+      timeout: Some(timeout),
+      ..self
+    }
+  }
+
+  fn request(
+    &self,
+    client: &reqwest::Client,
+    method: reqwest::Method,
+    path: &str,
+  ) -> reqwest::RequestBuilder {
+    let builder = client.request(method, format!("{}{}", self.url, path));
+    match &self.api_key {
+      Some(key) => builder.bearer_auth(key),
+      None => builder,
+    }
+  }
+
+  /// Send the conversation in `messages` to `model`, constraining the answer
+  /// to `schema`, and return the JSON text it answered with.  The generate
+  /// API has no notion of turns, so there the messages are concatenated into
+  /// a single prompt.  Timeouts and statuses the backend returns while
+  /// overloaded are reported as `LlmApiTimeout` and `LlmApiUnavailable`, so
+  /// callers can retry instead of failing the domain.  A body that does not
+  /// decode is an `LlmResponseDecode`, and a refusal from an OpenAI-compatible
+  /// backend comes back as the `Refused` classification issue.
+  pub async fn complete(
+    &self,
+    model: &str,
+    messages: &[ChatMessage],
+    schema: &Value,
+  ) -> Result<String, ClassifierError> {
+    let mut client = reqwest::Client::builder();
+    if let Some(timeout) = self.timeout {
+      client = client.timeout(timeout);
+    }
+    let client = client.build()?;
+    let request = match self.kind {
+      BackendKind::OllamaGenerate => self
+        .request(&client, reqwest::Method::POST, "/api/generate")
+        .json(&OllamaRequest {
+          model: model.to_string(),
+          prompt: messages
+            .iter()
+            .map(|m| m.content.as_str())
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+          format: schema.clone(),
+          stream: false,
+        }),
+      BackendKind::OllamaChat => self
+        .request(&client, reqwest::Method::POST, "/api/chat")
+        .json(&OllamaChatRequest {
+          model: model.to_string(),
+          messages: messages.to_vec(),
+          format: schema.clone(),
+          stream: false,
+        }),
+      BackendKind::OpenaiChat => self
+        .request(&client, reqwest::Method::POST, "/v1/chat/completions")
+        .json(&OpenAiChatRequest {
+          model: model.to_string(),
+          messages: messages.to_vec(),
+          response_format: json!({
+            "type": "json_schema",
+            "json_schema": {
+              "name": "classification",
+              "strict": true,
+              "schema": strict_schema(schema),
+            }
+          }),
+          stream: false,
+        }),
+    };
+
+    let response = request.send().await.map_err(llm_error)?;
+
+    let status = response.status();
+    if !status.is_success() {
+      error!("LLM API returned error: {}", status);
+      if is_retryable_status(status) {
+        return Err(ClassifierError::LlmApiUnavailable(status));
+      }
+      return Err(ClassifierError::HttpError(
+        response.error_for_status().unwrap_err(),
+      ));
+    }
+
+    let content = match self.kind {
+      BackendKind::OllamaGenerate => {
+        response
+          .json::<OllamaResponse>()
+          .await
+          .map_err(llm_error)?
+          .response
+      }
+      BackendKind::OllamaChat => {
+        response
+          .json::<OllamaChatResponse>()
+          .await
+          .map_err(llm_error)?
+          .message
+          .content
+      }
+      BackendKind::OpenaiChat => {
+        let message = response
+          .json::<OpenAiChatResponse>()
+          .await
+          .map_err(llm_error)?
+          .choices
+          .into_iter()
+          .next()
+          .ok_or(ClassifierError::EmptyLlmResponse)?
+          .message;
+        match (message.content, message.refusal) {
+          (Some(content), _) => content,
+          (None, Some(refusal)) => {
+            return Err(ClassifierError::InvalidClassification(
+              ClassificationIssue::Refused(refusal),
+            ));
+          }
+          (None, None) => return Err(ClassifierError::EmptyLlmResponse),
+        }
+      }
+    };
+    Ok(content)
+  }
+
+  /// Check that the backend is up and answering API requests by listing its
+  /// models.  Used to decide when to resume classification after the
+  /// backend was found unreachable.
+  pub async fn check_health(
+    &self,
+    timeout: Duration,
+  ) -> Result<(), ClassifierError> {
+    let client = reqwest::Client::builder().timeout(timeout).build()?;
+    let path = match self.kind {
+      BackendKind::OllamaGenerate | BackendKind::OllamaChat => "/api/tags",
+      BackendKind::OpenaiChat => "/v1/models",
+    };
+    self
+      .request(&client, reqwest::Method::GET, path)
+      .send()
+      .await?
+      .error_for_status()?;
+    Ok(())
+  }
+}
+
+/// Read an API key from `path`, ignoring surrounding whitespace.
+pub fn read_api_key(path: &std::path::Path) -> Result<String, ClassifierError> {
+  std::fs::read_to_string(path)
+    .map(|key| key.trim().to_string())
+    .map_err(ClassifierError::ApiKeyFileReadError)
+}
@@ -0,0 +1,469 @@
+//! On-disk cache of fetched landing pages.
+//!
+//! Reclassifying after a prompt change, or running several classifiers on
+//! the same domain, would otherwise download every landing page again.  The
+//! cache keeps the metadata extracted from a page, keyed by the domain as
+//! given and the proxy it was fetched through, along with the URL it was
+//! finally served from and a hash of the body.  Entries expire after a
+//! maximum age, and the oldest are evicted when the cache outgrows its size
+//! limit, checked at most once a minute across all classifier runs.  Only
+//! pages served with a 2xx or 3xx status are cached; failures and error
+//! pages are always fetched again.  The cache is best-effort: a read or
+//! write error is logged and treated as a miss.
+
+use crate::web_classify::{ExtractionLimits, FetchedPage, SiteMetadata};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+const DEFAULT_CACHE_MAX_AGE_SEC: u64 = 86400;
+const DEFAULT_CACHE_MAX_MB: u64 = 100;
+/// Minimum time between two scans of the cache directory for eviction.
+const EVICT_INTERVAL: Duration = Duration::from_secs(60);
+/// File in the cache directory whose modification time records the last
+/// eviction.
+const EVICTED_MARKER: &str = ".evicted";
+
+/// Where and for how long fetched pages are cached.
+#[derive(clap::Args, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct FetchCacheConfig {
+  /// Directory to cache fetched pages in, shared across runs.  Pages are
+  /// not cached without one.
+  #[arg(long, env = "CACHE_DIR")]
+  pub cache_dir: Option<PathBuf>,
+
+  /// Maximum age of a cached page, in seconds.
+  #[arg(
+    long,
+    env = "CACHE_MAX_AGE_SEC",
+    default_value_t = DEFAULT_CACHE_MAX_AGE_SEC
+  )]
+  pub cache_max_age_sec: u64,
+
+  /// Maximum size of the cache directory in MB.  The oldest pages are
+  /// evicted beyond it.
+  #[arg(long, env = "CACHE_MAX_MB", default_value_t = DEFAULT_CACHE_MAX_MB)]
+  pub cache_max_mb: u64,
+
+  /// Neither read nor write the cache, fetching every page afresh.
+  #[arg(long, env = "NO_CACHE")]
+  #[serde(skip)]
+  pub no_cache: bool,
+}
+
+impl Default for FetchCacheConfig {
+  fn default() -> Self {
+    Self {
+      cache_dir: None,
+      cache_max_age_sec: DEFAULT_CACHE_MAX_AGE_SEC,
+      cache_max_mb: DEFAULT_CACHE_MAX_MB,
+      no_cache: false,
+    }
+  }
+}
+
+impl FetchCacheConfig {
+  /// The cache to use for pages fetched through `proxy`, if caching is on.
+  /// A proxy can see other content than a direct fetch (a filtering proxy,
+  /// another country), so pages are only shared between runs using the same
+  /// proxy URL.
+  pub fn open(&self, proxy: Option<&str>) -> Option<FetchCache> {
+    match (&self.cache_dir, self.no_cache) {
+      (Some(dir), false) => Some(FetchCache {
+        dir: dir.clone(),
+        max_age: Duration::from_secs(self.cache_max_age_sec),
+        max_bytes: self.cache_max_mb * 1024 * 1024,
+        proxy: proxy.map(str::to_string),
+        evict_interval: EVICT_INTERVAL,
+      }),
+      _ => None,
+    }
+  }
+}
+
+/// A cached landing page.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CachedPage {
+  /// Domain or URL that was fetched
+  pub domain: String,
+  /// URL the page was served from, after redirects
+  pub final_url: String,
+  /// Hex SHA-256 of the body as received
+  pub body_sha256: String,
+  /// Seconds since the Unix epoch
+  pub fetched_at: u64,
+  pub scheme: String,
+  pub remote_addr: Option<SocketAddr>,
+  /// Budgets the metadata was extracted with
+  pub extraction: ExtractionLimits,
+  /// Size limit the body was read with, in KB
+  pub max_kb: usize,
+  pub metadata: SiteMetadata,
+}
+
+impl CachedPage {
+  /// Cache entry for the metadata extracted from `page`.
+  pub fn new(
+    domain: &str,
+    page: &FetchedPage,
+    extraction: &ExtractionLimits,
+    max_kb: usize,
+    metadata: SiteMetadata,
+  ) -> Self {
+    Self {
+      domain: domain.to_string(),
+      final_url: page.final_url.clone(),
+      body_sha256: page.body_sha256.clone(),
+      fetched_at: unix_time(SystemTime::now()),
+      scheme: page.scheme.clone(),
+      remote_addr: page.remote_addr,
+      extraction: extraction.clone(),
+      max_kb,
+      metadata,
+    }
+  }
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+  time
+    .duration_since(UNIX_EPOCH)
+    .map(|elapsed| elapsed.as_secs())
+    .unwrap_or_default()
+}
+
+/// A directory of cached pages, one JSON file per domain and proxy.
+#[derive(Debug, Clone)]
+pub struct FetchCache {
+  dir: PathBuf,
+  max_age: Duration,
+  max_bytes: u64,
+  /// Proxy URL pages are fetched through, part of every key
+  proxy: Option<String>,
+  evict_interval: Duration,
+}
+
+impl FetchCache {
+  fn path(&self, domain: &str) -> PathBuf {
+    let mut hasher = Sha256::new();
+    hasher.update(domain.to_ascii_lowercase());
+    if let Some(proxy) = &self.proxy {
+      hasher.update([0]);
+      hasher.update(proxy);
+    }
+    let key = hex::encode(hasher.finalize());
+    self.dir.join(format!("{}.json", key))
+  }
+
+  /// The page cached for `domain`, if one was extracted with the same
+  /// budgets and is recent enough.
+  pub fn get(
+    &self,
+    domain: &str,
+    extraction: &ExtractionLimits,
+    max_kb: usize,
+  ) -> Option<CachedPage> {
+    let path = self.path(domain);
+    let json = match std::fs::read(&path) {
+      Ok(json) => json,
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+      Err(e) => {
+        warn!("Failed to read cached page {}: {}", path.display(), e);
+        return None;
+      }
+    };
+    let page: CachedPage = serde_json::from_slice(&json)
+      .inspect_err(|e| {
+        warn!("Ignoring unreadable cached page {}: {}", path.display(), e)
+      })
+      .ok()?;
+    let age = unix_time(SystemTime::now()).saturating_sub(page.fetched_at);
+    if age > self.max_age.as_secs() {
+      info!("Cached page of {} expired {}s ago", domain, age);
+      return None;
+    }
+    if page.domain != domain
+      || page.extraction != *extraction
+      || page.max_kb != max_kb
+    {
+      info!("Cached page of {} was fetched with other settings", domain);
+      return None;
+    }
+    info!("Using page of {} cached {}s ago", domain, age);
+    Some(page)
+  }
+
+  /// Cache `page`, then evict old entries if no run did in the last
+  /// minute.  Error pages are not cached, so
+  /// a site that is briefly down is not judged by its error page for a whole
+  /// cache lifetime.  The file is written under a temporary name and
+  /// renamed, so concurrent classifiers never read a partial entry.
+  pub fn put(&self, page: &CachedPage) {
+    let status = page.metadata.http_status;
+    if !(200..400).contains(&status) {
+      info!("Not caching page of {} served with {}", page.domain, status);
+      return;
+    }
+    let write = || -> std::io::Result<()> {
+      std::fs::create_dir_all(&self.dir)?;
+      let json = serde_json::to_vec(page)?;
+      let path = self.path(&page.domain);
+      let tmp = path.with_extension(format!("tmp.{}", std::process::id()));
+      std::fs::write(&tmp, json)?;
+      std::fs::rename(&tmp, &path)
+    };
+    match write() {
+      Ok(()) if self.eviction_due() => self.evict(),
+      Ok(()) => {}
+      Err(e) => warn!("Failed to cache page of {}: {}", page.domain, e),
+    }
+  }
+
+  /// Whether the last eviction was long enough ago to scan the directory
+  /// again, recording this one if so.  Every classifier run is a separate
+  /// process caching a page or two, so the time of the last eviction is kept
+  /// in the modification time of a marker file.  Two runs racing past it
+  /// merely both evict.
+  fn eviction_due(&self) -> bool {
+    let marker = self.dir.join(EVICTED_MARKER);
+    let recent = std::fs::metadata(&marker)
+      .and_then(|metadata| metadata.modified())
+      .is_ok_and(|last| {
+        last
+          .elapsed()
+          .is_ok_and(|elapsed| elapsed < self.evict_interval)
+      });
+    if recent {
+      return false;
+    }
+    if let Err(e) = std::fs::write(&marker, b"") {
+      warn!("Failed to record eviction in {}: {}", marker.display(), e);
+    }
+    true
+  }
+
+  /// Remove expired entries, then the oldest until the cache fits its size
+  /// limit.
+  fn evict(&self) {
+    let entries = match std::fs::read_dir(&self.dir) {
+      Ok(entries) => entries,
+      Err(e) => {
+        warn!("Failed to list cache {}: {}", self.dir.display(), e);
+        return;
+      }
+    };
+    let mut files: Vec<(SystemTime, u64, PathBuf)> = entries
+      .filter_map(|entry| {
+        let entry = entry.ok()?;
+        let path = entry.path();
+        if path.extension()? != "json" {
+          return None;
+        }
+        let metadata = entry.metadata().ok()?;
+        Some((metadata.modified().ok()?, metadata.len(), path))
+      })
+      .collect();
+    files.sort();
+
+    let expired_before = SystemTime::now()
+      .checked_sub(self.max_age)
+      .unwrap_or(UNIX_EPOCH);
+    let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+    for (modified, len, path) in files {
+      if modified >= expired_before && total <= self.max_bytes {
+        break;
+      }
+      remove(&path);
+      total -= len;
+    }
+  }
+}
+
+fn remove(path: &Path) {
+  if let Err(e) = std::fs::remove_file(path) {
+    warn!("Failed to evict cached page {}: {}", path.display(), e);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn page(domain: &str, title: &str) -> FetchedPage {
+    FetchedPage {
+      body: title.to_string(),
+      encoding: "UTF-8".to_string(),
+      body_sha256: "00".to_string(),
+      status: 200,
+      scheme: "https".to_string(),
+      remote_addr: None,
+      final_url: format!("https://{}/", domain),
+      redirects: Vec::new(),
+      certificate: None,
+    }
+  }
+
+  fn entry(domain: &str, title: &str) -> CachedPage {
+    let page = page(domain, title);
+    CachedPage::new(
+      domain,
+      &page,
+      &ExtractionLimits::default(),
+      200,
+      SiteMetadata {
+        domain: domain.to_string(),
+        title: Some(page.body.clone()),
+        http_status: 200,
+        ..SiteMetadata::default()
+      },
+    )
+  }
+
+  fn cached_pages(dir: &Path) -> usize {
+    std::fs::read_dir(dir)
+      .unwrap()
+      .filter(|entry| {
+        entry
+          .as_ref()
+          .unwrap()
+          .path()
+          .extension()
+          .is_some_and(|ext| ext == "json")
+      })
+      .count()
+  }
+
+  fn cache(dir: &Path, max_age_sec: u64, max_mb: u64) -> FetchCache {
+    FetchCacheConfig {
+      cache_dir: Some(dir.to_path_buf()),
+      cache_max_age_sec: max_age_sec,
+      cache_max_mb: max_mb,
+      no_cache: false,
+    }
+    .open(None)
+    .unwrap()
+  }
+
+  #[test]
+  fn test_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = cache(dir.path(), 3600, 10);
+    let limits = ExtractionLimits::default();
+    assert!(cache.get("game.com", &limits, 200).is_none());
+
+    cache.put(&entry("game.com", "Game Store"));
+    let cached = cache.get("game.com", &limits, 200).unwrap();
+    assert_eq!(cached.final_url, "https://game.com/");
+    assert_eq!(cached.metadata.title, Some("Game Store".to_string()));
+    assert_eq!(cached.metadata.http_status, 200);
+
+    // Other budgets would have extracted other metadata.
+    assert!(cache.get("game.com", &limits, 100).is_none());
+    let other = ExtractionLimits {
+      body_text_chars: 0,
+      ..ExtractionLimits::default()
+    };
+    assert!(cache.get("game.com", &other, 200).is_none());
+    assert!(cache.get("other.com", &limits, 200).is_none());
+  }
+
+  #[test]
+  fn test_proxies_do_not_share_pages() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = FetchCacheConfig {
+      cache_dir: Some(dir.path().to_path_buf()),
+      ..FetchCacheConfig::default()
+    };
+    let limits = ExtractionLimits::default();
+    let proxied = config.open(Some("socks5h://127.0.0.1:9050")).unwrap();
+    proxied.put(&entry("game.com", "Game Store"));
+    assert!(proxied.get("game.com", &limits, 200).is_some());
+    assert!(
+      config
+        .open(None)
+        .unwrap()
+        .get("game.com", &limits, 200)
+        .is_none()
+    );
+    assert!(
+      config
+        .open(Some("http://proxy.lan:3128"))
+        .unwrap()
+        .get("game.com", &limits, 200)
+        .is_none()
+    );
+  }
+
+  #[test]
+  fn test_error_pages_are_not_cached() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = cache(dir.path(), 3600, 10);
+    let limits = ExtractionLimits::default();
+    for status in [404, 503] {
+      let mut error_page = entry("down.com", "Service Unavailable");
+      error_page.metadata.http_status = status;
+      cache.put(&error_page);
+      assert!(cache.get("down.com", &limits, 200).is_none(), "{}", status);
+    }
+
+    let mut redirect = entry("moved.com", "Moved");
+    redirect.metadata.http_status = 301;
+    cache.put(&redirect);
+    assert!(cache.get("moved.com", &limits, 200).is_some());
+  }
+
+  #[test]
+  fn test_expiry_and_eviction() {
+    let dir = tempfile::tempdir().unwrap();
+    let limits = ExtractionLimits::default();
+
+    let mut old = entry("old.com", "Old");
+    old.fetched_at -= 10;
+    cache(dir.path(), 3600, 10).put(&old);
+    assert!(
+      cache(dir.path(), 60, 10)
+        .get("old.com", &limits, 200)
+        .is_some()
+    );
+    assert!(
+      cache(dir.path(), 5, 10)
+        .get("old.com", &limits, 200)
+        .is_none()
+    );
+
+    // A limit of 0 MB keeps nothing around.
+    let full = FetchCache {
+      evict_interval: Duration::ZERO,
+      ..cache(dir.path(), 3600, 0)
+    };
+    full.put(&entry("game.com", "Game Store"));
+    assert_eq!(cached_pages(dir.path()), 0);
+  }
+
+  #[test]
+  fn test_eviction_is_throttled() {
+    let dir = tempfile::tempdir().unwrap();
+    let full = cache(dir.path(), 3600, 0);
+    full.put(&entry("game.com", "Game Store"));
+    assert_eq!(cached_pages(dir.path()), 0);
+
+    // The directory was just scanned, so the next page stays for now.
+    full.put(&entry("other.com", "Other"));
+    assert_eq!(cached_pages(dir.path()), 1);
+  }
+
+  #[test]
+  fn test_disabled() {
+    let config = FetchCacheConfig::default();
+    assert!(config.open(None).is_none());
+    let config = FetchCacheConfig {
+      cache_dir: Some(PathBuf::from("/tmp/cache")),
+      no_cache: true,
+      ..FetchCacheConfig::default()
+    };
+    assert!(config.open(None).is_none());
+  }
+}
@@ -0,0 +1,238 @@
+//! TLS certificate details of a fetched host.
+//!
+//! API endpoints, CDNs and telemetry hosts rarely serve useful HTML, but the
+//! certificate they present usually names the brand behind them (e.g.
+//! `prod.api.pvp.net` serving a `*.riotgames.com` certificate).  The
+//! certificate is taken from the page fetch when it got a response, and from
+//! a handshake of its own when it did not.  Certificates are read, never
+//! verified: an expired or self-signed certificate still says who runs the
+//! host.
+
+use crate::egress::EgressPolicy;
+use crate::web_classify::happy_eyeballs_order;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::rustls::client::danger::{
+  HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+};
+use tokio_rustls::rustls::crypto::{
+  CryptoProvider, aws_lc_rs, verify_tls12_signature, verify_tls13_signature,
+};
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use tokio_rustls::rustls::{
+  ClientConfig, DigitallySignedStruct, Error as TlsError, SignatureScheme,
+};
+use tracing::{info, warn};
+use x509_parser::extensions::GeneralName;
+
+/// Maximum number of subject alternative names kept.  CDN certificates can
+/// list hundreds.
+const MAX_SUBJECT_ALT_NAMES: usize = 20;
+
+/// Identity fields of a host's leaf certificate.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CertificateInfo {
+  /// Subject distinguished name (e.g., "CN=*.riotgames.com")
+  pub subject: String,
+  /// Issuer distinguished name
+  pub issuer: String,
+  /// DNS subject alternative names, in certificate order
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub subject_alt_names: Vec<String>,
+}
+
+impl CertificateInfo {
+  /// Parse a DER encoded certificate.
+  pub fn from_der(der: &[u8]) -> Option<Self> {
+    let (_, cert) = x509_parser::parse_x509_certificate(der)
+      .inspect_err(|e| warn!("Failed to parse peer certificate: {}", e))
+      .ok()?;
+    let subject_alt_names = match cert.subject_alternative_name() {
+      Ok(Some(san)) => san
+        .value
+        .general_names
+        .iter()
+        .filter_map(|name| match name {
+          GeneralName::DNSName(dns) => Some(dns.to_string()),
+          _ => None,
+        })
+        .take(MAX_SUBJECT_ALT_NAMES)
+        .collect(),
+      _ => Vec::new(),
+    };
+    Some(Self {
+      subject: cert.subject().to_string(),
+      issuer: cert.issuer().to_string(),
+      subject_alt_names,
+    })
+  }
+}
+
+/// Accepts any certificate; the handshake is only used to read it.
+#[derive(Debug)]
+struct AcceptAnyCertificate(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+  fn verify_server_cert(
+    &self,
+    _end_entity: &CertificateDer<'_>,
+    _intermediates: &[CertificateDer<'_>],
+    _server_name: &ServerName<'_>,
+    _ocsp_response: &[u8],
+    _now: UnixTime,
+  ) -> Result<ServerCertVerified, TlsError> {
+    Ok(ServerCertVerified::assertion())
+  }
+
+  fn verify_tls12_signature(
+    &self,
+    message: &[u8],
+    cert: &CertificateDer<'_>,
+    dss: &DigitallySignedStruct,
+  ) -> Result<HandshakeSignatureValid, TlsError> {
+    verify_tls12_signature(
+      message,
+      cert,
+      dss,
+      &self.0.signature_verification_algorithms,
+    )
+  }
+
+  fn verify_tls13_signature(
+    &self,
+    message: &[u8],
+    cert: &CertificateDer<'_>,
+    dss: &DigitallySignedStruct,
+  ) -> Result<HandshakeSignatureValid, TlsError> {
+    verify_tls13_signature(
+      message,
+      cert,
+      dss,
+      &self.0.signature_verification_algorithms,
+    )
+  }
+
+  fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+    self.0.signature_verification_algorithms.supported_schemes()
+  }
+}
+
+/// Read the certificate `domain` presents with a TLS handshake alone, for
+/// when fetching its page failed.  `domain` may be a bare hostname or an
+/// `https://` URL with a port; plain `http://` URLs have no certificate.
+/// Connects to `resolved_ips` when given, like `fetch_domain`, so the probe
+/// does not cause another DNS lookup, and only to addresses `egress` permits.
+pub async fn probe_certificate(
+  domain: &str,
+  timeout_sec: u64,
+  resolved_ips: &[String],
+  egress: &EgressPolicy,
+) -> Option<CertificateInfo> {
+  let url = if domain.contains("://") {
+    reqwest::Url::parse(domain).ok()?
+  } else {
+    reqwest::Url::parse(&format!("https://{}", domain)).ok()?
+  };
+  if url.scheme() != "https" {
+    return None;
+  }
+  let host = url.host_str()?.to_string();
+  let port = url.port_or_known_default()?;
+
+  let probe = async {
+    let mut addrs: Vec<SocketAddr> = happy_eyeballs_order(domain, resolved_ips)
+      .into_iter()
+      .map(|ip| SocketAddr::new(ip, port))
+      .collect();
+    if addrs.is_empty() {
+      addrs = tokio::net::lookup_host((host.as_str(), port))
+        .await?
+        .collect();
+    }
+    let addrs = egress.filter(&host, addrs).map_err(std::io::Error::other)?;
+    let stream = TcpStream::connect(&addrs[..]).await?;
+    let provider = Arc::new(aws_lc_rs::default_provider());
+    let config = ClientConfig::builder_with_provider(Arc::clone(&provider))
+      .with_safe_default_protocol_versions()
+      .map_err(std::io::Error::other)?
+      .dangerous()
+      .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate(
+        provider,
+      )))
+      .with_no_client_auth();
+    let server_name =
+      ServerName::try_from(host.clone()).map_err(std::io::Error::other)?;
+    let tls = TlsConnector::from(Arc::new(config))
+      .connect(server_name, stream)
+      .await?;
+    let (_, connection) = tls.get_ref();
+    Ok::<_, std::io::Error>(
+      connection
+        .peer_certificates()
+        .and_then(|chain| chain.first())
+        .map(|der| der.to_vec()),
+    )
+  };
+
+  match tokio::time::timeout(Duration::from_secs(timeout_sec), probe).await {
+    Ok(Ok(Some(der))) => {
+      let certificate = CertificateInfo::from_der(&der)?;
+      info!("Read TLS certificate of {}: {}", host, certificate.subject);
+      Some(certificate)
+    }
+    Ok(Ok(None)) => None,
+    Ok(Err(e)) => {
+      warn!("TLS handshake with {} failed: {}", host, e);
+      None
+    }
+    Err(_) => {
+      warn!("TLS handshake with {} timed out", host);
+      None
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use rcgen::{CertificateParams, DnType, KeyPair};
+
+  #[test]
+  fn test_from_der() {
+    let mut params = CertificateParams::new(vec![
+      "*.riotgames.com".to_string(),
+      "riotgames.com".to_string(),
+    ])
+    .unwrap();
+    params
+      .distinguished_name
+      .push(DnType::CommonName, "*.riotgames.com");
+    params
+      .distinguished_name
+      .push(DnType::OrganizationName, "Riot Games, Inc.");
+    let cert = params.self_signed(&KeyPair::generate().unwrap()).unwrap();
+
+    let info = CertificateInfo::from_der(cert.der()).unwrap();
+    assert!(
+      info.subject.contains("CN=*.riotgames.com"),
+      "{}",
+      info.subject
+    );
+    assert!(
+      info.subject.contains("O=Riot Games, Inc."),
+      "{}",
+      info.subject
+    );
+    assert_eq!(info.issuer, info.subject);
+    assert_eq!(
+      info.subject_alt_names,
+      vec!["*.riotgames.com", "riotgames.com"]
+    );
+
+    assert_eq!(CertificateInfo::from_der(b"not a certificate"), None);
+  }
+}
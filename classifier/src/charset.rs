@@ -0,0 +1,130 @@
+//! Decoding of fetched pages.
+//!
+//! The encoding is picked the way browsers pick it: a byte order mark wins,
+//! then the charset of the `Content-Type` header, then a `<meta>` charset
+//! declaration near the top of the page, falling back to UTF-8.  Decoding
+//! everything as UTF-8 would hand Shift_JIS, GBK or Windows-1251 pages to the
+//! LLM as mojibake.
+
+use encoding_rs::{Encoding, UTF_8, UTF_16BE, UTF_16LE};
+
+/// How far into the body `<meta>` charset declarations are looked for, as
+/// in the HTML prescan.
+const META_PRESCAN_BYTES: usize = 1024;
+
+/// Decode `body`, returning the text and the encoding it was decoded with.
+pub fn decode_body(
+  body: &[u8],
+  content_type: Option<&str>,
+) -> (String, &'static Encoding) {
+  let encoding = Encoding::for_bom(body)
+    .map(|(encoding, _)| encoding)
+    .or_else(|| content_type.and_then(charset_from_content_type))
+    .or_else(|| charset_from_meta(body))
+    .unwrap_or(UTF_8);
+  let (text, _) = encoding.decode_with_bom_removal(body);
+  (text.into_owned(), encoding)
+}
+
+/// The encoding named by the `charset` parameter of a `Content-Type` value.
+fn charset_from_content_type(content_type: &str) -> Option<&'static Encoding> {
+  content_type.split(';').skip(1).find_map(|param| {
+    let (name, value) = param.split_once('=')?;
+    if !name.trim().eq_ignore_ascii_case("charset") {
+      return None;
+    }
+    Encoding::for_label(value.trim().trim_matches(['"', '\'']).as_bytes())
+  })
+}
+
+/// The encoding declared by a `<meta charset>` or `<meta http-equiv
+/// content="...; charset=...">` tag at the top of `body`.
+fn charset_from_meta(body: &[u8]) -> Option<&'static Encoding> {
+  let head = &body[..body.len().min(META_PRESCAN_BYTES)];
+  // Only ASCII matters here, so a lossy conversion keeps every label intact.
+  let head = String::from_utf8_lossy(head).to_ascii_lowercase();
+  head.split("<meta").skip(1).find_map(|tag| {
+    let tag = tag.split('>').next()?;
+    let value = tag[tag.find("charset")? + "charset".len()..].trim_start();
+    let label: String = value
+      .strip_prefix('=')?
+      .trim_start()
+      .trim_start_matches(['"', '\''])
+      .chars()
+      .take_while(|c| {
+        !matches!(c, '"' | '\'' | ';' | '/') && !c.is_whitespace()
+      })
+      .collect();
+    // A page that could declare UTF-16 in ASCII is not UTF-16.
+    Encoding::for_label(label.as_bytes()).map(|encoding| {
+      if encoding == UTF_16BE || encoding == UTF_16LE {
+        UTF_8
+      } else {
+        encoding
+      }
+    })
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use encoding_rs::{GBK, SHIFT_JIS, WINDOWS_1251};
+
+  #[test]
+  fn test_content_type_charset() {
+    let (body, _, _) = SHIFT_JIS.encode("ゲームストア");
+    let (text, encoding) =
+      decode_body(&body, Some("text/html; charset=Shift_JIS"));
+    assert_eq!(text, "ゲームストア");
+    assert_eq!(encoding, SHIFT_JIS);
+
+    let (text, encoding) =
+      decode_body(&body, Some("text/html;charset=\"shift_jis\""));
+    assert_eq!(text, "ゲームストア");
+    assert_eq!(encoding, SHIFT_JIS);
+  }
+
+  #[test]
+  fn test_meta_charset() {
+    let (body, _, _) = GBK.encode(
+      r#"<html><head><meta charset="gbk"><title>游戏</title></head></html>"#,
+    );
+    let (text, encoding) = decode_body(&body, Some("text/html"));
+    assert!(text.contains("游戏"), "{}", text);
+    assert_eq!(encoding, GBK);
+
+    let (body, _, _) = WINDOWS_1251.encode(
+      r#"<META HTTP-EQUIV="Content-Type" CONTENT="text/html; charset=windows-1251"><title>Игры</title>"#,
+    );
+    let (text, encoding) = decode_body(&body, None);
+    assert!(text.contains("Игры"), "{}", text);
+    assert_eq!(encoding, WINDOWS_1251);
+
+    let (text, encoding) =
+      decode_body(br#"<meta charset="utf-16"><title>x</title>"#, None);
+    assert!(text.contains("<title>x</title>"));
+    assert_eq!(encoding, UTF_8);
+  }
+
+  #[test]
+  fn test_bom_wins() {
+    let mut body = vec![0xEF, 0xBB, 0xBF];
+    body.extend_from_slice("Café".as_bytes());
+    let (text, encoding) =
+      decode_body(&body, Some("text/html; charset=windows-1251"));
+    assert_eq!(text, "Café");
+    assert_eq!(encoding, UTF_8);
+  }
+
+  #[test]
+  fn test_defaults_to_utf8() {
+    let (text, encoding) = decode_body("Café".as_bytes(), Some("text/html"));
+    assert_eq!(text, "Café");
+    assert_eq!(encoding, UTF_8);
+
+    let (_, encoding) =
+      decode_body(b"<meta charset=\"bogus\">", Some("text/html; charset=x"));
+    assert_eq!(encoding, UTF_8);
+  }
+}
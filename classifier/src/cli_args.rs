@@ -1,4 +1,11 @@
-use clap::Parser;
+use crate::backend::BackendKind;
+use crate::cache::FetchCacheConfig;
+use crate::egress::EgressPolicy;
+use crate::ensemble::EnsembleStrategy;
+use crate::proxy::ProxyConfig;
+use crate::web_classify::ExtractionLimits;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser};
 use dns_smart_block_common::logging::LoggingArgs;
 use std::path::PathBuf;
 
@@ -16,17 +23,66 @@ pub struct CliArgs {
   #[arg(long, env = "DOMAIN")]
   pub domain: String,
 
-  /// Ollama API URL.
-  #[arg(long, env = "OLLAMA_URL", default_value = "http://localhost:11434")]
-  pub ollama_url: String,
+  /// Base URL of the LLM backend.  `--ollama-url` and `OLLAMA_URL` are
+  /// accepted as deprecated aliases.
+  #[arg(
+    long,
+    alias = "ollama-url",
+    env = "LLM_URL",
+    default_value = "http://localhost:11434"
+  )]
+  pub llm_url: String,
+
+  /// API the LLM backend speaks.
+  #[arg(
+    long,
+    env = "LLM_BACKEND",
+    value_enum,
+    default_value_t = BackendKind::OllamaGenerate
+  )]
+  pub llm_backend: BackendKind,
+
+  /// File holding an API key to send as a bearer token.
+  #[arg(long, env = "LLM_API_KEY_FILE")]
+  pub llm_api_key_file: Option<PathBuf>,
+
+  /// LLM request timeout in seconds.
+  #[arg(long, env = "LLM_TIMEOUT_SEC", default_value = "300")]
+  pub llm_timeout_sec: u64,
 
-  /// Ollama model to use.
+  /// Model to use.  Repeat the flag (or pass a comma-separated list)
+  /// to classify with an ensemble of models.  `--ollama-model` and
+  /// `OLLAMA_MODEL` are accepted as deprecated aliases.
   #[arg(
     long,
-    env = "OLLAMA_MODEL",
+    alias = "ollama-model",
+    env = "LLM_MODEL",
+    value_delimiter = ',',
     default_value = "llama3.1:8b-instruct-q4_K_M"
   )]
-  pub ollama_model: String,
+  pub llm_model: Vec<String>,
+
+  /// How to combine the votes when several models are given.
+  #[arg(
+    long,
+    env = "ENSEMBLE_STRATEGY",
+    value_enum,
+    default_value_t = EnsembleStrategy::Majority
+  )]
+  pub ensemble_strategy: EnsembleStrategy,
+
+  /// Larger model to re-run with when the result's confidence falls inside
+  /// the uncertainty band.  Repeat for further tiers, smallest first.
+  #[arg(long, env = "ESCALATION_MODEL", value_delimiter = ',')]
+  pub escalation_model: Vec<String>,
+
+  /// Lower bound (inclusive) of the confidence band that triggers escalation.
+  #[arg(long, env = "UNCERTAINTY_MIN", default_value = "0.0")]
+  pub uncertainty_min: f64,
+
+  /// Upper bound (exclusive) of the confidence band that triggers escalation.
+  #[arg(long, env = "UNCERTAINTY_MAX", default_value = "0.0")]
+  pub uncertainty_max: f64,
 
   /// Path to prompt template file.
   #[arg(long, env = "PROMPT_TEMPLATE", default_value = "prompt-template.txt")]
@@ -40,18 +96,134 @@ pub struct CliArgs {
   #[arg(long, env = "HTTP_TIMEOUT_SEC", default_value = "5")]
   pub http_timeout_sec: u64,
 
-  /// Maximum HTTP response size in KB.
+  /// Maximum HTTP response size in KB.  Reading stops there.
   #[arg(long, env = "HTTP_MAX_KB", default_value = "200")]
   pub http_max_kb: usize,
 
-  /// Pre-resolved IP address for the domain.  When provided, the HTTP fetch
-  /// connects directly to this IP instead of resolving the domain through the
-  /// local DNS stack, avoiding a duplicate log entry in the upstream resolver.
-  /// The Host header and TLS SNI still use the domain name.
-  #[arg(long, env = "RESOLVED_IP")]
-  pub resolved_ip: Option<String>,
+  #[command(flatten)]
+  pub extraction: ExtractionLimits,
+
+  #[command(flatten)]
+  pub egress: EgressPolicy,
+
+  #[command(flatten)]
+  pub cache: FetchCacheConfig,
+
+  /// Proxy to fetch pages through (http://, https:// or socks5h:// URL).
+  /// The proxy resolves the domain, so --resolved-ip is ignored.  LLM
+  /// requests do not use it.
+  #[arg(long, env = "HTTP_PROXY_URL")]
+  pub http_proxy: Option<String>,
+
+  /// File holding the --http-proxy credentials as `user:password`.
+  #[arg(long, env = "HTTP_PROXY_CREDENTIALS_FILE", requires = "http_proxy")]
+  pub http_proxy_credentials_file: Option<PathBuf>,
+
+  /// Pre-resolved IPv4 or IPv6 address for the domain (repeatable, or
+  /// comma-separated).  When provided, the HTTP fetch connects directly to
+  /// these addresses, in happy eyeballs order, instead of resolving the domain
+  /// through the local DNS stack, avoiding a duplicate log entry in the
+  /// upstream resolver.  The Host header and TLS SNI still use the domain
+  /// name.
+  #[arg(long, env = "RESOLVED_IP", value_delimiter = ',')]
+  pub resolved_ip: Vec<String>,
+
+  /// File of answers from earlier runs to reuse, one JSON `llm_results`
+  /// entry per line, or `-` to read them from standard input.  A model whose
+  /// result cache key matches one is not called.
+  #[arg(long)]
+  pub cached_results_file: Option<PathBuf>,
 
   /// Output format (json or human-readable).
   #[arg(long, env = "OUTPUT", default_value = "human")]
   pub output: String,
 }
+
+impl CliArgs {
+  /// Parse the command line like [`Parser::parse`], falling back to the
+  /// deprecated environment variables.
+  pub fn parse_with_deprecated_env() -> Self {
+    Self::from_matches_with_deprecated_env(
+      &Self::command().get_matches(),
+      |name| std::env::var(name).ok(),
+    )
+  }
+
+  /// Build the arguments from `matches`, then apply `OLLAMA_URL` and
+  /// `OLLAMA_MODEL`, as read by `env`, to `--llm-url` and `--llm-model`
+  /// when those were left at their defaults.  Clap reads a single
+  /// environment variable per argument, and `LLM_URL` and `LLM_MODEL` take
+  /// precedence.
+  fn from_matches_with_deprecated_env(
+    matches: &ArgMatches,
+    env: impl Fn(&str) -> Option<String>,
+  ) -> Self {
+    let mut args = Self::from_arg_matches(matches).unwrap_or_else(|e| e.exit());
+    let defaulted =
+      |id: &str| matches.value_source(id) == Some(ValueSource::DefaultValue);
+    if defaulted("llm_url") {
+      if let Some(url) = env("OLLAMA_URL") {
+        args.llm_url = url;
+      }
+    }
+    if defaulted("llm_model") {
+      if let Some(models) = env("OLLAMA_MODEL") {
+        args.llm_model = models.split(',').map(str::to_string).collect();
+      }
+    }
+    args
+  }
+
+  /// The proxy page fetches go through, if any.
+  pub fn proxy_config(&self) -> Option<ProxyConfig> {
+    self.http_proxy.as_ref().map(|url| ProxyConfig {
+      url: url.clone(),
+      credentials_file: self.http_proxy_credentials_file.clone(),
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_deprecated_ollama_aliases() {
+    let args = CliArgs::parse_from([
+      "dns-smart-block-classifier",
+      "--domain",
+      "example.com",
+      "--ollama-url",
+      "http://ollama.lan:11434",
+      "--ollama-model",
+      "llama3,qwen3",
+    ]);
+    assert_eq!(args.llm_url, "http://ollama.lan:11434");
+    assert_eq!(args.llm_model, vec!["llama3", "qwen3"]);
+  }
+
+  #[test]
+  fn test_deprecated_ollama_env() {
+    let parse = |argv: &[&str]| {
+      let matches = CliArgs::command().get_matches_from(
+        ["dns-smart-block-classifier", "--domain", "example.com"]
+          .iter()
+          .chain(argv),
+      );
+      CliArgs::from_matches_with_deprecated_env(&matches, |name| match name {
+        "OLLAMA_URL" => Some("http://ollama.lan:11434".to_string()),
+        "OLLAMA_MODEL" => Some("llama3,qwen3".to_string()),
+        _ => None,
+      })
+    };
+
+    let args = parse(&[]);
+    assert_eq!(args.llm_url, "http://ollama.lan:11434");
+    assert_eq!(args.llm_model, vec!["llama3", "qwen3"]);
+
+    // The current flags (or LLM_URL and LLM_MODEL) win.
+    let args = parse(&["--llm-url", "http://llm.lan:8080", "--llm-model", "m"]);
+    assert_eq!(args.llm_url, "http://llm.lan:8080");
+    assert_eq!(args.llm_model, vec!["m"]);
+  }
+}
@@ -0,0 +1,226 @@
+//! Which addresses the fetcher may connect to.
+//!
+//! Domains come straight from DNS logs, so a domain, a pre-resolved IP or a
+//! redirect can point the fetcher at the local network or a cloud metadata
+//! endpoint.  Private, loopback, link-local, CGNAT, benchmarking, reserved
+//! and multicast addresses are refused unless allowlisted, as are IPv6
+//! addresses embedding one (IPv4-mapped, NAT64 and 6to4).  The policy is
+//! checked for pre-resolved IPs and IP literals up front, for redirect
+//! targets, and for every hostname lookup, so it also holds across redirects
+//! and DNS rebinding.
+
+use ipnet::IpNet;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use thiserror::Error;
+use tracing::warn;
+
+/// Egress policy of the fetcher.
+#[derive(
+  clap::Args, Serialize, Deserialize, Debug, Clone, PartialEq, Default,
+)]
+#[serde(default)]
+pub struct EgressPolicy {
+  /// Network the fetcher may connect to even though it is private, loopback,
+  /// link-local, CGNAT, benchmarking, reserved or multicast, in CIDR
+  /// notation (repeatable, or comma-separated; use /32 or /128 for a single
+  /// address).
+  #[arg(long = "egress-allow", env = "EGRESS_ALLOW", value_delimiter = ',')]
+  pub allow: Vec<IpNet>,
+}
+
+/// A connection refused by the egress policy.
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("address {ip} of {host} is blocked by the egress policy")]
+pub struct EgressBlocked {
+  pub host: String,
+  pub ip: IpAddr,
+}
+
+/// Whether `ip` is in one of the ranges refused by default.
+fn is_internal(ip: IpAddr) -> bool {
+  match ip {
+    IpAddr::V4(v4) => {
+      let [a, b, ..] = v4.octets();
+      a == 0
+        || v4.is_private()
+        || v4.is_loopback()
+        || v4.is_link_local()
+        || v4.is_multicast()
+        // 100.64.0.0/10, carrier-grade NAT
+        || (a == 100 && (b & 0xc0) == 64)
+        // 198.18.0.0/15, benchmarking
+        || (a == 198 && (b & 0xfe) == 18)
+        // 240.0.0.0/4, reserved, including the broadcast address
+        || a >= 240
+    }
+    IpAddr::V6(v6) => match embedded_ipv4(v6) {
+      Some(v4) => is_internal(IpAddr::V4(v4)),
+      None => {
+        v6.is_unspecified()
+          || v6.is_loopback()
+          || v6.is_unique_local()
+          || v6.is_unicast_link_local()
+          || v6.is_multicast()
+      }
+    },
+  }
+}
+
+/// The IPv4 address embedded in an IPv4-mapped (`::ffff:0:0/96`), NAT64
+/// (`64:ff9b::/96`) or 6to4 (`2002::/16`) address, which reaches that IPv4
+/// host.
+fn embedded_ipv4(v6: Ipv6Addr) -> Option<Ipv4Addr> {
+  let octets = v6.octets();
+  match v6.segments() {
+    [0x64, 0xff9b, 0, 0, 0, 0, ..] => Some(Ipv4Addr::new(
+      octets[12], octets[13], octets[14], octets[15],
+    )),
+    [0x2002, ..] => {
+      Some(Ipv4Addr::new(octets[2], octets[3], octets[4], octets[5]))
+    }
+    _ => v6.to_ipv4_mapped(),
+  }
+}
+
+impl EgressPolicy {
+  /// Whether the fetcher may connect to `ip`.
+  pub fn permits(&self, ip: IpAddr) -> bool {
+    !is_internal(ip) || self.allow.iter().any(|net| net.contains(&ip))
+  }
+
+  /// Check that `host` may be connected to at `ip`.
+  pub fn check(&self, host: &str, ip: IpAddr) -> Result<(), EgressBlocked> {
+    if self.permits(ip) {
+      Ok(())
+    } else {
+      Err(EgressBlocked {
+        host: host.to_string(),
+        ip,
+      })
+    }
+  }
+
+  /// Keep the addresses of `host` the fetcher may connect to, failing when
+  /// none are left.
+  pub fn filter(
+    &self,
+    host: &str,
+    addrs: impl IntoIterator<Item = SocketAddr>,
+  ) -> Result<Vec<SocketAddr>, EgressBlocked> {
+    let mut permitted = Vec::new();
+    let mut blocked = None;
+    for addr in addrs {
+      match self.check(host, addr.ip()) {
+        Ok(()) => permitted.push(addr),
+        Err(e) => {
+          warn!("Not connecting to {}: {}", addr.ip(), e);
+          blocked.get_or_insert(e);
+        }
+      }
+    }
+    match (permitted.is_empty(), blocked) {
+      (true, Some(blocked)) => Err(blocked),
+      _ => Ok(permitted),
+    }
+  }
+}
+
+/// The egress block behind a failed request, if that is why it failed.
+pub fn blocked_by(error: &reqwest::Error) -> Option<EgressBlocked> {
+  let mut source: Option<&(dyn std::error::Error + 'static)> = Some(error);
+  while let Some(e) = source {
+    if let Some(blocked) = e.downcast_ref::<EgressBlocked>() {
+      return Some(blocked.clone());
+    }
+    source = e.source();
+  }
+  None
+}
+
+/// Resolves hostnames through the system resolver and drops the addresses
+/// the policy refuses.
+pub struct EgressResolver(pub EgressPolicy);
+
+impl Resolve for EgressResolver {
+  fn resolve(&self, name: Name) -> Resolving {
+    let policy = self.0.clone();
+    Box::pin(async move {
+      let host = name.as_str();
+      let addrs = tokio::net::lookup_host((host, 0)).await?;
+      let addrs: Addrs = Box::new(policy.filter(host, addrs)?.into_iter());
+      Ok(addrs)
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_default_policy() {
+    let policy = EgressPolicy::default();
+    for blocked in [
+      "0.0.0.0",
+      "10.1.2.3",
+      "100.64.0.1",
+      "127.0.0.1",
+      "169.254.169.254",
+      "172.16.0.1",
+      "192.168.1.1",
+      "198.18.0.1",
+      "198.19.255.254",
+      "224.0.0.251",
+      "240.0.0.1",
+      "255.255.255.255",
+      "::",
+      "::1",
+      "::ffff:192.168.1.1",
+      "64:ff9b::a9fe:a9fe",
+      "64:ff9b::127.0.0.1",
+      "2002:a00:1::1",
+      "2002:c0a8:101::",
+      "fd00::1",
+      "fe80::1",
+      "ff02::1",
+    ] {
+      assert!(!policy.permits(blocked.parse().unwrap()), "{}", blocked);
+    }
+    for permitted in [
+      "1.1.1.1",
+      "100.128.0.1",
+      "198.20.0.1",
+      "223.255.255.255",
+      "2606:4700::1111",
+      "64:ff9b::1.1.1.1",
+      "2002:101:101::1",
+    ] {
+      assert!(policy.permits(permitted.parse().unwrap()), "{}", permitted);
+    }
+  }
+
+  #[test]
+  fn test_allowlist() {
+    let policy = EgressPolicy {
+      allow: vec!["192.168.10.0/24".parse().unwrap()],
+    };
+    assert!(policy.permits("192.168.10.5".parse().unwrap()));
+    assert!(!policy.permits("192.168.11.5".parse().unwrap()));
+
+    let addrs = ["192.168.11.5:0", "192.168.10.5:0", "1.1.1.1:0"]
+      .map(|addr| addr.parse::<SocketAddr>().unwrap());
+    assert_eq!(
+      policy.filter("example.com", addrs).unwrap(),
+      vec![addrs[1], addrs[2]]
+    );
+    assert_eq!(
+      policy.filter("example.com", [addrs[0]]),
+      Err(EgressBlocked {
+        host: "example.com".to_string(),
+        ip: addrs[0].ip(),
+      })
+    );
+  }
+}
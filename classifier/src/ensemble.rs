@@ -0,0 +1,180 @@
+//! Combining the votes of several models into one classification.
+//!
+//! Small quantised models flip on edge cases, so a classifier can run the
+//! same site metadata through several models and combine their answers.
+//! Each strategy scores the outcome by how strongly the models back it, so a
+//! split vote lands below `min_confidence` and goes to the review queue
+//! rather than straight into the blocklist.
+
+use crate::output::Classification;
+use serde::{Deserialize, Serialize};
+
+/// How the votes of an ensemble are combined.
+#[derive(
+  Debug,
+  Clone,
+  Copy,
+  Default,
+  PartialEq,
+  Eq,
+  Serialize,
+  Deserialize,
+  clap::ValueEnum,
+)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum EnsembleStrategy {
+  /// Matching when more than half of the models say so.  The confidence is
+  /// the summed confidence of the models agreeing with the outcome divided
+  /// by the number of models.
+  #[default]
+  Majority,
+  /// Matching only when every model says so; any dissent lets the domain
+  /// through.  Confidence as for `majority`.
+  Unanimous,
+  /// Average each model's probability that the site matches (its
+  /// confidence, or one minus it for a "not matching" vote) and match when
+  /// the mean exceeds one half.  The confidence is the mean probability of
+  /// the outcome.
+  ConfidenceWeighted,
+}
+
+impl EnsembleStrategy {
+  pub fn as_str(self) -> &'static str {
+    match self {
+      EnsembleStrategy::Majority => "majority",
+      EnsembleStrategy::Unanimous => "unanimous",
+      EnsembleStrategy::ConfidenceWeighted => "confidence_weighted",
+    }
+  }
+}
+
+/// One model's answer within an ensemble.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ModelVote {
+  pub model: String,
+  pub is_matching_site: bool,
+  pub confidence: f64,
+  pub reasoning: String,
+}
+
+/// Combine `votes` into a single classification.  `votes` must not be empty.
+pub fn combine(
+  strategy: EnsembleStrategy,
+  votes: &[ModelVote],
+) -> Classification {
+  let total = votes.len() as f64;
+  let matching = votes.iter().filter(|v| v.is_matching_site).count();
+
+  let (is_matching_site, confidence) = match strategy {
+    EnsembleStrategy::Majority | EnsembleStrategy::Unanimous => {
+      let is_matching_site = if strategy == EnsembleStrategy::Majority {
+        matching * 2 > votes.len()
+      } else {
+        matching == votes.len()
+      };
+      let agreeing: f64 = votes
+        .iter()
+        .filter(|v| v.is_matching_site == is_matching_site)
+        .map(|v| v.confidence)
+        .sum();
+      (is_matching_site, agreeing / total)
+    }
+    EnsembleStrategy::ConfidenceWeighted => {
+      let p_matching = votes
+        .iter()
+        .map(|v| {
+          if v.is_matching_site {
+            v.confidence
+          } else {
+            1.0 - v.confidence
+          }
+        })
+        .sum::<f64>()
+        / total;
+      if p_matching > 0.5 {
+        (true, p_matching)
+      } else {
+        (false, 1.0 - p_matching)
+      }
+    }
+  };
+
+  Classification {
+    is_matching_site,
+    confidence,
+    reasoning: format!(
+      "{} of {} models voted matching ({})",
+      matching,
+      votes.len(),
+      strategy.as_str()
+    ),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn vote(is_matching_site: bool, confidence: f64) -> ModelVote {
+    ModelVote {
+      model: "m".to_string(),
+      is_matching_site,
+      confidence,
+      reasoning: String::new(),
+    }
+  }
+
+  #[test]
+  fn test_majority_scales_confidence_by_agreement() {
+    let votes = [vote(true, 0.9), vote(true, 0.9), vote(false, 0.6)];
+    let result = combine(EnsembleStrategy::Majority, &votes);
+    assert!(result.is_matching_site);
+    assert!((result.confidence - 0.6).abs() < 1e-9);
+    assert_eq!(result.reasoning, "2 of 3 models voted matching (majority)");
+  }
+
+  #[test]
+  fn test_majority_tie_does_not_match() {
+    let votes = [vote(true, 0.9), vote(false, 0.8)];
+    let result = combine(EnsembleStrategy::Majority, &votes);
+    assert!(!result.is_matching_site);
+    assert!((result.confidence - 0.4).abs() < 1e-9);
+  }
+
+  #[test]
+  fn test_unanimous_requires_every_model_to_block() {
+    let votes = [vote(true, 0.9), vote(true, 0.7), vote(false, 0.6)];
+    let result = combine(EnsembleStrategy::Unanimous, &votes);
+    assert!(!result.is_matching_site);
+    assert!((result.confidence - 0.2).abs() < 1e-9);
+
+    let votes = [vote(true, 0.9), vote(true, 0.7)];
+    let result = combine(EnsembleStrategy::Unanimous, &votes);
+    assert!(result.is_matching_site);
+    assert!((result.confidence - 0.8).abs() < 1e-9);
+  }
+
+  #[test]
+  fn test_confidence_weighted_mean() {
+    // A confident "matching" outweighs a hesitant "not matching":
+    // (0.9 + (1 - 0.6)) / 2 = 0.65.
+    let votes = [vote(true, 0.9), vote(false, 0.6)];
+    let result = combine(EnsembleStrategy::ConfidenceWeighted, &votes);
+    assert!(result.is_matching_site);
+    assert!((result.confidence - 0.65).abs() < 1e-9);
+
+    let votes = [vote(true, 0.6), vote(false, 0.9)];
+    let result = combine(EnsembleStrategy::ConfidenceWeighted, &votes);
+    assert!(!result.is_matching_site);
+    assert!((result.confidence - 0.65).abs() < 1e-9);
+  }
+
+  #[test]
+  fn test_strategy_names() {
+    let strategy: EnsembleStrategy =
+      serde_json::from_str(r#""confidence_weighted""#).unwrap();
+    assert_eq!(strategy, EnsembleStrategy::ConfidenceWeighted);
+    assert_eq!(strategy.as_str(), "confidence_weighted");
+  }
+}
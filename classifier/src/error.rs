@@ -1,3 +1,4 @@
+use crate::egress::EgressBlocked;
 use serde::{Deserialize, Serialize};
 use std::fmt;
 use strum::EnumString;
@@ -12,12 +13,45 @@ pub enum ClassifierErrorType {
   DomainFetchError,
   DomainFetchTimeoutError,
   HtmlParseError,
-  OllamaApiConnectionError,
-  OllamaApiTimeoutError,
-  OllamaApiError,
-  OllamaResponseParseError,
+  // The LLM variants were named after Ollama before other backends existed;
+  // the old names are still accepted from older classifiers and events.
+  #[serde(alias = "OllamaApiConnectionError")]
+  #[strum(
+    serialize = "LlmApiConnectionError",
+    serialize = "OllamaApiConnectionError"
+  )]
+  LlmApiConnectionError,
+  #[serde(alias = "OllamaApiTimeoutError")]
+  #[strum(
+    serialize = "LlmApiTimeoutError",
+    serialize = "OllamaApiTimeoutError"
+  )]
+  LlmApiTimeoutError,
+  #[serde(alias = "OllamaApiUnavailableError")]
+  #[strum(
+    serialize = "LlmApiUnavailableError",
+    serialize = "OllamaApiUnavailableError"
+  )]
+  LlmApiUnavailableError,
+  #[serde(alias = "OllamaApiError")]
+  #[strum(serialize = "LlmApiError", serialize = "OllamaApiError")]
+  LlmApiError,
+  #[serde(alias = "OllamaResponseParseError")]
+  #[strum(
+    serialize = "LlmResponseParseError",
+    serialize = "OllamaResponseParseError"
+  )]
+  LlmResponseParseError,
   ClassificationParseError,
+  ClassificationSchemaError,
+  ConfidenceOutOfRange,
+  EmptyReasoning,
+  ModelRefusal,
   MetadataSerializationError,
+  ApiKeyFileReadError,
+  EgressBlocked,
+  ProxyConfigError,
+  CachedResultsReadError,
 }
 
 impl fmt::Display for ClassifierErrorType {
@@ -31,20 +65,66 @@ impl fmt::Display for ClassifierErrorType {
         write!(f, "DomainFetchTimeoutError")
       }
       Self::HtmlParseError => write!(f, "HtmlParseError"),
-      Self::OllamaApiConnectionError => {
-        write!(f, "OllamaApiConnectionError")
+      Self::LlmApiConnectionError => {
+        write!(f, "LlmApiConnectionError")
       }
-      Self::OllamaApiTimeoutError => write!(f, "OllamaApiTimeoutError"),
-      Self::OllamaApiError => write!(f, "OllamaApiError"),
-      Self::OllamaResponseParseError => {
-        write!(f, "OllamaResponseParseError")
+      Self::LlmApiTimeoutError => write!(f, "LlmApiTimeoutError"),
+      Self::LlmApiUnavailableError => {
+        write!(f, "LlmApiUnavailableError")
+      }
+      Self::LlmApiError => write!(f, "LlmApiError"),
+      Self::LlmResponseParseError => {
+        write!(f, "LlmResponseParseError")
       }
       Self::ClassificationParseError => {
         write!(f, "ClassificationParseError")
       }
+      Self::ClassificationSchemaError => {
+        write!(f, "ClassificationSchemaError")
+      }
+      Self::ConfidenceOutOfRange => write!(f, "ConfidenceOutOfRange"),
+      Self::EmptyReasoning => write!(f, "EmptyReasoning"),
+      Self::ModelRefusal => write!(f, "ModelRefusal"),
       Self::MetadataSerializationError => {
         write!(f, "MetadataSerializationError")
       }
+      Self::ApiKeyFileReadError => write!(f, "ApiKeyFileReadError"),
+      Self::EgressBlocked => write!(f, "EgressBlocked"),
+      Self::ProxyConfigError => write!(f, "ProxyConfigError"),
+      Self::CachedResultsReadError => write!(f, "CachedResultsReadError"),
+    }
+  }
+}
+
+/// Why a model's answer was rejected
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum ClassificationIssue {
+  #[error("answer is not valid JSON: {0}")]
+  NotJson(String),
+
+  #[error("answer does not match the schema: {0}")]
+  SchemaMismatch(String),
+
+  #[error("confidence {0} is outside 0.0-1.0")]
+  ConfidenceOutOfRange(f64),
+
+  #[error("reasoning is empty")]
+  EmptyReasoning,
+
+  #[error("model refused to answer: {0}")]
+  Refused(String),
+}
+
+impl ClassificationIssue {
+  pub fn to_error_type(&self) -> ClassifierErrorType {
+    match self {
+      Self::NotJson(_) => ClassifierErrorType::ClassificationParseError,
+      Self::SchemaMismatch(_) => ClassifierErrorType::ClassificationSchemaError,
+      Self::ConfidenceOutOfRange(_) => {
+        ClassifierErrorType::ConfidenceOutOfRange
+      }
+      Self::EmptyReasoning => ClassifierErrorType::EmptyReasoning,
+      Self::Refused(_) => ClassifierErrorType::ModelRefusal,
     }
   }
 }
@@ -63,6 +143,36 @@ pub enum ClassifierError {
 
   #[error("Domain fetch retries exhausted with no attempts made")]
   DomainFetchRetriesExhausted,
+
+  #[error("API key file read error: {0}")]
+  ApiKeyFileReadError(std::io::Error),
+
+  #[error("LLM response contained no choices")]
+  EmptyLlmResponse,
+
+  #[error("LLM response could not be decoded: {0}")]
+  LlmResponseDecode(reqwest::Error),
+
+  #[error("LLM API timeout: {0}")]
+  LlmApiTimeout(reqwest::Error),
+
+  #[error("LLM API temporarily unavailable: {0}")]
+  LlmApiUnavailable(reqwest::StatusCode),
+
+  #[error("Invalid classification: {0}")]
+  InvalidClassification(ClassificationIssue),
+
+  #[error("Fetch blocked: {0}")]
+  EgressBlocked(#[from] EgressBlocked),
+
+  #[error("Proxy credentials file read error: {0}")]
+  ProxyCredentialsFileReadError(std::io::Error),
+
+  #[error("Invalid proxy: {0}")]
+  InvalidProxy(String),
+
+  #[error("Cached results read error: {0}")]
+  CachedResultsReadError(String),
 }
 
 impl ClassifierError {
@@ -76,9 +186,9 @@ impl ClassifierError {
         if e.is_timeout() {
           ClassifierErrorType::DomainFetchTimeoutError
         } else if e.is_connect() {
-          ClassifierErrorType::OllamaApiConnectionError
+          ClassifierErrorType::LlmApiConnectionError
         } else if e.is_status() {
-          ClassifierErrorType::OllamaApiError
+          ClassifierErrorType::LlmApiError
         } else {
           ClassifierErrorType::DomainFetchError
         }
@@ -89,6 +199,28 @@ impl ClassifierError {
       ClassifierError::DomainFetchRetriesExhausted => {
         ClassifierErrorType::DomainFetchError
       }
+      ClassifierError::ApiKeyFileReadError(_) => {
+        ClassifierErrorType::ApiKeyFileReadError
+      }
+      ClassifierError::EmptyLlmResponse
+      | ClassifierError::LlmResponseDecode(_) => {
+        ClassifierErrorType::LlmResponseParseError
+      }
+      ClassifierError::LlmApiTimeout(_) => {
+        ClassifierErrorType::LlmApiTimeoutError
+      }
+      ClassifierError::LlmApiUnavailable(_) => {
+        ClassifierErrorType::LlmApiUnavailableError
+      }
+      ClassifierError::InvalidClassification(issue) => issue.to_error_type(),
+      ClassifierError::EgressBlocked(_) => ClassifierErrorType::EgressBlocked,
+      ClassifierError::ProxyCredentialsFileReadError(_)
+      | ClassifierError::InvalidProxy(_) => {
+        ClassifierErrorType::ProxyConfigError
+      }
+      ClassifierError::CachedResultsReadError(_) => {
+        ClassifierErrorType::CachedResultsReadError
+      }
     }
   }
 }
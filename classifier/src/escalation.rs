@@ -0,0 +1,55 @@
+//! Tiered model escalation.
+//!
+//! Running a large model on every domain is too slow on CPU-only hosts, while
+//! a small model is unreliable near the confidence threshold.  A classifier
+//! can therefore list escalation models: the cheap tier runs first, and each
+//! following tier only runs while the current result's confidence falls
+//! inside the uncertainty band.
+
+use serde::{Deserialize, Serialize};
+
+/// Confidence range `[min, max)` within which a result is too uncertain to
+/// stand and is escalated to the next tier.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct UncertaintyBand {
+  pub min: f64,
+  pub max: f64,
+}
+
+impl UncertaintyBand {
+  /// The band `margin` either side of `threshold`, clamped to `[0, 1]`.
+  pub fn around(threshold: f64, margin: f64) -> Self {
+    Self {
+      min: (threshold - margin).max(0.0),
+      max: (threshold + margin).min(1.0),
+    }
+  }
+
+  pub fn contains(&self, confidence: f64) -> bool {
+    confidence >= self.min && confidence < self.max
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_band_around_threshold() {
+    let band = UncertaintyBand::around(0.8, 0.1);
+    assert!((band.min - 0.7).abs() < 1e-9);
+    assert!((band.max - 0.9).abs() < 1e-9);
+    assert!(!band.contains(0.65));
+    assert!(band.contains(0.75));
+    assert!(band.contains(0.85));
+    assert!(!band.contains(0.95));
+  }
+
+  #[test]
+  fn test_band_is_clamped() {
+    let band = UncertaintyBand::around(0.95, 0.1);
+    assert_eq!(band.max, 1.0);
+    // A fully confident result is never escalated.
+    assert!(!band.contains(1.0));
+  }
+}
@@ -1,21 +1,36 @@
+pub mod backend;
+pub mod cache;
+pub mod certificate;
+pub mod charset;
 pub mod cli_args;
+pub mod egress;
+pub mod ensemble;
 pub mod error;
+pub mod escalation;
 pub mod output;
+pub mod proxy;
+pub mod schema;
 pub mod web_classify;
 
 use crate::{
-  error::ClassifierError, output::Classification, web_classify::SiteMetadata,
+  backend::{BackendKind, ChatMessage, LlmBackend},
+  error::{ClassificationIssue, ClassifierError},
+  output::{Classification, LlmResult},
+  schema::{classification_schema, parse_classification},
+  web_classify::SiteMetadata,
 };
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use tracing::{error, info};
+use std::path::Path;
+use tracing::{error, info, warn};
 
 /// Request payload sent to the Ollama `/api/generate` endpoint.
 #[derive(Serialize, Debug, Clone)]
 pub struct OllamaRequest {
   pub model: String,
   pub prompt: String,
-  pub format: String,
+  /// JSON Schema the answer must match
+  pub format: serde_json::Value,
   pub stream: bool,
 }
 
@@ -32,11 +47,95 @@ pub fn compute_prompt_hash(content: &str) -> String {
   format!("sha256:{}", hex::encode(hasher.finalize()))
 }
 
-/// Send site metadata to an Ollama model and parse the structured
-/// classification response.
+/// Compute the result cache key of a model's answer: a hash of everything
+/// the answer depends on, namely the evidence, the prompt, the model and the
+/// generation options (backend API and answer schema).
+pub fn compute_result_key(
+  metadata: &SiteMetadata,
+  prompt_hash: &str,
+  backend: BackendKind,
+  model: &str,
+) -> Result<String, ClassifierError> {
+  let mut hasher = Sha256::new();
+  for part in [
+    serde_json::to_string(metadata)?,
+    prompt_hash.to_string(),
+    model.to_string(),
+    backend.as_str().to_string(),
+    classification_schema().to_string(),
+  ] {
+    hasher.update(part.as_bytes());
+    // Separate the parts, so that moving text between them changes the key.
+    hasher.update([0]);
+  }
+  Ok(format!("sha256:{}", hex::encode(hasher.finalize())))
+}
+
+/// Classify like [`classify_with_llm`], unless `cached` holds an answer with
+/// the same result cache key, which is then returned without calling the
+/// LLM.
+pub async fn classify_with_result_cache(
+  metadata: &SiteMetadata,
+  backend: &LlmBackend,
+  model: &str,
+  prompt_template: &str,
+  cached: &[LlmResult],
+) -> Result<LlmResult, ClassifierError> {
+  let key = compute_result_key(
+    metadata,
+    &compute_prompt_hash(prompt_template),
+    backend.kind,
+    model,
+  )?;
+  if let Some(hit) = cached.iter().find(|result| result.key == key) {
+    info!("Reusing cached answer of {} for identical evidence", model);
+    return Ok(LlmResult {
+      model: model.to_string(),
+      reused: true,
+      ..hit.clone()
+    });
+  }
+  let classification =
+    classify_with_llm(metadata, backend, model, prompt_template).await?;
+  Ok(LlmResult {
+    key,
+    model: model.to_string(),
+    classification,
+    reused: false,
+  })
+}
+
+/// Read the answers of earlier runs to reuse from `path`, one JSON
+/// `llm_results` entry per line, or from standard input if `path` is `-`.
+pub fn read_cached_results(
+  path: &Path,
+) -> Result<Vec<LlmResult>, ClassifierError> {
+  let error = |e: &dyn std::fmt::Display| {
+    ClassifierError::CachedResultsReadError(format!(
+      "{}: {}",
+      path.display(),
+      e
+    ))
+  };
+  let text = if path == Path::new("-") {
+    std::io::read_to_string(std::io::stdin())
+  } else {
+    std::fs::read_to_string(path)
+  }
+  .map_err(|e| error(&e))?;
+  text
+    .lines()
+    .filter(|line| !line.trim().is_empty())
+    .map(|line| serde_json::from_str(line).map_err(|e| error(&e)))
+    .collect()
+}
+
+/// Send site metadata to a model on `backend` and parse the structured
+/// classification response.  An answer that fails validation, or a refusal to
+/// answer, is sent back once with the validation error before giving up.
 pub async fn classify_with_llm(
   metadata: &SiteMetadata,
-  ollama_url: &str,
+  backend: &LlmBackend,
   model: &str,
   prompt_template: &str,
 ) -> Result<Classification, ClassifierError> {
@@ -44,48 +143,36 @@ pub async fn classify_with_llm(
 
   let metadata_json = serde_json::to_string(metadata)?;
   let prompt = prompt_template.replace("{{INPUT_JSON}}", &metadata_json);
+  let schema = classification_schema();
+  let mut messages = vec![ChatMessage::user(prompt)];
 
-  let ollama_request = OllamaRequest {
-    model: model.to_string(),
-    prompt,
-    format: "json".to_string(),
-    stream: false,
-  };
-
-  let client = reqwest::Client::new();
-  let response = client
-    .post(format!("{}/api/generate", ollama_url))
-    .json(&ollama_request)
-    .send()
-    .await
-    .map_err(|e| {
-      if e.is_timeout() {
-        error!("Ollama API timeout");
-      } else if e.is_connect() {
-        error!("Failed to connect to Ollama API");
+  let (content, issue) = match backend.complete(model, &messages, &schema).await
+  {
+    Ok(content) => {
+      info!("LLM response: {}", content);
+      match parse_classification(&content) {
+        Ok(classification) => return Ok(classification),
+        Err(issue) => (content, issue),
       }
-      e
-    })?;
-
-  if !response.status().is_success() {
-    error!("Ollama API returned error: {}", response.status());
-    return Err(ClassifierError::HttpError(
-      response.error_for_status().unwrap_err(),
-    ));
-  }
-
-  let ollama_response: OllamaResponse = response.json().await.map_err(|e| {
-    error!("Failed to parse Ollama response");
-    e
-  })?;
-
-  info!("LLM response: {}", ollama_response.response);
+    }
+    Err(ClassifierError::InvalidClassification(
+      ClassificationIssue::Refused(refusal),
+    )) => (refusal.clone(), ClassificationIssue::Refused(refusal)),
+    Err(e) => return Err(e),
+  };
+  warn!("Rejected LLM answer ({}), asking again", issue);
 
-  let classification: Classification =
-    serde_json::from_str(&ollama_response.response).map_err(|e| {
-      error!("Failed to parse classification from LLM output");
-      ClassifierError::JsonError(e)
-    })?;
+  messages.push(ChatMessage::assistant(content));
+  messages.push(ChatMessage::user(format!(
+    "Your answer was rejected: {}.  Respond again with only a JSON object \
+     matching this schema: {}",
+    issue, schema
+  )));
+  let content = backend.complete(model, &messages, &schema).await?;
+  info!("LLM response: {}", content);
 
-  Ok(classification)
+  parse_classification(&content).map_err(|issue| {
+    error!("Failed to parse classification from LLM output: {}", issue);
+    ClassifierError::InvalidClassification(issue)
+  })
 }
@@ -1,20 +1,29 @@
-use clap::Parser;
 use dns_smart_block_classifier::{
-  classify_with_llm,
+  backend::{LlmBackend, read_api_key},
+  cache::{CachedPage, FetchCache},
+  certificate::probe_certificate,
+  classify_with_result_cache,
   cli_args::CliArgs,
   compute_prompt_hash,
+  ensemble::{ModelVote, combine},
   error::ClassifierError,
+  escalation::UncertaintyBand,
   output::{
-    ClassificationMetadata, ClassificationOutput, ErrorInfo, ErrorOutput,
+    Classification, ClassificationMetadata, ClassificationOutput,
+    EnsembleVotes, ErrorInfo, ErrorOutput, EscalationTiers, LlmResult,
     PartialMetadata,
   },
-  web_classify::{extract_metadata, fetch_domain},
+  read_cached_results,
+  web_classify::{
+    FetchedPage, SiteMetadata, extract_metadata_with_limits, fetch_domain,
+  },
 };
+use std::time::Duration;
 use tracing::{error, info};
 
 #[tokio::main]
 async fn main() {
-  let args = CliArgs::parse();
+  let args = CliArgs::parse_with_deprecated_env();
 
   // Initialize logging with auto-detection and CLI overrides
   args.logging.init_tracing();
@@ -25,8 +34,23 @@ async fn main() {
   info!("Configuration:");
   info!("  Domain: {}", args.domain);
   info!("  Classification Type: {}", args.classification_type);
-  info!("  Ollama URL: {}", args.ollama_url);
-  info!("  Ollama Model: {}", args.ollama_model);
+  info!(
+    "  LLM Backend: {} at {}",
+    args.llm_backend.as_str(),
+    args.llm_url
+  );
+  info!("  Model(s): {}", args.llm_model.join(", "));
+  if args.llm_model.len() > 1 {
+    info!("  Ensemble Strategy: {}", args.ensemble_strategy.as_str());
+  }
+  if !args.escalation_model.is_empty() {
+    info!(
+      "  Escalation Model(s): {} (band [{}, {}))",
+      args.escalation_model.join(", "),
+      args.uncertainty_min,
+      args.uncertainty_max
+    );
+  }
   info!("  Prompt Template: {:?}", args.prompt_template);
   info!("  HTTP Timeout: {}s", args.http_timeout_sec);
   info!("  HTTP Max KB: {}", args.http_max_kb);
@@ -109,83 +133,265 @@ async fn run_classification(
   info!("  Prompt template loaded (hash: {})", prompt_hash);
   info!("  Prompt length: {} characters", prompt_template.len());
 
+  let api_key = match &args.llm_api_key_file {
+    Some(path) => Some(read_api_key(path).map_err(|e| {
+      error!("Failed to read API key from {:?}: {}", path, e);
+      ErrorOutput {
+        domain: args.domain.clone(),
+        result: "error".to_string(),
+        error: ErrorInfo {
+          error_type: e.to_error_type(),
+          message: e.to_string(),
+        },
+        metadata: None,
+      }
+    })?),
+    None => None,
+  };
+  let cached_results = match &args.cached_results_file {
+    Some(path) => read_cached_results(path).map_err(|e| {
+      error!("Failed to read cached results from {:?}: {}", path, e);
+      ErrorOutput {
+        domain: args.domain.clone(),
+        result: "error".to_string(),
+        error: ErrorInfo {
+          error_type: e.to_error_type(),
+          message: e.to_string(),
+        },
+        metadata: None,
+      }
+    })?,
+    None => Vec::new(),
+  };
+  let backend = LlmBackend::new(args.llm_backend, &args.llm_url, api_key)
+    .with_timeout(Duration::from_secs(args.llm_timeout_sec));
+
+  let proxy = match args.proxy_config() {
+    Some(config) => Some(config.proxy().map_err(|e| {
+      error!("Failed to set up proxy {}: {}", config.url, e);
+      ErrorOutput {
+        domain: args.domain.clone(),
+        result: "error".to_string(),
+        error: ErrorInfo {
+          error_type: e.to_error_type(),
+          message: e.to_string(),
+        },
+        metadata: None,
+      }
+    })?),
+    None => None,
+  };
+
   // Fetch domain content (best-effort - continue even if it fails)
   info!("Step 2/3: Fetching domain content from {}...", args.domain);
   let fetch_start = Instant::now();
-  let metadata = match fetch_domain(
-    &args.domain,
-    args.http_timeout_sec,
-    args.http_max_kb,
-    args.resolved_ip.as_deref(),
-  )
-  .await
-  {
-    Ok((html, status)) => {
-      info!(
-        "  HTTP fetch succeeded: status={}, size={} bytes, elapsed={:.2}s",
-        status,
-        html.len(),
-        fetch_start.elapsed().as_secs_f64()
-      );
-      // Successfully fetched - extract metadata from HTML
-      extract_metadata(&args.domain, &html, status).unwrap_or_else(|e| {
-        error!("Failed to extract metadata from HTML: {}", e);
-        // Fall back to minimal metadata with fetch error
-        use dns_smart_block_classifier::web_classify::SiteMetadata;
-        SiteMetadata::from_fetch_error(
+  // Scheme and address the page was fetched from, when it was.
+  let mut connection = None;
+  let cache = args.cache.open(args.http_proxy.as_deref());
+  let cached = cache.as_ref().and_then(|cache| {
+    cache.get(&args.domain, &args.extraction, args.http_max_kb)
+  });
+  let metadata = match cached {
+    Some(page) => {
+      connection = Some((page.scheme, page.remote_addr));
+      page.metadata
+    }
+    None => match fetch_domain(
+      &args.domain,
+      args.http_timeout_sec,
+      args.http_max_kb,
+      &args.resolved_ip,
+      &args.egress,
+      proxy.as_ref(),
+    )
+    .await
+    {
+      Ok(page) => {
+        info!(
+          "  HTTP fetch succeeded: status={}, size={} bytes, elapsed={:.2}s",
+          page.status,
+          page.body.len(),
+          fetch_start.elapsed().as_secs_f64()
+        );
+        connection = Some((page.scheme.clone(), page.remote_addr));
+        // Successfully fetched - extract metadata from HTML
+        extract_metadata_with_limits(
           &args.domain,
-          &format!("Metadata extraction failed: {}", e),
+          &page.body,
+          page.status,
+          &args.extraction,
         )
-      })
-    }
-    Err(e) => {
-      error!(
-        "  HTTP fetch failed after {:.2}s: {}",
-        fetch_start.elapsed().as_secs_f64(),
-        e
-      );
-      // HTTP fetch failed - create minimal metadata with just domain name
-      use dns_smart_block_classifier::web_classify::SiteMetadata;
-      SiteMetadata::from_fetch_error(&args.domain, &e.to_string())
-    }
+        .map(|metadata| {
+          let metadata = SiteMetadata {
+            final_host: page.final_host(),
+            redirect_chain: page.redirects.clone(),
+            tls_certificate: page.certificate.clone(),
+            encoding: Some(page.encoding.clone()),
+            ..metadata
+          };
+          cache_page(cache.as_ref(), args, &args.domain, &page, &metadata);
+          metadata
+        })
+        .unwrap_or_else(|e| {
+          error!("Failed to extract metadata from HTML: {}", e);
+          // Fall back to minimal metadata with fetch error
+          SiteMetadata {
+            tls_certificate: page.certificate.clone(),
+            ..SiteMetadata::from_fetch_error(
+              &args.domain,
+              &format!("Metadata extraction failed: {}", e),
+            )
+          }
+        })
+      }
+      // Nothing was fetched, and no other evidence may be gathered either.
+      Err(e @ ClassifierError::EgressBlocked(_)) => {
+        error!("  HTTP fetch refused: {}", e);
+        return Err(ErrorOutput {
+          domain: args.domain.clone(),
+          result: "error".to_string(),
+          error: ErrorInfo {
+            error_type: e.to_error_type(),
+            message: e.to_string(),
+          },
+          metadata: None,
+        });
+      }
+      Err(e) => {
+        error!(
+          "  HTTP fetch failed after {:.2}s: {}",
+          fetch_start.elapsed().as_secs_f64(),
+          e
+        );
+        // HTTP fetch failed - create minimal metadata with just domain name,
+        // plus the certificate if the host still completes a TLS handshake.
+        // The handshake connects directly, so it is skipped in proxy mode.
+        let tls_certificate = match proxy {
+          Some(_) => None,
+          None => {
+            probe_certificate(
+              &args.domain,
+              args.http_timeout_sec,
+              &args.resolved_ip,
+              &args.egress,
+            )
+            .await
+          }
+        };
+        SiteMetadata {
+          tls_certificate,
+          ..SiteMetadata::from_fetch_error(&args.domain, &e.to_string())
+        }
+      }
+    },
   };
 
+  let metadata =
+    with_parent_site(args, proxy.as_ref(), cache.as_ref(), metadata).await;
+
   info!("  Extracted metadata:");
   info!("    Title: {:?}", metadata.title);
   info!("    Language: {:?}", metadata.language);
   info!("    HTTP Status: {}", metadata.http_status);
 
-  // Classify with LLM
+  // Classify with LLM, once per model on the same metadata.
   info!("Step 3/3: Classifying with LLM...");
-  info!("  Calling Ollama API at {}", args.ollama_url);
-  info!("  Using model: {}", args.ollama_model);
+  info!("  Calling {} API at {}", backend.kind.as_str(), backend.url);
+  let mut model_label = args.llm_model.join(",");
   let llm_start = Instant::now();
-  let classification = classify_with_llm(
-    &metadata,
-    &args.ollama_url,
-    &args.ollama_model,
-    &prompt_template,
-  )
-  .await
-  .map_err(|e| {
-    error!(
-      "  LLM classification failed after {:.2}s: {}",
-      llm_start.elapsed().as_secs_f64(),
-      e
+  let mut votes = Vec::with_capacity(args.llm_model.len());
+  let mut llm_results = Vec::new();
+  for model in &args.llm_model {
+    info!("  Using model: {}", model);
+    votes.push(
+      classify_with_model(
+        args,
+        &backend,
+        &metadata,
+        model,
+        &prompt_template,
+        &prompt_hash,
+        &cached_results,
+        &mut llm_results,
+      )
+      .await?,
     );
-    ErrorOutput {
-      domain: args.domain.clone(),
-      result: "error".to_string(),
-      error: ErrorInfo {
-        error_type: e.to_error_type(),
-        message: e.to_string(),
+  }
+
+  let (mut classification, ensemble) = if votes.len() == 1 {
+    let vote = votes.remove(0);
+    (
+      Classification {
+        is_matching_site: vote.is_matching_site,
+        confidence: vote.confidence,
+        reasoning: vote.reasoning,
       },
-      metadata: Some(PartialMetadata {
-        model: args.ollama_model.clone(),
-        prompt_hash: prompt_hash.clone(),
+      None,
+    )
+  } else {
+    for vote in &votes {
+      info!(
+        "  Vote from {}: is_matching={}, confidence={:.2}",
+        vote.model, vote.is_matching_site, vote.confidence
+      );
+    }
+    (
+      combine(args.ensemble_strategy, &votes),
+      Some(EnsembleVotes {
+        strategy: args.ensemble_strategy,
+        votes,
       }),
+    )
+  };
+
+  // Escalate to larger models while the result is too uncertain to stand.
+  let band = UncertaintyBand {
+    min: args.uncertainty_min,
+    max: args.uncertainty_max,
+  };
+  let escalation = if args.escalation_model.is_empty() {
+    None
+  } else {
+    let mut tiers = vec![ModelVote {
+      model: model_label.clone(),
+      is_matching_site: classification.is_matching_site,
+      confidence: classification.confidence,
+      reasoning: classification.reasoning.clone(),
+    }];
+    for model in &args.escalation_model {
+      if !band.contains(classification.confidence) {
+        break;
+      }
+      info!(
+        "  Confidence {:.2} is within [{:.2}, {:.2}), escalating to {}",
+        classification.confidence, band.min, band.max, model
+      );
+      let vote = classify_with_model(
+        args,
+        &backend,
+        &metadata,
+        model,
+        &prompt_template,
+        &prompt_hash,
+        &cached_results,
+        &mut llm_results,
+      )
+      .await?;
+      classification = Classification {
+        is_matching_site: vote.is_matching_site,
+        confidence: vote.confidence,
+        reasoning: vote.reasoning.clone(),
+      };
+      model_label = model.clone();
+      tiers.push(vote);
     }
-  })?;
+    info!("  Decided by tier {} ({})", tiers.len() - 1, model_label);
+    Some(EscalationTiers {
+      band,
+      decided_by_tier: tiers.len() - 1,
+      tiers,
+    })
+  };
 
   info!(
     "  LLM classification succeeded in {:.2}s",
@@ -206,8 +412,152 @@ async fn run_classification(
     classification,
     metadata: ClassificationMetadata {
       http_status: metadata.http_status,
-      model: args.ollama_model.clone(),
+      model: model_label,
       prompt_hash,
+      fetch_error: metadata.fetch_error.clone(),
+      final_host: metadata.final_host.clone(),
+      redirect_chain: metadata.redirect_chain.clone(),
+      scheme: connection.as_ref().map(|(scheme, _)| scheme.clone()),
+      remote_addr: connection
+        .and_then(|(_, addr)| addr)
+        .map(|addr| addr.to_string()),
+      evidence_inherited_from: metadata
+        .parent_site
+        .as_ref()
+        .map(|parent| parent.domain.clone()),
+      llm_results,
+    },
+    ensemble,
+    escalation,
+  })
+}
+
+/// Fetch the parent domain's page when [`SiteMetadata::parent_to_fetch`]
+/// asks for it, and attach it as `parent_site` evidence.  The parent is
+/// looked up through the system resolver, or the proxy, since only the host
+/// was pre-resolved.
+async fn with_parent_site(
+  args: &CliArgs,
+  proxy: Option<&reqwest::Proxy>,
+  cache: Option<&FetchCache>,
+  metadata: SiteMetadata,
+) -> SiteMetadata {
+  let Some(parent) = metadata.parent_to_fetch() else {
+    return metadata;
+  };
+
+  info!(
+    "  No usable landing page, fetching parent domain {}...",
+    parent
+  );
+  let cached = cache
+    .and_then(|cache| cache.get(&parent, &args.extraction, args.http_max_kb));
+  let parent_site = match cached {
+    Some(page) => Some(page.metadata),
+    None => match fetch_domain(
+      &parent,
+      args.http_timeout_sec,
+      args.http_max_kb,
+      &[],
+      &args.egress,
+      proxy,
+    )
+    .await
+    {
+      Ok(page) => extract_metadata_with_limits(
+        &parent,
+        &page.body,
+        page.status,
+        &args.extraction,
+      )
+      .inspect(|metadata| cache_page(cache, args, &parent, &page, metadata))
+      .inspect_err(|e| {
+        error!("  Failed to extract metadata of {}: {}", parent, e)
+      })
+      .ok(),
+      Err(e) => {
+        error!("  Fetching parent domain {} failed: {}", parent, e);
+        None
+      }
     },
+  };
+  let metadata = metadata.with_parent_site(parent_site);
+  if metadata.parent_site.is_some() {
+    info!("  Inheriting evidence from {}", parent);
+  }
+  metadata
+}
+
+/// Cache the metadata extracted from `page`, when caching is on.
+fn cache_page(
+  cache: Option<&FetchCache>,
+  args: &CliArgs,
+  domain: &str,
+  page: &FetchedPage,
+  metadata: &SiteMetadata,
+) {
+  if let Some(cache) = cache {
+    cache.put(&CachedPage::new(
+      domain,
+      page,
+      &args.extraction,
+      args.http_max_kb,
+      metadata.clone(),
+    ));
+  }
+}
+
+/// Classify `metadata` with a single model, unless `cached_results` holds its
+/// answer, mapping failures to the error output reported for this domain.
+#[allow(clippy::too_many_arguments)]
+async fn classify_with_model(
+  args: &CliArgs,
+  backend: &LlmBackend,
+  metadata: &SiteMetadata,
+  model: &str,
+  prompt_template: &str,
+  prompt_hash: &str,
+  cached_results: &[LlmResult],
+  results: &mut Vec<LlmResult>,
+) -> Result<ModelVote, ErrorOutput> {
+  use std::time::Instant;
+  let llm_start = Instant::now();
+
+  let result = classify_with_result_cache(
+    metadata,
+    backend,
+    model,
+    prompt_template,
+    cached_results,
+  )
+  .await
+  .map_err(|e| {
+    error!(
+      "  LLM classification with {} failed after {:.2}s: {}",
+      model,
+      llm_start.elapsed().as_secs_f64(),
+      e
+    );
+    ErrorOutput {
+      domain: args.domain.clone(),
+      result: "error".to_string(),
+      error: ErrorInfo {
+        error_type: e.to_error_type(),
+        message: e.to_string(),
+      },
+      metadata: Some(PartialMetadata {
+        model: model.to_string(),
+        prompt_hash: prompt_hash.to_string(),
+      }),
+    }
+  })?;
+
+  let classification = result.classification.clone();
+  results.push(result);
+  Ok(ModelVote {
+    model: model.to_string(),
+    is_matching_site: classification.is_matching_site,
+    confidence: classification.confidence,
+    reasoning: classification.reasoning,
   })
 }
@@ -1,4 +1,7 @@
+use crate::ensemble::{EnsembleStrategy, ModelVote};
 use crate::error::ClassifierErrorType;
+use crate::escalation::UncertaintyBand;
+use crate::web_classify::RedirectHop;
 use serde::{Deserialize, Serialize};
 
 /// Classification result from the LLM
@@ -15,6 +18,44 @@ pub struct ClassificationMetadata {
   pub http_status: u16,
   pub model: String,
   pub prompt_hash: String,
+  /// Why the site could not be fetched, when the classification was made
+  /// from the domain name alone.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub fetch_error: Option<String>,
+  /// Host the landing page was served from, after redirects.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub final_host: Option<String>,
+  /// Redirects followed to reach the landing page.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub redirect_chain: Vec<RedirectHop>,
+  /// Scheme the landing page was fetched over: "https", or "http" after
+  /// falling back.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub scheme: Option<String>,
+  /// Address that served the landing page (e.g., "[2001:db8::1]:443").
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub remote_addr: Option<String>,
+  /// Parent domain whose landing page was passed as evidence because the
+  /// host's own had none.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub evidence_inherited_from: Option<String>,
+  /// Every model's answer with its result cache key, so that it can be
+  /// reused for identical evidence.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub llm_results: Vec<LlmResult>,
+}
+
+/// A model's answer, keyed on the evidence, prompt, model and generation
+/// options it was given.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LlmResult {
+  /// Result cache key, see `compute_result_key`
+  pub key: String,
+  pub model: String,
+  pub classification: Classification,
+  /// Whether the answer was taken from the cache instead of the LLM
+  #[serde(default)]
+  pub reused: bool,
 }
 
 /// Error information
@@ -31,6 +72,28 @@ pub struct ClassificationOutput {
   pub result: String, // "classified"
   pub classification: Classification,
   pub metadata: ClassificationMetadata,
+  /// Individual votes when the classification combines several models.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub ensemble: Option<EnsembleVotes>,
+  /// Result of each tier when escalation models are configured.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub escalation: Option<EscalationTiers>,
+}
+
+/// The tiers run for an escalated classification.  `tiers[0]` is the cheap
+/// first tier; the last entry is the tier that decided.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EscalationTiers {
+  pub band: UncertaintyBand,
+  pub decided_by_tier: usize,
+  pub tiers: Vec<ModelVote>,
+}
+
+/// How an ensemble classification was reached.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EnsembleVotes {
+  pub strategy: EnsembleStrategy,
+  pub votes: Vec<ModelVote>,
 }
 
 /// Output format for errors
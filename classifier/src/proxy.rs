@@ -0,0 +1,135 @@
+//! Outbound proxy of the fetcher.
+//!
+//! Page fetches can go through an HTTP(S) or SOCKS5 proxy, so that sketchy
+//! domains see the proxy's address rather than the household's, or because
+//! the network only allows outbound traffic through one.  The proxy resolves
+//! the names it is asked for: pre-resolved IPs are not used in proxy mode,
+//! and SOCKS5 proxies must be given as `socks5h://` so no lookup happens
+//! locally.
+
+use crate::error::ClassifierError;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Proxy URL schemes page fetches can go through.
+const SUPPORTED_SCHEMES: [&str; 3] = ["http", "https", "socks5h"];
+
+/// A proxy for page fetches.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProxyConfig {
+  /// Proxy URL (e.g., "http://proxy.lan:3128" or "socks5h://tor.lan:9050")
+  pub url: String,
+
+  /// File holding the proxy credentials as `user:password` (optional)
+  pub credentials_file: Option<PathBuf>,
+}
+
+impl ProxyConfig {
+  /// Check the URL, without reading the credentials.
+  pub fn validate(&self) -> Result<(), String> {
+    let url = reqwest::Url::parse(&self.url)
+      .map_err(|e| format!("invalid proxy URL '{}': {}", self.url, e))?;
+    if url.scheme() == "socks5" {
+      return Err(format!(
+        "proxy URL '{}' resolves names locally, use socks5h:// instead",
+        self.url
+      ));
+    }
+    if !SUPPORTED_SCHEMES.contains(&url.scheme()) {
+      return Err(format!(
+        "proxy URL '{}' must use one of {}",
+        self.url,
+        SUPPORTED_SCHEMES.join(", ")
+      ));
+    }
+    if url.host_str().is_none() {
+      return Err(format!("proxy URL '{}' has no host", self.url));
+    }
+    Ok(())
+  }
+
+  /// The proxy every fetch is sent through, with its credentials read from
+  /// disk.
+  pub fn proxy(&self) -> Result<reqwest::Proxy, ClassifierError> {
+    self.validate().map_err(ClassifierError::InvalidProxy)?;
+    let proxy = reqwest::Proxy::all(&self.url)?;
+    match &self.credentials_file {
+      Some(path) => {
+        let (user, password) = read_proxy_credentials(path)?;
+        Ok(proxy.basic_auth(&user, &password))
+      }
+      None => Ok(proxy),
+    }
+  }
+}
+
+/// Read `user:password` proxy credentials, ignoring surrounding whitespace.
+pub fn read_proxy_credentials(
+  path: &Path,
+) -> Result<(String, String), ClassifierError> {
+  let credentials = std::fs::read_to_string(path)
+    .map_err(ClassifierError::ProxyCredentialsFileReadError)?;
+  let (user, password) =
+    credentials.trim().split_once(':').ok_or_else(|| {
+      ClassifierError::InvalidProxy(format!(
+        "proxy credentials in {} are not in user:password form",
+        path.display()
+      ))
+    })?;
+  Ok((user.to_string(), password.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::Write;
+
+  fn proxy(url: &str) -> ProxyConfig {
+    ProxyConfig {
+      url: url.to_string(),
+      credentials_file: None,
+    }
+  }
+
+  #[test]
+  fn test_validate() {
+    for url in [
+      "http://proxy.lan:3128",
+      "https://proxy.example.com",
+      "socks5h://127.0.0.1:9050",
+    ] {
+      assert_eq!(proxy(url).validate(), Ok(()), "{}", url);
+    }
+    for (url, expected) in [
+      ("socks5://127.0.0.1:9050", "use socks5h://"),
+      ("ftp://proxy.lan", "must use one of"),
+      ("proxy.lan:3128", "must use one of"),
+      ("not a url", "invalid proxy URL"),
+    ] {
+      let e = proxy(url).validate().unwrap_err();
+      assert!(e.contains(expected), "{}: {}", url, e);
+    }
+  }
+
+  #[test]
+  fn test_read_proxy_credentials() {
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    writeln!(file, "household:s3cr:et").unwrap();
+    assert_eq!(
+      read_proxy_credentials(file.path()).unwrap(),
+      ("household".to_string(), "s3cr:et".to_string())
+    );
+
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    writeln!(file, "just-a-token").unwrap();
+    assert!(matches!(
+      read_proxy_credentials(file.path()),
+      Err(ClassifierError::InvalidProxy(_))
+    ));
+
+    assert!(matches!(
+      read_proxy_credentials(Path::new("/nonexistent/proxy")),
+      Err(ClassifierError::ProxyCredentialsFileReadError(_))
+    ));
+  }
+}
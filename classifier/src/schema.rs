@@ -0,0 +1,124 @@
+//! The shape of a classification answer.
+//!
+//! The JSON Schema is sent with every request so backends that support
+//! structured outputs constrain the model to it.  Not every backend enforces
+//! every keyword, so the answer is validated again here before it is
+//! trusted.
+
+use crate::error::ClassificationIssue;
+use crate::output::Classification;
+use serde_json::{Value, json};
+
+/// JSON Schema of the object the model must answer with.
+pub fn classification_schema() -> Value {
+  json!({
+    "type": "object",
+    "properties": {
+      "is_matching_site": { "type": "boolean" },
+      "confidence": { "type": "number", "minimum": 0.0, "maximum": 1.0 },
+      "reasoning": { "type": "string", "minLength": 1 }
+    },
+    "required": ["is_matching_site", "confidence", "reasoning"],
+    "additionalProperties": false
+  })
+}
+
+/// Keywords OpenAI's strict structured outputs reject with a 400.
+const NON_STRICT_KEYWORDS: [&str; 2] = ["minLength", "maxLength"];
+
+/// `schema` without the keywords OpenAI's strict structured outputs do not
+/// support, for backends that enforce the schema strictly.  The answer is
+/// still checked against the full schema by [`parse_classification`].
+pub fn strict_schema(schema: &Value) -> Value {
+  match schema {
+    Value::Object(object) => Value::Object(
+      object
+        .iter()
+        .filter(|(key, _)| !NON_STRICT_KEYWORDS.contains(&key.as_str()))
+        .map(|(key, value)| (key.clone(), strict_schema(value)))
+        .collect(),
+    ),
+    Value::Array(items) => {
+      Value::Array(items.iter().map(strict_schema).collect())
+    }
+    other => other.clone(),
+  }
+}
+
+/// Parse and validate the model's answer.
+pub fn parse_classification(
+  content: &str,
+) -> Result<Classification, ClassificationIssue> {
+  let value: Value = serde_json::from_str(content)
+    .map_err(|e| ClassificationIssue::NotJson(e.to_string()))?;
+  let classification: Classification = serde_json::from_value(value)
+    .map_err(|e| ClassificationIssue::SchemaMismatch(e.to_string()))?;
+  if !(0.0..=1.0).contains(&classification.confidence) {
+    return Err(ClassificationIssue::ConfidenceOutOfRange(
+      classification.confidence,
+    ));
+  }
+  if classification.reasoning.trim().is_empty() {
+    return Err(ClassificationIssue::EmptyReasoning);
+  }
+  Ok(classification)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_valid_answer() {
+    let classification = parse_classification(
+      r#"{"is_matching_site": true, "confidence": 0.9, "reasoning": "A game store"}"#,
+    )
+    .unwrap();
+    assert!(classification.is_matching_site);
+    assert_eq!(classification.confidence, 0.9);
+  }
+
+  #[test]
+  fn test_strict_schema_drops_unsupported_keywords() {
+    let strict = strict_schema(&classification_schema());
+    assert_eq!(
+      strict["properties"]["reasoning"],
+      json!({ "type": "string" })
+    );
+    assert_eq!(
+      strict["properties"]["confidence"],
+      classification_schema()["properties"]["confidence"]
+    );
+    assert_eq!(strict["required"], classification_schema()["required"]);
+  }
+
+  #[test]
+  fn test_each_issue_is_reported() {
+    assert!(matches!(
+      parse_classification("The site sells games."),
+      Err(ClassificationIssue::NotJson(_))
+    ));
+    assert!(matches!(
+      parse_classification(
+        r#"{"is_matching_site": "yes", "confidence": 0.9, "reasoning": "x"}"#
+      ),
+      Err(ClassificationIssue::SchemaMismatch(_))
+    ));
+    assert!(matches!(
+      parse_classification(r#"{"is_matching_site": true, "confidence": 0.9}"#),
+      Err(ClassificationIssue::SchemaMismatch(_))
+    ));
+    assert_eq!(
+      parse_classification(
+        r#"{"is_matching_site": true, "confidence": 95, "reasoning": "x"}"#
+      ),
+      Err(ClassificationIssue::ConfidenceOutOfRange(95.0))
+    );
+    assert_eq!(
+      parse_classification(
+        r#"{"is_matching_site": true, "confidence": 0.9, "reasoning": "  "}"#
+      ),
+      Err(ClassificationIssue::EmptyReasoning)
+    );
+  }
+}
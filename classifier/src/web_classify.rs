@@ -1,14 +1,130 @@
+use crate::certificate::CertificateInfo;
+use crate::charset::decode_body;
+use crate::egress::{EgressPolicy, EgressResolver, blocked_by};
 use crate::error::ClassifierError;
 use reqwest::redirect::Policy;
-use scraper::{Html, Selector};
-use serde::Serialize;
-use std::net::IpAddr;
+use scraper::{ElementRef, Html, Node, Selector};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use tracing::{info, warn};
 
+const DEFAULT_BODY_TEXT_CHARS: usize = 1000;
+const DEFAULT_MAX_HEADINGS: usize = 10;
+const DEFAULT_FIELD_CHARS: usize = 300;
+const DEFAULT_MAX_JSON_LD: usize = 3;
+const DEFAULT_MAX_LINK_DOMAINS: usize = 10;
+
+/// Size budgets for the page evidence passed to the LLM, so prompts stay
+/// within the model's context.  A budget of 0 omits the field.
+#[derive(clap::Args, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ExtractionLimits {
+  /// Characters of visible body text to include.
+  #[arg(
+    long,
+    env = "BODY_TEXT_CHARS",
+    default_value_t = DEFAULT_BODY_TEXT_CHARS
+  )]
+  pub body_text_chars: usize,
+
+  /// Number of h1/h2 headings to include.
+  #[arg(long, env = "MAX_HEADINGS", default_value_t = DEFAULT_MAX_HEADINGS)]
+  pub max_headings: usize,
+
+  /// Characters of each heading, keyword list, Twitter card field and
+  /// JSON-LD name or description.
+  #[arg(long, env = "FIELD_CHARS", default_value_t = DEFAULT_FIELD_CHARS)]
+  pub field_chars: usize,
+
+  /// Number of schema.org JSON-LD entities to include.
+  #[arg(long, env = "MAX_JSON_LD", default_value_t = DEFAULT_MAX_JSON_LD)]
+  pub max_json_ld: usize,
+
+  /// Number of outbound link domains to include, most linked first.
+  #[arg(
+    long,
+    env = "MAX_LINK_DOMAINS",
+    default_value_t = DEFAULT_MAX_LINK_DOMAINS
+  )]
+  pub max_link_domains: usize,
+}
+
+impl Default for ExtractionLimits {
+  fn default() -> Self {
+    Self {
+      body_text_chars: DEFAULT_BODY_TEXT_CHARS,
+      max_headings: DEFAULT_MAX_HEADINGS,
+      field_chars: DEFAULT_FIELD_CHARS,
+      max_json_ld: DEFAULT_MAX_JSON_LD,
+      max_link_domains: DEFAULT_MAX_LINK_DOMAINS,
+    }
+  }
+}
+
+/// A schema.org entity described by a page's JSON-LD.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct JsonLdEntity {
+  #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+  pub entity_type: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub name: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub description: Option<String>,
+}
+
+/// Maximum number of redirects followed when fetching a landing page.
+const MAX_REDIRECTS: usize = 10;
+
+/// A redirect followed while fetching a landing page.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RedirectHop {
+  /// URL that answered with the redirect
+  pub url: String,
+  /// Its redirect status code
+  pub status: u16,
+}
+
+/// A fetched landing page and how the fetch got there.
+#[derive(Debug, Clone)]
+pub struct FetchedPage {
+  /// Response body, truncated to the size limit and decoded
+  pub body: String,
+  /// Name of the encoding the body was decoded from (e.g., "Shift_JIS")
+  pub encoding: String,
+  /// Hex SHA-256 of the body as received, before decoding
+  pub body_sha256: String,
+  pub status: u16,
+  /// Scheme that was fetched, "https" or "http" after falling back
+  pub scheme: String,
+  /// Address the body was served from
+  pub remote_addr: Option<SocketAddr>,
+  /// URL the body was served from, after redirects
+  pub final_url: String,
+  /// Redirects followed, in order
+  pub redirects: Vec<RedirectHop>,
+  /// Certificate of the host that served the body, for HTTPS
+  pub certificate: Option<CertificateInfo>,
+}
+
+impl FetchedPage {
+  /// Host the fetch ended up on.
+  pub fn final_host(&self) -> Option<String> {
+    reqwest::Url::parse(&self.final_url)
+      .ok()
+      .and_then(|url| url.host_str().map(str::to_ascii_lowercase))
+  }
+}
+
 /// Metadata extracted from an HTTP fetch of a domain's landing page.
 /// Passed to the LLM as structured input for classification.
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
 pub struct SiteMetadata {
   pub domain: String,
   #[serde(skip_serializing_if = "Option::is_none")]
@@ -23,6 +139,39 @@ pub struct SiteMetadata {
   pub og_site_name: Option<String>,
   #[serde(skip_serializing_if = "Option::is_none")]
   pub language: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub keywords: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub twitter_title: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub twitter_description: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub twitter_site: Option<String>,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub headings: Vec<String>,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub json_ld: Vec<JsonLdEntity>,
+  /// Excerpt of the visible body text, scripts and styles stripped
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub body_text: Option<String>,
+  /// Domains the page links to most, excluding its own
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub outbound_link_domains: Vec<String>,
+  /// Host the landing page was served from, after redirects
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub final_host: Option<String>,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub redirect_chain: Vec<RedirectHop>,
+  /// Subject, issuer and SANs of the host's TLS certificate
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub tls_certificate: Option<CertificateInfo>,
+  /// Encoding the page was decoded from
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub encoding: Option<String>,
+  /// Evidence from the registrable parent domain, when this host's own
+  /// landing page had none
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub parent_site: Option<Box<SiteMetadata>>,
   pub http_status: u16,
   #[serde(skip_serializing_if = "Option::is_none")]
   pub fetch_error: Option<String>,
@@ -33,30 +182,158 @@ impl SiteMetadata {
   pub fn from_fetch_error(domain: &str, error: &str) -> Self {
     Self {
       domain: domain.to_string(),
-      title: None,
-      description: None,
-      og_title: None,
-      og_description: None,
-      og_site_name: None,
-      language: None,
       http_status: 0,
       fetch_error: Some(error.to_string()),
+      ..Self::default()
+    }
+  }
+
+  /// Whether the landing page says anything about the site: it was served
+  /// successfully with a title, a description (Open Graph included) or body
+  /// text.
+  pub fn has_page_content(&self) -> bool {
+    (200..400).contains(&self.http_status)
+      && (self.title.is_some()
+        || self.description.is_some()
+        || self.og_title.is_some()
+        || self.og_description.is_some()
+        || self.body_text.is_some())
+  }
+
+  /// The registrable parent domain whose page to fetch as further evidence,
+  /// when the landing page says nothing about the host, as is common for API
+  /// and CDN hosts.
+  pub fn parent_to_fetch(&self) -> Option<String> {
+    if self.has_page_content() {
+      return None;
+    }
+    parent_domain(&self.domain)
+  }
+
+  /// Attach the parent domain's page as `parent_site` evidence, unless it
+  /// says nothing about the site either.
+  pub fn with_parent_site(self, parent_site: Option<SiteMetadata>) -> Self {
+    match parent_site.filter(Self::has_page_content) {
+      Some(parent_site) => Self {
+        parent_site: Some(Box::new(parent_site)),
+        ..self
+      },
+      None => self,
+    }
+  }
+}
+
+/// The registrable domain `domain` belongs to per the Public Suffix List,
+/// when that is another name (e.g., "game.com" for "api.game.com").  URLs
+/// and IP addresses have none.
+pub fn parent_domain(domain: &str) -> Option<String> {
+  if domain.contains("://") || domain.parse::<IpAddr>().is_ok() {
+    return None;
+  }
+  let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+  let parent = psl::domain_str(&domain)?;
+  (parent != domain).then(|| parent.to_string())
+}
+
+/// Parse pre-resolved addresses, skipping any that are not IP addresses, and
+/// order them for happy eyeballs (RFC 8305): IPv6 first, then alternating
+/// between the families.
+pub fn happy_eyeballs_order(
+  domain: &str,
+  resolved_ips: &[String],
+) -> Vec<IpAddr> {
+  let mut v6 = Vec::new();
+  let mut v4 = Vec::new();
+  for ip_str in resolved_ips {
+    match ip_str.trim().parse::<IpAddr>() {
+      Ok(ip) if v6.contains(&ip) || v4.contains(&ip) => {}
+      Ok(ip @ IpAddr::V6(_)) => v6.push(ip),
+      Ok(ip @ IpAddr::V4(_)) => v4.push(ip),
+      Err(e) => {
+        warn!(
+          "Could not parse resolved_ip '{}' for {}: {} — ignoring it",
+          ip_str, domain, e
+        );
+      }
+    }
+  }
+  let mut ordered = Vec::with_capacity(v6.len() + v4.len());
+  let (mut v6, mut v4) = (v6.into_iter(), v4.into_iter());
+  loop {
+    match (v6.next(), v4.next()) {
+      (None, None) => break,
+      (a, b) => ordered.extend(a.into_iter().chain(b)),
     }
   }
+  ordered
 }
 
-/// Fetch a domain's landing page via HTTPS (falling back to HTTP), returning
-/// the response body truncated to `max_kb` and the HTTP status code.
+/// The IP address a URL's host is written as, if it is one.
+fn ip_literal(url: &reqwest::Url) -> Option<IpAddr> {
+  url
+    .host_str()?
+    .trim_start_matches('[')
+    .trim_end_matches(']')
+    .parse()
+    .ok()
+}
+
+/// Fetch a domain's landing page, returning the response body, read up to
+/// `max_kb` and decoded, the HTTP status code, the redirects followed on the
+/// way and the serving host's certificate.  A bare domain is fetched over
+/// HTTPS, falling back to plain HTTP when the connection or TLS handshake
+/// fails; a URL is fetched as given.  Every address connected to, including
+/// on redirects, must be permitted by `egress`; a fetch it blocks fails with
+/// `ClassifierError::EgressBlocked` without being retried.
+///
+/// With a `proxy`, every request goes through it and the proxy resolves the
+/// names: `resolved_ips` are not used, and `egress` can only be applied to
+/// hosts given as IP addresses.
 pub async fn fetch_domain(
   domain: &str,
   timeout_sec: u64,
   max_kb: usize,
-  resolved_ip: Option<&str>,
-) -> Result<(String, u16), ClassifierError> {
+  resolved_ips: &[String],
+  egress: &EgressPolicy,
+  proxy: Option<&reqwest::Proxy>,
+) -> Result<FetchedPage, ClassifierError> {
   info!("Fetching domain: {}", domain);
 
+  let urls = if domain.starts_with("http://") || domain.starts_with("https://")
+  {
+    vec![domain.to_string()]
+  } else {
+    vec![format!("https://{}", domain), format!("http://{}", domain)]
+  };
+  let host = reqwest::Url::parse(&urls[0]).ok();
+  // IP literals are connected to without a lookup, so check them here.
+  if let Some(ip) = host.as_ref().and_then(ip_literal) {
+    egress.check(domain, ip)?;
+  }
+  let host = host.and_then(|url| url.host_str().map(str::to_string));
+
+  // Each followed redirect is recorded here; reset before every attempt.
+  let redirects: Arc<Mutex<Vec<RedirectHop>>> = Arc::default();
+  let recorder = Arc::clone(&redirects);
+  let redirect_egress = egress.clone();
   let mut builder = reqwest::Client::builder()
-    .redirect(Policy::limited(10))
+    .redirect(Policy::custom(move |attempt| {
+      if attempt.previous().len() > MAX_REDIRECTS {
+        return attempt.error("too many redirects");
+      }
+      if let Some(ip) = ip_literal(attempt.url()) {
+        if let Err(blocked) = redirect_egress.check(&ip.to_string(), ip) {
+          return attempt.error(blocked);
+        }
+      }
+      if let Some(from) = attempt.previous().last() {
+        recorder.lock().unwrap().push(RedirectHop {
+          url: from.to_string(),
+          status: attempt.status().as_u16(),
+        });
+      }
+      attempt.follow()
+    }))
     .timeout(Duration::from_secs(timeout_sec))
     .user_agent(
       "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) \
@@ -64,42 +341,49 @@ pub async fn fetch_domain(
        Safari/605.1.15",
     )
     .gzip(true)
+    .tls_info(true)
     .danger_accept_invalid_certs(true);
 
-  // When a pre-resolved IP is available, instruct reqwest to connect directly
-  // to that address rather than resolving through the local DNS stack.  This
-  // avoids a second DNS lookup that would generate a spurious log entry in the
-  // upstream resolver (Blocky, etc.) and potentially re-trigger classification.
-  // The Host header and TLS SNI still carry the domain name so the server
-  // responds correctly.  Note: reqwest's resolve() overrides apply per
-  // hostname; HTTP redirects to the same domain reuse the override, while
-  // redirects to other hostnames fall back to normal resolution.
-  if let Some(ip_str) = resolved_ip {
-    match ip_str.parse::<IpAddr>() {
-      Ok(ip) => {
-        // Use port 443 as the connection target for HTTPS (the default fetch
-        // scheme).  Same-domain HTTP redirects (port 80) also benefit since
-        // reqwest reuses the override for the same hostname.
-        builder = builder.resolve(domain, std::net::SocketAddr::new(ip, 443));
-        info!("Using pre-resolved IP {} for {}", ip_str, domain);
-      }
-      Err(e) => {
-        warn!(
-          "Could not parse resolved_ip '{}' for {}: {} — falling back to DNS",
-          ip_str, domain, e
-        );
-      }
+  // Only the proxy's own name is resolved locally in proxy mode, and the
+  // proxy is usually on the local network, so the policy is not applied to
+  // it.
+  if let Some(proxy) = proxy {
+    builder = builder.proxy(proxy.clone());
+    if !resolved_ips.is_empty() {
+      info!(
+        "Not using pre-resolved IPs for {}: fetching via proxy",
+        domain
+      );
+    }
+  } else {
+    builder = builder.dns_resolver(EgressResolver(egress.clone()));
+  }
+
+  // When pre-resolved IPs are available, instruct reqwest to connect directly
+  // to those addresses rather than resolving through the local DNS stack.
+  // This avoids a second DNS lookup that would generate a spurious log entry
+  // in the upstream resolver (Blocky, etc.) and potentially re-trigger
+  // classification.  The Host header and TLS SNI still carry the domain name
+  // so the server responds correctly.  The override is per hostname and the
+  // port comes from the URL, so the HTTP fallback (port 80) and same-domain
+  // redirects reuse it, while redirects to other hostnames fall back to
+  // normal resolution.  Given addresses of both families, the connector races
+  // them, starting the IPv4 attempt shortly after the IPv6 one.  These
+  // addresses bypass the resolver, so the egress policy is applied here.
+  // Through a proxy they are not pinned, but a domain whose every known
+  // address is internal is still refused rather than handed to the proxy.
+  let ips = happy_eyeballs_order(domain, resolved_ips);
+  if let (Some(host), false) = (host, ips.is_empty()) {
+    let addrs =
+      egress.filter(&host, ips.iter().map(|ip| SocketAddr::new(*ip, 0)))?;
+    if proxy.is_none() {
+      builder = builder.resolve_to_addrs(&host, &addrs);
+      info!("Using pre-resolved IPs {:?} for {}", addrs, host);
     }
   }
 
   let client = builder.build()?;
 
-  let url = if domain.starts_with("http://") || domain.starts_with("https://") {
-    domain.to_string()
-  } else {
-    format!("https://{}", domain)
-  };
-
   // Retry logic with exponential backoff: 3 attempts with 500ms, 1s, 2s delays
   let max_attempts = 3;
   let mut last_error = None;
@@ -111,42 +395,89 @@ pub async fn fetch_domain(
       tokio::time::sleep(Duration::from_millis(delay_ms)).await;
     }
 
-    match client
-      .get(&url)
-      .header(
-        "Accept",
-        "text/html,application/xhtml+xml,\
-        application/xml;q=0.9,*/*;q=0.8",
-      )
-      .header("Accept-Language", "en-US,en;q=0.9")
-      .send()
-      .await
-    {
-      Ok(response) => {
-        let status = response.status().as_u16();
-        info!("HTTP status: {} (attempt {})", status, attempt + 1);
-
-        let max_bytes = max_kb * 1024;
-        let body_bytes = response.bytes().await?;
-
-        let body = if body_bytes.len() > max_bytes {
-          info!(
-            "Truncating response from {} bytes to {} KB",
-            body_bytes.len(),
-            max_kb
-          );
-          &body_bytes[..max_bytes]
-        } else {
-          &body_bytes[..]
-        };
-
-        let html = String::from_utf8_lossy(body).to_string();
-        return Ok((html, status));
+    for (i, url) in urls.iter().enumerate() {
+      redirects.lock().unwrap().clear();
+
+      let response = match client
+        .get(url)
+        .header(
+          "Accept",
+          "text/html,application/xhtml+xml,\
+          application/xml;q=0.9,*/*;q=0.8",
+        )
+        .header("Accept-Language", "en-US,en;q=0.9")
+        .send()
+        .await
+      {
+        Ok(response) => response,
+        Err(e) => {
+          if let Some(blocked) = blocked_by(&e) {
+            warn!("Fetch of {} blocked: {}", url, blocked);
+            return Err(blocked.into());
+          }
+          warn!("HTTP request failed on attempt {}: {}", attempt + 1, e);
+          let fall_back = e.is_connect() && i + 1 < urls.len();
+          last_error = Some(e);
+          if fall_back {
+            info!("Falling back to {}", urls[i + 1]);
+            continue;
+          }
+          break;
+        }
+      };
+
+      let status = response.status().as_u16();
+      let final_url = response.url().to_string();
+      let remote_addr = response.remote_addr();
+      let certificate = response
+        .extensions()
+        .get::<reqwest::tls::TlsInfo>()
+        .and_then(|tls| tls.peer_certificate())
+        .and_then(CertificateInfo::from_der);
+      info!(
+        "HTTP status: {} from {:?} (attempt {})",
+        status,
+        remote_addr,
+        attempt + 1
+      );
+
+      let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+      // Stream the body and stop at the size limit, so a huge page is
+      // never held in memory.
+      let max_bytes = max_kb * 1024;
+      let mut response = response;
+      let mut body = Vec::new();
+      while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > max_bytes {
+          body.extend_from_slice(&chunk[..max_bytes - body.len()]);
+          info!("Truncating response at {} KB", max_kb);
+          break;
+        }
+        body.extend_from_slice(&chunk);
       }
-      Err(e) => {
-        warn!("HTTP request failed on attempt {}: {}", attempt + 1, e);
-        last_error = Some(e);
+      let body_sha256 = hex::encode(Sha256::digest(&body));
+      let (body, encoding) = decode_body(&body, content_type.as_deref());
+
+      let redirects = std::mem::take(&mut *redirects.lock().unwrap());
+      if !redirects.is_empty() {
+        info!("Followed {} redirect(s) to {}", redirects.len(), final_url);
       }
+      return Ok(FetchedPage {
+        body,
+        encoding: encoding.name().to_string(),
+        body_sha256,
+        status,
+        scheme: url.split("://").next().unwrap_or_default().to_string(),
+        remote_addr,
+        final_url,
+        redirects,
+        certificate,
+      });
     }
   }
 
@@ -184,12 +515,210 @@ pub fn text_from_css_selector(
     .filter(|s| !s.is_empty())
 }
 
-/// Parse HTML and extract title, meta description, OpenGraph tags, and
-/// language attribute into a [`SiteMetadata`] struct for LLM classification.
+/// At most `max` characters of `s`.
+fn truncate_chars(s: &str, max: usize) -> String {
+  s.chars().take(max).collect()
+}
+
+/// `s` with runs of whitespace collapsed to single spaces.
+fn collapse_whitespace(s: &str) -> String {
+  s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// The first meta tag with `name` or `property` equal to `key`, truncated.
+fn meta_content(document: &Html, key: &str, max: usize) -> Option<String> {
+  if max == 0 {
+    return None;
+  }
+  attr_from_css_selector(
+    document,
+    &format!("meta[name='{key}'], meta[property='{key}']"),
+    "content",
+  )
+  .map(|s| truncate_chars(&s, max))
+}
+
+/// Text of the `h1` and `h2` headings, in document order.
+fn extract_headings(document: &Html, limits: &ExtractionLimits) -> Vec<String> {
+  let selector = Selector::parse("h1, h2").expect("valid selector");
+  document
+    .select(&selector)
+    .map(|el| collapse_whitespace(&el.text().collect::<String>()))
+    .filter(|s| !s.is_empty())
+    .map(|s| truncate_chars(&s, limits.field_chars))
+    .take(limits.max_headings)
+    .collect()
+}
+
+/// Text a visitor would see in the body, skipping scripts, styles and other
+/// non-rendered elements.
+fn extract_body_text(document: &Html, max: usize) -> Option<String> {
+  if max == 0 {
+    return None;
+  }
+  let selector = Selector::parse("body").expect("valid selector");
+  let body = document.select(&selector).next()?;
+  let mut text = String::new();
+  for node in body.descendants() {
+    let Node::Text(fragment) = node.value() else {
+      continue;
+    };
+    let hidden = node.ancestors().filter_map(ElementRef::wrap).any(|el| {
+      matches!(
+        el.value().name(),
+        "script" | "style" | "noscript" | "template" | "svg"
+      )
+    });
+    if !hidden {
+      text.push_str(fragment);
+      text.push(' ');
+    }
+    if text.len() > max * 4 {
+      // Enough raw text for the budget even after collapsing whitespace.
+      break;
+    }
+  }
+  Some(truncate_chars(&collapse_whitespace(&text), max))
+    .filter(|s| !s.is_empty())
+}
+
+/// A JSON-LD string property, or the strings of an array property joined.
+fn json_ld_string(value: Option<&Value>) -> Option<String> {
+  match value? {
+    Value::String(s) => Some(s.trim().to_string()),
+    Value::Array(items) => Some(
+      items
+        .iter()
+        .filter_map(Value::as_str)
+        .collect::<Vec<_>>()
+        .join(", "),
+    ),
+    _ => None,
+  }
+  .filter(|s| !s.is_empty())
+}
+
+/// Collect the entities of a JSON-LD document, descending into arrays and
+/// `@graph`.
+fn collect_json_ld(value: &Value, entities: &mut Vec<JsonLdEntity>) {
+  match value {
+    Value::Array(items) => {
+      for item in items {
+        collect_json_ld(item, entities);
+      }
+    }
+    Value::Object(object) => {
+      if let Some(graph) = object.get("@graph") {
+        collect_json_ld(graph, entities);
+      }
+      let entity = JsonLdEntity {
+        entity_type: json_ld_string(object.get("@type")),
+        name: json_ld_string(object.get("name")),
+        description: json_ld_string(object.get("description")),
+      };
+      if entity.name.is_some() || entity.description.is_some() {
+        entities.push(entity);
+      }
+    }
+    _ => {}
+  }
+}
+
+/// The schema.org entities described by the page's JSON-LD scripts.
+/// Scripts that fail to parse are skipped.
+fn extract_json_ld(
+  document: &Html,
+  limits: &ExtractionLimits,
+) -> Vec<JsonLdEntity> {
+  let selector = Selector::parse("script[type='application/ld+json']")
+    .expect("valid selector");
+  let mut entities = Vec::new();
+  for script in document.select(&selector) {
+    let text = script.text().collect::<String>();
+    if let Ok(value) = serde_json::from_str::<Value>(&text) {
+      collect_json_ld(&value, &mut entities);
+    }
+  }
+  entities
+    .into_iter()
+    .take(limits.max_json_ld)
+    .map(|entity| JsonLdEntity {
+      entity_type: entity.entity_type,
+      name: entity.name.map(|s| truncate_chars(&s, limits.field_chars)),
+      description: entity
+        .description
+        .map(|s| truncate_chars(&s, limits.field_chars)),
+    })
+    .collect()
+}
+
+/// Whether `host` belongs to the same site as `domain`: equal, or one a
+/// subdomain of the other.
+fn same_site(host: &str, domain: &str) -> bool {
+  host == domain
+    || host.ends_with(&format!(".{domain}"))
+    || domain.ends_with(&format!(".{host}"))
+}
+
+/// The domains of absolute links to other sites, most linked first.
+fn extract_outbound_link_domains(
+  document: &Html,
+  domain: &str,
+  max: usize,
+) -> Vec<String> {
+  if max == 0 {
+    return Vec::new();
+  }
+  let selector = Selector::parse("a[href]").expect("valid selector");
+  let mut counts: HashMap<String, usize> = HashMap::new();
+  for link in document.select(&selector) {
+    let Some(href) = link.value().attr("href") else {
+      continue;
+    };
+    let Ok(url) = reqwest::Url::parse(href.trim()) else {
+      // Relative links stay on the site.
+      continue;
+    };
+    if !matches!(url.scheme(), "http" | "https") {
+      continue;
+    }
+    let Some(host) = url.host_str() else {
+      continue;
+    };
+    let host = host.trim_start_matches("www.").to_ascii_lowercase();
+    if !same_site(&host, domain.trim_start_matches("www.")) {
+      *counts.entry(host).or_default() += 1;
+    }
+  }
+  let mut domains: Vec<(String, usize)> = counts.into_iter().collect();
+  domains.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+  domains.into_iter().take(max).map(|(d, _)| d).collect()
+}
+
+/// Parse HTML and extract the page evidence for LLM classification using
+/// the default size budgets.
 pub fn extract_metadata(
   domain: &str,
   html: &str,
   status: u16,
+) -> Result<SiteMetadata, ClassifierError> {
+  extract_metadata_with_limits(
+    domain,
+    html,
+    status,
+    &ExtractionLimits::default(),
+  )
+}
+
+/// Parse HTML and extract title, meta description, OpenGraph and Twitter
+/// card tags, keywords, language, headings, JSON-LD entities, an excerpt of
+/// the visible text and the outbound link domains into a [`SiteMetadata`]
+/// struct for LLM classification, each within its budget in `limits`.
+pub fn extract_metadata_with_limits(
+  domain: &str,
+  html: &str,
+  status: u16,
+  limits: &ExtractionLimits,
 ) -> Result<SiteMetadata, ClassifierError> {
   info!("Extracting metadata from HTML");
   let document = Html::parse_document(html);
@@ -217,8 +746,24 @@ pub fn extract_metadata(
     og_description,
     og_site_name,
     language,
+    keywords: meta_content(&document, "keywords", limits.field_chars),
+    twitter_title: meta_content(&document, "twitter:title", limits.field_chars),
+    twitter_description: meta_content(
+      &document,
+      "twitter:description",
+      limits.field_chars,
+    ),
+    twitter_site: meta_content(&document, "twitter:site", limits.field_chars),
+    headings: extract_headings(&document, limits),
+    json_ld: extract_json_ld(&document, limits),
+    body_text: extract_body_text(&document, limits.body_text_chars),
+    outbound_link_domains: extract_outbound_link_domains(
+      &document,
+      &domain.to_ascii_lowercase(),
+      limits.max_link_domains,
+    ),
     http_status: status,
-    fetch_error: None,
+    ..SiteMetadata::default()
   })
 }
 
@@ -544,6 +1089,101 @@ mod tests {
     );
   }
 
+  #[test]
+  fn test_extract_metadata_page_evidence() {
+    let html = r#"
+      <!DOCTYPE html>
+      <html lang="en">
+        <head>
+          <title>Home</title>
+          <meta name="keywords" content="games, mmo, fantasy">
+          <meta name="twitter:title" content="Play Realm Online">
+          <meta name="twitter:description" content="A free fantasy MMO">
+          <meta name="twitter:site" content="@realmonline">
+          <style>body { color: red; }</style>
+          <script type="application/ld+json">
+            {"@context": "https://schema.org", "@graph": [
+              {"@type": "VideoGame", "name": "Realm Online",
+               "description": "Massively multiplayer fantasy game"},
+              {"@type": "WebSite", "url": "https://realm.example"}
+            ]}
+          </script>
+          <script type="application/ld+json">not json</script>
+        </head>
+        <body>
+          <script>var tracking = "hidden";</script>
+          <h1>Realm   Online</h1>
+          <p>Build your   guild and raid dungeons.</p>
+          <h2>Download</h2>
+          <h3>Ignored</h3>
+          <a href="/play">Play</a>
+          <a href="https://forum.realm.example/">Forum</a>
+          <a href="https://discord.gg/realm">Discord</a>
+          <a href="https://www.twitch.tv/realm">Twitch</a>
+          <a href="https://discord.gg/realm-help">Help</a>
+          <a href="mailto:support@realm.example">Mail</a>
+        </body>
+      </html>
+    "#;
+
+    let result = extract_metadata("realm.example", html, 200).unwrap();
+
+    assert_eq!(result.keywords, Some("games, mmo, fantasy".to_string()));
+    assert_eq!(result.twitter_title, Some("Play Realm Online".to_string()));
+    assert_eq!(
+      result.twitter_description,
+      Some("A free fantasy MMO".to_string())
+    );
+    assert_eq!(result.twitter_site, Some("@realmonline".to_string()));
+    assert_eq!(result.headings, vec!["Realm Online", "Download"]);
+    assert_eq!(
+      result.json_ld,
+      vec![JsonLdEntity {
+        entity_type: Some("VideoGame".to_string()),
+        name: Some("Realm Online".to_string()),
+        description: Some("Massively multiplayer fantasy game".to_string()),
+      }]
+    );
+    let body_text = result.body_text.unwrap();
+    assert!(body_text.contains("Build your guild and raid dungeons."));
+    assert!(!body_text.contains("hidden"));
+    assert_eq!(
+      result.outbound_link_domains,
+      vec!["discord.gg", "twitch.tv"]
+    );
+  }
+
+  #[test]
+  fn test_extract_metadata_respects_limits() {
+    let html = r#"
+      <html>
+        <head><meta name="keywords" content="one, two, three"></head>
+        <body>
+          <h1>First heading</h1>
+          <h1>Second heading</h1>
+          <p>Some visible text on the page.</p>
+          <a href="https://a.example/">A</a>
+          <a href="https://b.example/">B</a>
+        </body>
+      </html>
+    "#;
+    let limits = ExtractionLimits {
+      body_text_chars: 0,
+      max_headings: 1,
+      field_chars: 5,
+      max_json_ld: 0,
+      max_link_domains: 1,
+    };
+
+    let result =
+      extract_metadata_with_limits("example.com", html, 200, &limits).unwrap();
+
+    assert_eq!(result.keywords, Some("one, ".to_string()));
+    assert_eq!(result.headings, vec!["First"]);
+    assert_eq!(result.body_text, None);
+    assert_eq!(result.outbound_link_domains, vec!["a.example"]);
+  }
+
   #[test]
   fn test_extract_metadata_complex_title_with_entities() {
     let html = r#"
@@ -559,4 +1199,107 @@ mod tests {
 
     assert_eq!(result.title, Some("Test & Example - Site Name".to_string()));
   }
+
+  #[test]
+  fn test_has_page_content() {
+    let page = extract_metadata(
+      "game.com",
+      "<html><head><title>Game</title></head></html>",
+      200,
+    )
+    .unwrap();
+    assert!(page.has_page_content());
+
+    let empty = extract_metadata("api.game.com", "", 404).unwrap();
+    assert!(!empty.has_page_content());
+    let error_page = extract_metadata(
+      "api.game.com",
+      "<html><head><title>404 Not Found</title></head></html>",
+      404,
+    )
+    .unwrap();
+    assert!(!error_page.has_page_content());
+    assert!(
+      !SiteMetadata::from_fetch_error("api.game.com", "timeout")
+        .has_page_content()
+    );
+  }
+
+  #[test]
+  fn test_parent_site_fallback() {
+    let empty = extract_metadata("api.game.com", "", 404).unwrap();
+    assert_eq!(empty.parent_to_fetch(), Some("game.com".to_string()));
+    let page = extract_metadata(
+      "api.game.com",
+      "<html><head><title>Game API</title></head></html>",
+      200,
+    )
+    .unwrap();
+    assert_eq!(page.parent_to_fetch(), None);
+    let apex = extract_metadata("game.com", "", 404).unwrap();
+    assert_eq!(apex.parent_to_fetch(), None);
+
+    // Only a parent page with content is attached.
+    let parent = extract_metadata(
+      "game.com",
+      "<html><head><title>Game</title></head></html>",
+      200,
+    )
+    .unwrap();
+    let inherited = empty.clone().with_parent_site(Some(parent));
+    assert_eq!(
+      inherited.parent_site.and_then(|parent| parent.title),
+      Some("Game".to_string())
+    );
+    let empty_parent = extract_metadata("game.com", "", 404).unwrap();
+    assert!(
+      empty
+        .clone()
+        .with_parent_site(Some(empty_parent))
+        .parent_site
+        .is_none()
+    );
+    assert!(empty.with_parent_site(None).parent_site.is_none());
+  }
+
+  #[test]
+  fn test_parent_domain() {
+    assert_eq!(parent_domain("api.game.com"), Some("game.com".to_string()));
+    assert_eq!(
+      parent_domain("a.b.cdn.example.co.uk."),
+      Some("example.co.uk".to_string())
+    );
+    assert_eq!(parent_domain("game.com"), None);
+    assert_eq!(parent_domain("127.0.0.1"), None);
+    assert_eq!(parent_domain("http://127.0.0.1:8080/"), None);
+  }
+
+  #[test]
+  fn test_happy_eyeballs_order() {
+    let ips = [
+      "192.0.2.1",
+      "192.0.2.2",
+      "not-an-ip",
+      "2001:db8::1",
+      "192.0.2.1",
+      "2001:db8::2",
+      "192.0.2.3",
+    ]
+    .map(String::from);
+    let ordered: Vec<String> = happy_eyeballs_order("example.com", &ips)
+      .iter()
+      .map(IpAddr::to_string)
+      .collect();
+    assert_eq!(
+      ordered,
+      vec![
+        "2001:db8::1",
+        "192.0.2.1",
+        "2001:db8::2",
+        "192.0.2.2",
+        "192.0.2.3"
+      ]
+    );
+    assert!(happy_eyeballs_order("example.com", &[]).is_empty());
+  }
 }
@@ -1,11 +1,20 @@
 use dns_smart_block_classifier::{
-  classify_with_llm, output::Classification, web_classify::SiteMetadata,
   OllamaResponse,
+  backend::{BackendKind, LlmBackend},
+  classify_with_llm, classify_with_result_cache, compute_prompt_hash,
+  compute_result_key,
+  egress::EgressPolicy,
+  error::{ClassifierError, ClassifierErrorType},
+  output::{Classification, LlmResult},
+  read_cached_results,
+  schema::{classification_schema, strict_schema},
+  web_classify::SiteMetadata,
 };
 use serde_json::json;
+use std::time::Duration;
 use wiremock::{
-  matchers::{body_partial_json, method, path},
   Mock, MockServer, ResponseTemplate,
+  matchers::{body_partial_json, header, method, path},
 };
 
 /// Sample HTML content for a gaming site (Steam-like)
@@ -49,6 +58,11 @@ Respond ONLY with valid JSON in this exact format:
 }
 "#;
 
+/// An Ollama generate backend at `url`.
+fn ollama_backend(url: &str) -> LlmBackend {
+  LlmBackend::new(BackendKind::OllamaGenerate, url, None)
+}
+
 /// Helper function to create a SiteMetadata for a gaming site
 fn create_gaming_site_metadata() -> SiteMetadata {
   SiteMetadata {
@@ -67,6 +81,7 @@ fn create_gaming_site_metadata() -> SiteMetadata {
         language: Some("en".to_string()),
         http_status: 200,
         fetch_error: None,
+        ..Default::default()
     }
 }
 
@@ -95,7 +110,7 @@ async fn test_classify_gaming_site_with_mock_ollama() {
   Mock::given(method("POST"))
     .and(path("/api/generate"))
     .and(body_partial_json(json!({
-        "format": "json",
+        "format": classification_schema(),
         "stream": false
     })))
     .respond_with(ResponseTemplate::new(200).set_body_json(&ollama_response))
@@ -108,7 +123,7 @@ async fn test_classify_gaming_site_with_mock_ollama() {
   // Call the classification function
   let result = classify_with_llm(
     &metadata,
-    &mock_server.uri(),
+    &ollama_backend(&mock_server.uri()),
     "test-model",
     GAMING_PROMPT_TEMPLATE,
   )
@@ -121,6 +136,114 @@ async fn test_classify_gaming_site_with_mock_ollama() {
   assert!(result.confidence > 0.9);
 }
 
+#[tokio::test]
+async fn test_result_cache_reuses_answer_for_same_key() {
+  let mock_server = MockServer::start().await;
+  let ollama_response = OllamaResponse {
+    response: json!({
+        "is_matching_site": true,
+        "confidence": 0.9,
+        "reasoning": "Game store"
+    })
+    .to_string(),
+  };
+  Mock::given(method("POST"))
+    .and(path("/api/generate"))
+    .respond_with(ResponseTemplate::new(200).set_body_json(&ollama_response))
+    .expect(1)
+    .mount(&mock_server)
+    .await;
+
+  let backend = ollama_backend(&mock_server.uri());
+  let metadata = create_gaming_site_metadata();
+  let key = compute_result_key(
+    &metadata,
+    &compute_prompt_hash(GAMING_PROMPT_TEMPLATE),
+    BackendKind::OllamaGenerate,
+    "test-model",
+  )
+  .unwrap();
+
+  // A miss asks the model and reports the key of its answer.
+  let answered = classify_with_result_cache(
+    &metadata,
+    &backend,
+    "test-model",
+    GAMING_PROMPT_TEMPLATE,
+    &[],
+  )
+  .await
+  .expect("Classification should succeed");
+  assert_eq!(answered.key, key);
+  assert!(!answered.reused);
+  assert!(answered.classification.is_matching_site);
+
+  // The same evidence, prompt and model reuse it without a request.
+  let reused = classify_with_result_cache(
+    &metadata,
+    &backend,
+    "test-model",
+    GAMING_PROMPT_TEMPLATE,
+    std::slice::from_ref(&answered),
+  )
+  .await
+  .expect("Cached answer should be reused");
+  assert_eq!(
+    reused,
+    LlmResult {
+      reused: true,
+      ..answered.clone()
+    }
+  );
+
+  // Changed evidence or another model does not match the key.
+  let mut changed = metadata.clone();
+  changed.title = Some("Awesome Game Store - Now Selling Laptops".to_string());
+  for (metadata, model) in [(&changed, "test-model"), (&metadata, "other")] {
+    assert_ne!(
+      compute_result_key(
+        metadata,
+        &compute_prompt_hash(GAMING_PROMPT_TEMPLATE),
+        BackendKind::OllamaGenerate,
+        model,
+      )
+      .unwrap(),
+      key
+    );
+  }
+}
+
+#[test]
+fn test_read_cached_results() {
+  let answer = LlmResult {
+    key: "sha256:abc".to_string(),
+    model: "test-model".to_string(),
+    classification: Classification {
+      is_matching_site: true,
+      confidence: 0.9,
+      reasoning: "Game store".to_string(),
+    },
+    reused: false,
+  };
+  let dir = tempfile::tempdir().unwrap();
+  let path = dir.path().join("cached.jsonl");
+  std::fs::write(
+    &path,
+    format!("{}\n\n", serde_json::to_string(&answer).unwrap()),
+  )
+  .unwrap();
+  assert_eq!(read_cached_results(&path).unwrap(), vec![answer]);
+
+  std::fs::write(&path, "not json\n").unwrap();
+  for path in [path, dir.path().join("missing.jsonl")] {
+    let error = read_cached_results(&path).unwrap_err();
+    assert_eq!(
+      error.to_error_type(),
+      ClassifierErrorType::CachedResultsReadError
+    );
+  }
+}
+
 #[tokio::test]
 async fn test_classify_non_gaming_site_with_mock_ollama() {
   // Set up the mock Ollama server
@@ -142,6 +265,7 @@ async fn test_classify_non_gaming_site_with_mock_ollama() {
     language: Some("en".to_string()),
     http_status: 200,
     fetch_error: None,
+    ..Default::default()
   };
 
   // Set up the mock response for a non-gaming site
@@ -163,7 +287,7 @@ async fn test_classify_non_gaming_site_with_mock_ollama() {
   // Call the classification function
   let result = classify_with_llm(
     &metadata,
-    &mock_server.uri(),
+    &ollama_backend(&mock_server.uri()),
     "test-model",
     GAMING_PROMPT_TEMPLATE,
   )
@@ -191,7 +315,7 @@ async fn test_ollama_api_error_handling() {
   // Call the classification function and expect an error
   let result = classify_with_llm(
     &metadata,
-    &mock_server.uri(),
+    &ollama_backend(&mock_server.uri()),
     "test-model",
     GAMING_PROMPT_TEMPLATE,
   )
@@ -200,6 +324,458 @@ async fn test_ollama_api_error_handling() {
   assert!(result.is_err());
 }
 
+#[tokio::test]
+async fn test_overloaded_backend_errors_are_transient() {
+  let metadata = create_gaming_site_metadata();
+  for (status, expected) in [
+    (429, ClassifierErrorType::LlmApiUnavailableError),
+    (503, ClassifierErrorType::LlmApiUnavailableError),
+    (504, ClassifierErrorType::LlmApiUnavailableError),
+    (400, ClassifierErrorType::LlmApiError),
+    (500, ClassifierErrorType::LlmApiError),
+  ] {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("POST"))
+      .and(path("/api/generate"))
+      .respond_with(ResponseTemplate::new(status))
+      .mount(&mock_server)
+      .await;
+
+    let error = classify_with_llm(
+      &metadata,
+      &ollama_backend(&mock_server.uri()),
+      "test-model",
+      GAMING_PROMPT_TEMPLATE,
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(error.to_error_type(), expected, "status {}", status);
+  }
+
+  let mock_server = MockServer::start().await;
+  Mock::given(method("POST"))
+    .and(path("/api/generate"))
+    .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(5)))
+    .mount(&mock_server)
+    .await;
+  let error = classify_with_llm(
+    &metadata,
+    &ollama_backend(&mock_server.uri())
+      .with_timeout(Duration::from_millis(200)),
+    "test-model",
+    GAMING_PROMPT_TEMPLATE,
+  )
+  .await
+  .unwrap_err();
+  assert_eq!(
+    error.to_error_type(),
+    ClassifierErrorType::LlmApiTimeoutError
+  );
+}
+
+#[tokio::test]
+async fn test_classify_with_ollama_chat() {
+  let mock_server = MockServer::start().await;
+
+  Mock::given(method("POST"))
+    .and(path("/api/chat"))
+    .and(body_partial_json(json!({
+        "model": "test-model",
+        "format": classification_schema(),
+        "stream": false
+    })))
+    .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+        "model": "test-model",
+        "message": {
+            "role": "assistant",
+            "content": json!({
+                "is_matching_site": true,
+                "confidence": 0.9,
+                "reasoning": "A game store"
+            })
+            .to_string()
+        },
+        "done": true
+    })))
+    .mount(&mock_server)
+    .await;
+
+  let backend =
+    LlmBackend::new(BackendKind::OllamaChat, &mock_server.uri(), None);
+  let result = classify_with_llm(
+    &create_gaming_site_metadata(),
+    &backend,
+    "test-model",
+    GAMING_PROMPT_TEMPLATE,
+  )
+  .await
+  .expect("Classification should succeed");
+
+  assert!(result.is_matching_site);
+  assert_eq!(result.confidence, 0.9);
+}
+
+#[tokio::test]
+async fn test_classify_with_openai_chat() {
+  let mock_server = MockServer::start().await;
+
+  Mock::given(method("POST"))
+    .and(path("/v1/chat/completions"))
+    .and(header("authorization", "Bearer secret-key"))
+    .and(body_partial_json(json!({
+        "model": "test-model",
+        "response_format": {
+            "type": "json_schema",
+            "json_schema": {
+                "strict": true,
+                "schema": strict_schema(&classification_schema())
+            }
+        }
+    })))
+    .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+        "id": "chatcmpl-1",
+        "object": "chat.completion",
+        "choices": [{
+            "index": 0,
+            "message": {
+                "role": "assistant",
+                "content": json!({
+                    "is_matching_site": false,
+                    "confidence": 0.8,
+                    "reasoning": "Not a gaming site"
+                })
+                .to_string()
+            },
+            "finish_reason": "stop"
+        }]
+    })))
+    .mount(&mock_server)
+    .await;
+
+  let backend = LlmBackend::new(
+    BackendKind::OpenaiChat,
+    &mock_server.uri(),
+    Some("secret-key".to_string()),
+  );
+  let result = classify_with_llm(
+    &create_gaming_site_metadata(),
+    &backend,
+    "test-model",
+    GAMING_PROMPT_TEMPLATE,
+  )
+  .await
+  .expect("Classification should succeed");
+
+  assert!(!result.is_matching_site);
+  assert_eq!(result.confidence, 0.8);
+
+  // Strict mode rejects minLength, so it is left out of the wire schema.
+  let requests = mock_server.received_requests().await.unwrap();
+  let body: serde_json::Value = requests[0].body_json().unwrap();
+  assert!(
+    !body["response_format"]["json_schema"]["schema"]
+      .to_string()
+      .contains("minLength")
+  );
+
+  // Without the key the mock does not match and the request fails.
+  let anonymous =
+    LlmBackend::new(BackendKind::OpenaiChat, &mock_server.uri(), None);
+  assert!(
+    classify_with_llm(
+      &create_gaming_site_metadata(),
+      &anonymous,
+      "test-model",
+      GAMING_PROMPT_TEMPLATE,
+    )
+    .await
+    .is_err()
+  );
+}
+
+#[tokio::test]
+async fn test_openai_refusal_is_repaired() {
+  let mock_server = MockServer::start().await;
+
+  // A refusal carries no content; it is quoted back like a bad answer.
+  Mock::given(method("POST"))
+    .and(path("/v1/chat/completions"))
+    .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+        "choices": [{
+            "index": 0,
+            "message": {
+                "role": "assistant",
+                "content": null,
+                "refusal": "I can't help with that."
+            },
+            "finish_reason": "stop"
+        }]
+    })))
+    .up_to_n_times(1)
+    .with_priority(1)
+    .mount(&mock_server)
+    .await;
+  Mock::given(method("POST"))
+    .and(path("/v1/chat/completions"))
+    .and(body_partial_json(json!({
+        "messages": [
+            {},
+            {
+                "role": "assistant",
+                "content": "I can't help with that."
+            },
+            { "role": "user" }
+        ]
+    })))
+    .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+        "choices": [{
+            "index": 0,
+            "message": {
+                "role": "assistant",
+                "content": json!({
+                    "is_matching_site": true,
+                    "confidence": 0.9,
+                    "reasoning": "A game store"
+                })
+                .to_string()
+            },
+            "finish_reason": "stop"
+        }]
+    })))
+    .expect(1)
+    .mount(&mock_server)
+    .await;
+
+  let backend =
+    LlmBackend::new(BackendKind::OpenaiChat, &mock_server.uri(), None);
+  let result = classify_with_llm(
+    &create_gaming_site_metadata(),
+    &backend,
+    "test-model",
+    GAMING_PROMPT_TEMPLATE,
+  )
+  .await
+  .expect("Repaired classification should succeed");
+
+  assert_eq!(result.confidence, 0.9);
+}
+
+#[tokio::test]
+async fn test_openai_refusal_and_undecodable_body_errors() {
+  let mock_server = MockServer::start().await;
+  Mock::given(method("POST"))
+    .and(path("/v1/chat/completions"))
+    .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+        "choices": [{
+            "message": {
+                "role": "assistant",
+                "content": null,
+                "refusal": "I can't help with that."
+            }
+        }]
+    })))
+    .expect(2)
+    .mount(&mock_server)
+    .await;
+
+  let backend =
+    LlmBackend::new(BackendKind::OpenaiChat, &mock_server.uri(), None);
+  let error = classify_with_llm(
+    &create_gaming_site_metadata(),
+    &backend,
+    "test-model",
+    GAMING_PROMPT_TEMPLATE,
+  )
+  .await
+  .unwrap_err();
+  assert_eq!(error.to_error_type(), ClassifierErrorType::ModelRefusal);
+
+  // A truncated body is a bad answer, not a failed domain fetch.
+  let mock_server = MockServer::start().await;
+  Mock::given(method("POST"))
+    .and(path("/v1/chat/completions"))
+    .respond_with(
+      ResponseTemplate::new(200)
+        .set_body_raw(r#"{"choices":[{"message":{"#, "application/json"),
+    )
+    .mount(&mock_server)
+    .await;
+
+  let backend =
+    LlmBackend::new(BackendKind::OpenaiChat, &mock_server.uri(), None);
+  let error = classify_with_llm(
+    &create_gaming_site_metadata(),
+    &backend,
+    "test-model",
+    GAMING_PROMPT_TEMPLATE,
+  )
+  .await
+  .unwrap_err();
+  assert!(matches!(error, ClassifierError::LlmResponseDecode(_)));
+  assert_eq!(
+    error.to_error_type(),
+    ClassifierErrorType::LlmResponseParseError
+  );
+}
+
+#[tokio::test]
+async fn test_openai_health_check_lists_models() {
+  let mock_server = MockServer::start().await;
+
+  Mock::given(method("GET"))
+    .and(path("/v1/models"))
+    .and(header("authorization", "Bearer secret-key"))
+    .respond_with(
+      ResponseTemplate::new(200)
+        .set_body_json(json!({ "object": "list", "data": [] })),
+    )
+    .mount(&mock_server)
+    .await;
+
+  let backend = LlmBackend::new(
+    BackendKind::OpenaiChat,
+    &mock_server.uri(),
+    Some("secret-key".to_string()),
+  );
+  assert!(backend.check_health(Duration::from_secs(5)).await.is_ok());
+}
+
+#[tokio::test]
+async fn test_invalid_answer_is_repaired_once() {
+  let mock_server = MockServer::start().await;
+
+  // The first answer is out of range; the re-prompt quotes the problem.
+  Mock::given(method("POST"))
+    .and(path("/api/chat"))
+    .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+        "message": {
+            "role": "assistant",
+            "content": json!({
+                "is_matching_site": true,
+                "confidence": 95,
+                "reasoning": "A game store"
+            })
+            .to_string()
+        }
+    })))
+    .up_to_n_times(1)
+    .with_priority(1)
+    .mount(&mock_server)
+    .await;
+  Mock::given(method("POST"))
+    .and(path("/api/chat"))
+    .and(body_partial_json(json!({
+        "messages": [
+            {},
+            { "role": "assistant" },
+            { "role": "user" }
+        ]
+    })))
+    .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+        "message": {
+            "role": "assistant",
+            "content": json!({
+                "is_matching_site": true,
+                "confidence": 0.95,
+                "reasoning": "A game store"
+            })
+            .to_string()
+        }
+    })))
+    .expect(1)
+    .mount(&mock_server)
+    .await;
+
+  let backend =
+    LlmBackend::new(BackendKind::OllamaChat, &mock_server.uri(), None);
+  let result = classify_with_llm(
+    &create_gaming_site_metadata(),
+    &backend,
+    "test-model",
+    GAMING_PROMPT_TEMPLATE,
+  )
+  .await
+  .expect("Repaired classification should succeed");
+
+  assert_eq!(result.confidence, 0.95);
+}
+
+#[tokio::test]
+async fn test_invalid_answer_fails_after_repair() {
+  let mock_server = MockServer::start().await;
+
+  let ollama_response = OllamaResponse {
+    response: json!({
+        "is_matching_site": true,
+        "confidence": 0.9,
+        "reasoning": ""
+    })
+    .to_string(),
+  };
+  Mock::given(method("POST"))
+    .and(path("/api/generate"))
+    .respond_with(ResponseTemplate::new(200).set_body_json(&ollama_response))
+    .expect(2)
+    .mount(&mock_server)
+    .await;
+
+  let error = classify_with_llm(
+    &create_gaming_site_metadata(),
+    &ollama_backend(&mock_server.uri()),
+    "test-model",
+    GAMING_PROMPT_TEMPLATE,
+  )
+  .await
+  .unwrap_err();
+
+  assert!(matches!(error, ClassifierError::InvalidClassification(_)));
+  assert_eq!(error.to_error_type(), ClassifierErrorType::EmptyReasoning);
+}
+
+#[tokio::test]
+async fn test_ollama_health_check() {
+  let mock_server = MockServer::start().await;
+
+  Mock::given(method("GET"))
+    .and(path("/api/tags"))
+    .respond_with(
+      ResponseTemplate::new(200).set_body_json(json!({ "models": [] })),
+    )
+    .mount(&mock_server)
+    .await;
+
+  assert!(
+    ollama_backend(&mock_server.uri())
+      .check_health(Duration::from_secs(5))
+      .await
+      .is_ok()
+  );
+
+  // A server that answers with an error status is not healthy.
+  mock_server.reset().await;
+  Mock::given(method("GET"))
+    .and(path("/api/tags"))
+    .respond_with(ResponseTemplate::new(503))
+    .mount(&mock_server)
+    .await;
+  assert!(
+    ollama_backend(&mock_server.uri())
+      .check_health(Duration::from_secs(5))
+      .await
+      .is_err()
+  );
+
+  // Nor is one that cannot be reached at all.
+  let unreachable = mock_server.uri();
+  drop(mock_server);
+  assert!(
+    ollama_backend(&unreachable)
+      .check_health(Duration::from_secs(5))
+      .await
+      .is_err()
+  );
+}
+
 #[test]
 fn test_gaming_site_html_parsing() {
   use dns_smart_block_classifier::web_classify::extract_metadata;
@@ -210,11 +786,408 @@ fn test_gaming_site_html_parsing() {
 
   assert_eq!(metadata.domain, "awesomegames.example");
   assert!(metadata.title.is_some());
-  assert!(metadata
-    .title
-    .as_ref()
-    .unwrap()
-    .contains("Awesome Game Store"));
+  assert!(
+    metadata
+      .title
+      .as_ref()
+      .unwrap()
+      .contains("Awesome Game Store")
+  );
   assert!(metadata.description.is_some());
   assert_eq!(metadata.http_status, 200);
 }
+
+/// Egress policy letting fetches reach the local test servers.
+fn loopback_allowed() -> EgressPolicy {
+  EgressPolicy {
+    allow: vec!["127.0.0.0/8".parse().unwrap(), "::1/128".parse().unwrap()],
+  }
+}
+
+#[tokio::test]
+async fn test_fetch_records_redirect_chain() {
+  use dns_smart_block_classifier::web_classify::{RedirectHop, fetch_domain};
+
+  let mock_server = MockServer::start().await;
+  Mock::given(method("GET"))
+    .and(path("/"))
+    .respond_with(
+      ResponseTemplate::new(301).insert_header("Location", "/parked"),
+    )
+    .mount(&mock_server)
+    .await;
+  Mock::given(method("GET"))
+    .and(path("/parked"))
+    .respond_with(
+      ResponseTemplate::new(302).insert_header("Location", "/store"),
+    )
+    .mount(&mock_server)
+    .await;
+  Mock::given(method("GET"))
+    .and(path("/store"))
+    .respond_with(ResponseTemplate::new(200).set_body_string(GAMING_SITE_HTML))
+    .mount(&mock_server)
+    .await;
+
+  let page = fetch_domain(
+    &format!("{}/", mock_server.uri()),
+    5,
+    200,
+    &[],
+    &loopback_allowed(),
+    None,
+  )
+  .await
+  .expect("Fetch should succeed");
+
+  assert_eq!(page.status, 200);
+  assert_eq!(page.final_url, format!("{}/store", mock_server.uri()));
+  assert_eq!(page.final_host(), Some("127.0.0.1".to_string()));
+  assert_eq!(
+    page.redirects,
+    vec![
+      RedirectHop {
+        url: format!("{}/", mock_server.uri()),
+        status: 301,
+      },
+      RedirectHop {
+        url: format!("{}/parked", mock_server.uri()),
+        status: 302,
+      },
+    ]
+  );
+}
+
+/// Serve TLS on a local port with a self-signed certificate for
+/// `*.riotgames.com`.  With `respond`, every connection gets a small HTML
+/// page; without, connections are closed right after the handshake.
+async fn start_tls_server(respond: bool) -> u16 {
+  use std::sync::Arc;
+  use tokio::io::{AsyncReadExt, AsyncWriteExt};
+  use tokio_rustls::TlsAcceptor;
+  use tokio_rustls::rustls::ServerConfig;
+  use tokio_rustls::rustls::crypto::aws_lc_rs;
+  use tokio_rustls::rustls::pki_types::PrivatePkcs8KeyDer;
+
+  let mut params =
+    rcgen::CertificateParams::new(vec!["*.riotgames.com".to_string()]).unwrap();
+  params
+    .distinguished_name
+    .push(rcgen::DnType::CommonName, "*.riotgames.com");
+  let key = rcgen::KeyPair::generate().unwrap();
+  let cert = params.self_signed(&key).unwrap();
+  let config = ServerConfig::builder_with_provider(Arc::new(
+    aws_lc_rs::default_provider(),
+  ))
+  .with_safe_default_protocol_versions()
+  .unwrap()
+  .with_no_client_auth()
+  .with_single_cert(
+    vec![cert.der().clone()],
+    PrivatePkcs8KeyDer::from(key.serialize_der()).into(),
+  )
+  .unwrap();
+  let acceptor = TlsAcceptor::from(Arc::new(config));
+
+  let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let port = listener.local_addr().unwrap().port();
+  tokio::spawn(async move {
+    while let Ok((stream, _)) = listener.accept().await {
+      let acceptor = acceptor.clone();
+      tokio::spawn(async move {
+        let Ok(mut tls) = acceptor.accept(stream).await else {
+          return;
+        };
+        if respond {
+          let mut request = [0u8; 4096];
+          let _ = tls.read(&mut request).await;
+          let body = "<html><head><title>API</title></head></html>";
+          let response = format!(
+            "HTTP/1.1 404 Not Found\r\nContent-Type: text/html\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+          );
+          let _ = tls.write_all(response.as_bytes()).await;
+        }
+        let _ = tls.shutdown().await;
+      });
+    }
+  });
+  port
+}
+
+#[tokio::test]
+async fn test_fetch_records_tls_certificate() {
+  use dns_smart_block_classifier::web_classify::fetch_domain;
+
+  let port = start_tls_server(true).await;
+  let page = fetch_domain(
+    &format!("https://localhost:{}/", port),
+    5,
+    200,
+    &[],
+    &loopback_allowed(),
+    None,
+  )
+  .await
+  .expect("Fetch should succeed");
+
+  assert_eq!(page.status, 404);
+  let certificate = page.certificate.expect("Certificate should be recorded");
+  assert!(certificate.subject.contains("CN=*.riotgames.com"));
+  assert_eq!(certificate.subject_alt_names, vec!["*.riotgames.com"]);
+}
+
+#[tokio::test]
+async fn test_probe_certificate_after_failed_fetch() {
+  use dns_smart_block_classifier::certificate::probe_certificate;
+  use dns_smart_block_classifier::web_classify::fetch_domain;
+
+  let port = start_tls_server(false).await;
+  let url = format!("https://prod.api.pvp.net:{}/", port);
+  let egress = loopback_allowed();
+
+  // The server hangs up after the handshake, so there is no page to read...
+  assert!(
+    fetch_domain(&url, 2, 200, &["127.0.0.1".to_string()], &egress, None)
+      .await
+      .is_err()
+  );
+
+  // ...but its certificate can still be read.
+  let certificate =
+    probe_certificate(&url, 2, &["127.0.0.1".to_string()], &egress)
+      .await
+      .expect("Certificate should be read from the handshake");
+  assert!(certificate.subject.contains("CN=*.riotgames.com"));
+  assert_eq!(certificate.subject_alt_names, vec!["*.riotgames.com"]);
+
+  assert_eq!(
+    probe_certificate(&format!("http://localhost:{}/", port), 2, &[], &egress)
+      .await,
+    None
+  );
+}
+
+#[tokio::test]
+async fn test_fetch_decodes_charset_and_caps_size() {
+  use dns_smart_block_classifier::web_classify::{
+    extract_metadata, fetch_domain,
+  };
+  use encoding_rs::SHIFT_JIS;
+
+  let mock_server = MockServer::start().await;
+  let html = format!(
+    "<html><head><title>ゲームストア</title></head><body>{}</body></html>",
+    "あ".repeat(4096)
+  );
+  let (body, _, _) = SHIFT_JIS.encode(&html);
+  Mock::given(method("GET"))
+    .and(path("/"))
+    .respond_with(
+      ResponseTemplate::new(200)
+        .insert_header("Content-Type", "text/html; charset=Shift_JIS")
+        .set_body_bytes(body.into_owned()),
+    )
+    .mount(&mock_server)
+    .await;
+
+  let page = fetch_domain(
+    &format!("{}/", mock_server.uri()),
+    5,
+    1,
+    &[],
+    &loopback_allowed(),
+    None,
+  )
+  .await
+  .expect("Fetch should succeed");
+
+  assert_eq!(page.encoding, "Shift_JIS");
+  // 1 KB of two-byte characters, cut off after the title.
+  assert!(page.body.chars().count() <= 1024);
+  assert!(!page.body.contains("</html>"));
+  let metadata = extract_metadata("example.jp", &page.body, page.status)
+    .expect("Extraction should succeed");
+  assert_eq!(metadata.title, Some("ゲームストア".to_string()));
+}
+
+#[tokio::test]
+async fn test_fetch_falls_back_to_http_on_resolved_ips() {
+  use dns_smart_block_classifier::web_classify::fetch_domain;
+
+  // A plain HTTP server fails the TLS handshake, so the fetch falls back to
+  // HTTP.  The domain does not exist; both schemes connect to the
+  // pre-resolved addresses, IPv6 first, and only the IPv4 one answers.
+  let mock_server = MockServer::start().await;
+  Mock::given(method("GET"))
+    .and(path("/"))
+    .respond_with(ResponseTemplate::new(200).set_body_string(GAMING_SITE_HTML))
+    .mount(&mock_server)
+    .await;
+  let port = mock_server.address().port();
+
+  let page = fetch_domain(
+    &format!("fallback.invalid:{}", port),
+    5,
+    200,
+    &["127.0.0.1".to_string(), "::1".to_string()],
+    &loopback_allowed(),
+    None,
+  )
+  .await
+  .expect("Fetch should fall back to HTTP");
+
+  assert_eq!(page.status, 200);
+  assert_eq!(page.scheme, "http");
+  assert_eq!(
+    page.remote_addr,
+    Some(std::net::SocketAddr::from(([127, 0, 0, 1], port)))
+  );
+  assert!(page.body.contains("Awesome Game Store"));
+}
+
+#[tokio::test]
+async fn test_fetch_refuses_internal_addresses() {
+  use dns_smart_block_classifier::egress::EgressBlocked;
+  use dns_smart_block_classifier::web_classify::fetch_domain;
+  use std::net::IpAddr;
+
+  let mock_server = MockServer::start().await;
+  Mock::given(method("GET"))
+    .and(path("/"))
+    .respond_with(ResponseTemplate::new(200).set_body_string(GAMING_SITE_HTML))
+    .mount(&mock_server)
+    .await;
+  Mock::given(method("GET"))
+    .and(path("/metadata"))
+    .respond_with(
+      ResponseTemplate::new(302)
+        .insert_header("Location", "http://169.254.169.254/latest/"),
+    )
+    .mount(&mock_server)
+    .await;
+  let url = format!("{}/", mock_server.uri());
+  let loopback: IpAddr = "127.0.0.1".parse().unwrap();
+  let default = EgressPolicy::default();
+
+  // An IP literal, and a domain pre-resolved to one.
+  let e = fetch_domain(&url, 5, 200, &[], &default, None)
+    .await
+    .unwrap_err();
+  assert_eq!(e.to_error_type(), ClassifierErrorType::EgressBlocked);
+  let e = fetch_domain(
+    &format!("internal.invalid:{}", mock_server.address().port()),
+    5,
+    200,
+    &["127.0.0.1".to_string()],
+    &default,
+    None,
+  )
+  .await
+  .unwrap_err();
+  assert!(
+    matches!(
+      e,
+      ClassifierError::EgressBlocked(EgressBlocked { ip, .. }) if ip == loopback
+    ),
+    "{}",
+    e
+  );
+  // A name that resolves to one.
+  let e = fetch_domain(
+    &format!("http://localhost:{}/", mock_server.address().port()),
+    5,
+    200,
+    &[],
+    &default,
+    None,
+  )
+  .await
+  .unwrap_err();
+  assert_eq!(e.to_error_type(), ClassifierErrorType::EgressBlocked);
+
+  // Allowing the server's address does not allow where it redirects to.
+  let e = fetch_domain(
+    &format!("{}/metadata", mock_server.uri()),
+    5,
+    200,
+    &[],
+    &loopback_allowed(),
+    None,
+  )
+  .await
+  .unwrap_err();
+  assert_eq!(
+    e.to_string(),
+    "Fetch blocked: address 169.254.169.254 of 169.254.169.254 is blocked by \
+     the egress policy"
+  );
+
+  let page = fetch_domain(&url, 5, 200, &[], &loopback_allowed(), None)
+    .await
+    .expect("Allowlisted fetch should succeed");
+  assert_eq!(page.status, 200);
+}
+
+#[tokio::test]
+async fn test_fetch_through_proxy() {
+  use dns_smart_block_classifier::proxy::ProxyConfig;
+  use dns_smart_block_classifier::web_classify::fetch_domain;
+  use std::io::Write;
+
+  // The mock server plays the proxy: a plain HTTP proxy is sent the
+  // absolute URL, and the domain does not exist, so only the proxy could
+  // have resolved it.
+  let proxy_server = MockServer::start().await;
+  Mock::given(method("GET"))
+    .and(path("/"))
+    .and(header(
+      "Proxy-Authorization",
+      "Basic aG91c2Vob2xkOnMzY3JldA==",
+    ))
+    .respond_with(ResponseTemplate::new(200).set_body_string(GAMING_SITE_HTML))
+    .expect(1)
+    .mount(&proxy_server)
+    .await;
+  let mut credentials = tempfile::NamedTempFile::new().unwrap();
+  writeln!(credentials, "household:s3cret").unwrap();
+  let proxy = ProxyConfig {
+    url: proxy_server.uri(),
+    credentials_file: Some(credentials.path().to_path_buf()),
+  }
+  .proxy()
+  .unwrap();
+
+  // The proxy itself is on loopback, which the default policy allows since
+  // it is configured rather than requested; pre-resolved IPs are not pinned.
+  let page = fetch_domain(
+    "http://gaming.invalid/",
+    5,
+    200,
+    &["192.0.2.1".to_string()],
+    &EgressPolicy::default(),
+    Some(&proxy),
+  )
+  .await
+  .expect("Fetch through the proxy should succeed");
+
+  assert_eq!(page.status, 200);
+  assert_eq!(page.final_url, "http://gaming.invalid/");
+  assert!(page.body.contains("Awesome Game Store"));
+
+  // They are still checked: a domain known only by internal addresses is
+  // refused without asking the proxy, which saw exactly one request.
+  let e = fetch_domain(
+    "http://gaming.invalid/",
+    5,
+    200,
+    &["10.0.0.5".to_string(), "::1".to_string()],
+    &EgressPolicy::default(),
+    Some(&proxy),
+  )
+  .await
+  .unwrap_err();
+  assert_eq!(e.to_error_type(), ClassifierErrorType::EgressBlocked);
+}
@@ -1,8 +1,9 @@
 use dns_smart_block_classifier::compute_prompt_hash;
+use dns_smart_block_classifier::ensemble::{EnsembleStrategy, ModelVote};
 use dns_smart_block_classifier::error::ClassifierErrorType;
 use dns_smart_block_classifier::output::{
-  Classification, ClassificationMetadata, ClassificationOutput, ErrorInfo,
-  ErrorOutput,
+  Classification, ClassificationMetadata, ClassificationOutput, EnsembleVotes,
+  ErrorInfo, ErrorOutput,
 };
 use serde_json;
 
@@ -20,7 +21,16 @@ fn test_classification_output_serialization() {
       model: "llama2".to_string(),
       prompt_hash: "sha256:abcd1234".to_string(),
       http_status: 200,
+      fetch_error: None,
+      final_host: None,
+      redirect_chain: vec![],
+      scheme: None,
+      remote_addr: None,
+      evidence_inherited_from: None,
+      llm_results: vec![],
     },
+    ensemble: None,
+    escalation: None,
   };
 
   let json = output.to_json().expect("Failed to serialize");
@@ -34,6 +44,70 @@ fn test_classification_output_serialization() {
   assert_eq!(parsed["metadata"]["model"], "llama2");
   assert_eq!(parsed["metadata"]["prompt_hash"], "sha256:abcd1234");
   assert_eq!(parsed["metadata"]["http_status"], 200);
+  assert!(parsed["metadata"].get("evidence_inherited_from").is_none());
+  assert!(parsed["metadata"].get("llm_results").is_none());
+
+  // Evidence taken from the parent domain's page is recorded.
+  let inherited = ClassificationOutput {
+    metadata: ClassificationMetadata {
+      evidence_inherited_from: Some("game.com".to_string()),
+      ..output.metadata
+    },
+    ..output
+  };
+  let parsed: serde_json::Value =
+    serde_json::from_str(&inherited.to_json().unwrap()).unwrap();
+  assert_eq!(parsed["metadata"]["evidence_inherited_from"], "game.com");
+}
+
+#[test]
+fn test_ensemble_votes_serialization() {
+  let output = ClassificationOutput {
+    domain: "gaming-site.com".to_string(),
+    result: "classified".to_string(),
+    classification: Classification {
+      is_matching_site: true,
+      confidence: 0.6,
+      reasoning: "2 of 3 models voted matching (majority)".to_string(),
+    },
+    metadata: ClassificationMetadata {
+      model: "a,b,c".to_string(),
+      prompt_hash: "sha256:abcd1234".to_string(),
+      http_status: 200,
+      fetch_error: None,
+      final_host: None,
+      redirect_chain: vec![],
+      scheme: None,
+      remote_addr: None,
+      evidence_inherited_from: None,
+      llm_results: vec![],
+    },
+    ensemble: Some(EnsembleVotes {
+      strategy: EnsembleStrategy::Majority,
+      votes: vec![ModelVote {
+        model: "a".to_string(),
+        is_matching_site: true,
+        confidence: 0.9,
+        reasoning: "Game wiki".to_string(),
+      }],
+    }),
+    escalation: None,
+  };
+
+  let json = output.to_json().expect("Failed to serialize");
+  let parsed: serde_json::Value =
+    serde_json::from_str(&json).expect("Invalid JSON");
+  assert_eq!(parsed["ensemble"]["strategy"], "majority");
+  assert_eq!(parsed["ensemble"]["votes"][0]["model"], "a");
+
+  // Single-model output omits the field entirely.
+  let single = ClassificationOutput {
+    ensemble: None,
+    ..output
+  };
+  let parsed: serde_json::Value =
+    serde_json::from_str(&single.to_json().unwrap()).unwrap();
+  assert!(parsed.get("ensemble").is_none());
 }
 
 #[test]
@@ -42,7 +116,7 @@ fn test_error_output_serialization() {
     domain: "error-domain.com".to_string(),
     result: "error".to_string(),
     error: ErrorInfo {
-      error_type: ClassifierErrorType::OllamaApiTimeoutError,
+      error_type: ClassifierErrorType::LlmApiTimeoutError,
       message: "Connection timed out after 30s".to_string(),
     },
     metadata: None,
@@ -54,7 +128,7 @@ fn test_error_output_serialization() {
 
   assert_eq!(parsed["domain"], "error-domain.com");
   assert_eq!(parsed["result"], "error");
-  assert_eq!(parsed["error"]["error_type"], "OllamaApiTimeoutError");
+  assert_eq!(parsed["error"]["error_type"], "LlmApiTimeoutError");
   assert_eq!(parsed["error"]["message"], "Connection timed out after 30s");
 }
 
@@ -115,6 +189,7 @@ fn test_classification_output_deserialization() {
 
 #[test]
 fn test_error_output_deserialization() {
+  // Written by a classifier from before the LLM error types were renamed.
   let json = r#"{
         "domain": "error-site.com",
         "result": "error",
@@ -129,7 +204,7 @@ fn test_error_output_deserialization() {
 
   assert_eq!(output.domain, "error-site.com");
   assert_eq!(output.result, "error");
-  assert_eq!(output.error.error_type, ClassifierErrorType::OllamaApiError);
+  assert_eq!(output.error.error_type, ClassifierErrorType::LlmApiError);
   assert_eq!(output.error.message, "Model not found");
   assert!(output.metadata.is_none());
 }
@@ -141,12 +216,21 @@ fn test_all_error_types_serialize() {
     ClassifierErrorType::DomainFetchError,
     ClassifierErrorType::DomainFetchTimeoutError,
     ClassifierErrorType::HtmlParseError,
-    ClassifierErrorType::OllamaApiConnectionError,
-    ClassifierErrorType::OllamaApiTimeoutError,
-    ClassifierErrorType::OllamaApiError,
-    ClassifierErrorType::OllamaResponseParseError,
+    ClassifierErrorType::LlmApiConnectionError,
+    ClassifierErrorType::LlmApiTimeoutError,
+    ClassifierErrorType::LlmApiUnavailableError,
+    ClassifierErrorType::LlmApiError,
+    ClassifierErrorType::LlmResponseParseError,
     ClassifierErrorType::ClassificationParseError,
+    ClassifierErrorType::ClassificationSchemaError,
+    ClassifierErrorType::ConfidenceOutOfRange,
+    ClassifierErrorType::EmptyReasoning,
+    ClassifierErrorType::ModelRefusal,
     ClassifierErrorType::MetadataSerializationError,
+    ClassifierErrorType::ApiKeyFileReadError,
+    ClassifierErrorType::EgressBlocked,
+    ClassifierErrorType::ProxyConfigError,
+    ClassifierErrorType::CachedResultsReadError,
   ];
 
   for error_type in error_types {
@@ -211,7 +295,16 @@ fn test_confidence_values() {
         model: "test".to_string(),
         prompt_hash: "sha256:test".to_string(),
         http_status: 200,
+        fetch_error: None,
+        final_host: None,
+        redirect_chain: vec![],
+        scheme: None,
+        remote_addr: None,
+        evidence_inherited_from: None,
+        llm_results: vec![],
       },
+      ensemble: None,
+      escalation: None,
     };
 
     let json = output.to_json().expect("Failed to serialize");
@@ -239,7 +332,16 @@ fn test_http_status_codes() {
         model: "test".to_string(),
         prompt_hash: "sha256:test".to_string(),
         http_status: status,
+        fetch_error: None,
+        final_host: None,
+        redirect_chain: vec![],
+        scheme: None,
+        remote_addr: None,
+        evidence_inherited_from: None,
+        llm_results: vec![],
       },
+      ensemble: None,
+      escalation: None,
     };
 
     let json = output.to_json().expect("Failed to serialize");
@@ -16,7 +16,9 @@
 //! - Set OLLAMA_URL environment variable if not using default
 
 use dns_smart_block_classifier::{
-  classify_with_llm, web_classify::SiteMetadata,
+  backend::{BackendKind, LlmBackend},
+  classify_with_llm,
+  web_classify::SiteMetadata,
 };
 use std::env;
 
@@ -110,6 +112,7 @@ fn create_gaming_site_metadata() -> SiteMetadata {
         language: Some("en".to_string()),
         http_status: 200,
         fetch_error: None,
+        ..Default::default()
     }
 }
 
@@ -131,6 +134,7 @@ fn create_non_gaming_site_metadata() -> SiteMetadata {
         language: Some("en".to_string()),
         http_status: 200,
         fetch_error: None,
+        ..Default::default()
     }
 }
 
@@ -149,10 +153,11 @@ async fn test_model_classification(
   println!("Domain: {}", metadata.domain);
   println!("Expected is_matching_site: {}", expected_is_matching);
 
+  let backend = LlmBackend::new(BackendKind::OllamaGenerate, &ollama_url, None);
   let start = std::time::Instant::now();
 
   let result =
-    classify_with_llm(metadata, &ollama_url, model, GAMING_PROMPT_TEMPLATE)
+    classify_with_llm(metadata, &backend, model, GAMING_PROMPT_TEMPLATE)
       .await?;
 
   let duration = start.elapsed();
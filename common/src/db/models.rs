@@ -353,6 +353,63 @@ impl ClassifierState {
 
     Ok(states)
   }
+
+  /// Return the state of each shadow classification type for a domain.
+  /// Shadow results are only recorded as events, so a shadow type is
+  /// `Current` when its latest event is a `classified` event newer than the
+  /// matching entry of `classified_since`.
+  pub async fn shadow_states(
+    pool: &PgPool,
+    domain: &str,
+    shadow_types: &[String],
+    classified_since: &[DateTime<Utc>],
+  ) -> Result<Vec<(String, ClassifierState)>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, (String, Option<String>, Option<bool>)>(
+      r#"
+      WITH shadow_types AS (
+          SELECT *
+          FROM unnest($2::text[], $3::timestamptz[])
+              AS t(classification_type, classified_since)
+      ),
+      latest_events AS (
+          SELECT DISTINCT ON (action_data->>'classification_type')
+              action_data->>'classification_type' AS classification_type,
+              action::text AS action,
+              created_at
+          FROM domain_classification_events
+          WHERE domain = $1
+            AND action_data->>'classification_type' = ANY($2)
+          ORDER BY action_data->>'classification_type', created_at DESC
+      )
+      SELECT st.classification_type,
+             le.action,
+             le.created_at > st.classified_since AS is_recent
+      FROM shadow_types st
+      LEFT JOIN latest_events le
+          ON st.classification_type = le.classification_type
+      "#,
+    )
+    .bind(domain)
+    .bind(shadow_types)
+    .bind(classified_since)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(
+      rows
+        .into_iter()
+        .map(|(classification_type, action, is_recent)| {
+          let state = match (action.as_deref(), is_recent) {
+            (Some("classified"), Some(true)) => ClassifierState::Current,
+            (Some("classified"), _) => ClassifierState::Expired,
+            (Some("error"), _) => ClassifierState::Error,
+            _ => ClassifierState::Missing,
+          };
+          (classification_type, state)
+        })
+        .collect(),
+    )
+  }
 }
 
 /// Input for inserting or ensuring a prompt exists.
@@ -754,6 +811,548 @@ impl ErroredClassification {
   }
 }
 
+/// Records that a domain arrived from DNS traffic.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DomainSeen {
+  pub domain: String,
+}
+
+impl DomainSeen {
+  /// Advance the domain's `last_seen` timestamp, creating the row if needed.
+  /// Unlike event inserts this leaves `last_updated` alone for existing rows.
+  pub async fn touch(&self, pool: &PgPool) -> Result<(), sqlx::Error> {
+    sqlx::query(
+      r#"
+      INSERT INTO domains (domain, last_updated, last_seen)
+      VALUES ($1, NOW(), NOW())
+      ON CONFLICT (domain) DO UPDATE SET last_seen = NOW()
+      "#,
+    )
+    .bind(&self.domain)
+    .execute(pool)
+    .await?;
+    Ok(())
+  }
+}
+
+/// A matching classification that is still current but will expire soon.
+/// Used by the queue-processor's refresh scheduler to reclassify domains that
+/// are still in use before they drop out of the blocklist.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExpiringClassification {
+  pub domain: String,
+  pub classification_type: String,
+  pub valid_until: DateTime<Utc>,
+}
+
+impl ExpiringClassification {
+  /// Return matching classifications of the given types that expire before
+  /// `expiring_before`, for domains seen since `seen_since`, soonest expiry
+  /// first.
+  pub async fn find(
+    pool: &PgPool,
+    classification_types: &[String],
+    expiring_before: DateTime<Utc>,
+    seen_since: DateTime<Utc>,
+    limit: i64,
+  ) -> Result<Vec<Self>, sqlx::Error> {
+    sqlx::query_as::<_, (String, String, DateTime<Utc>)>(
+      r#"
+      WITH latest_classifications AS (
+          SELECT DISTINCT ON (dc.domain, dc.classification_type)
+              dc.domain,
+              dc.classification_type,
+              dc.is_matching_site,
+              dc.valid_until
+          FROM domain_classifications dc
+          WHERE dc.classification_type = ANY($1)
+          ORDER BY dc.domain, dc.classification_type, dc.created_at DESC
+      )
+      SELECT lc.domain, lc.classification_type, lc.valid_until
+      FROM latest_classifications lc
+      JOIN domains d ON d.domain = lc.domain
+      WHERE lc.is_matching_site
+        AND lc.valid_until > NOW()
+        AND lc.valid_until <= $2
+        AND d.last_seen >= $3
+      ORDER BY lc.valid_until
+      LIMIT $4
+      "#,
+    )
+    .bind(classification_types)
+    .bind(expiring_before)
+    .bind(seen_since)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+    .map(|rows| {
+      rows
+        .into_iter()
+        .map(|(domain, classification_type, valid_until)| Self {
+          domain,
+          classification_type,
+          valid_until,
+        })
+        .collect()
+    })
+  }
+
+  /// Return the classification types of `domain` whose current matching
+  /// classification expires before `expiring_before`.  A refresh message only
+  /// reclassifies these, so a duplicate refresh for a domain that has already
+  /// been reclassified is a no-op.
+  pub async fn types_for_domain(
+    pool: &PgPool,
+    domain: &str,
+    expiring_before: DateTime<Utc>,
+  ) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar(
+      r#"
+      SELECT classification_type
+      FROM (
+          SELECT DISTINCT ON (classification_type)
+              classification_type,
+              is_matching_site,
+              valid_until
+          FROM domain_classifications
+          WHERE domain = $1
+          ORDER BY classification_type, created_at DESC
+      ) latest
+      WHERE is_matching_site
+        AND valid_until > NOW()
+        AND valid_until <= $2
+      "#,
+    )
+    .bind(domain)
+    .bind(expiring_before)
+    .fetch_all(pool)
+    .await
+  }
+}
+
+/// Current LLM classifications produced by a prompt or model the classifier
+/// is no longer configured with.  Only `llm_prompt` sources are considered,
+/// so admin and provisioned decisions are never reported as stale.
+pub struct StaleClassification;
+
+impl StaleClassification {
+  /// Return the domains whose current `classification_type` classification
+  /// was made with a prompt other than `prompt_hash` or a model not in
+  /// `models`.
+  pub async fn find_domains(
+    pool: &PgPool,
+    classification_type: &str,
+    prompt_hash: &str,
+    models: &[String],
+  ) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar(
+      r#"
+      WITH latest AS (
+          SELECT DISTINCT ON (dc.domain)
+              dc.domain,
+              dc.model,
+              dc.valid_until,
+              cs.source_type,
+              p.hash
+          FROM domain_classifications dc
+          LEFT JOIN classification_sources cs ON cs.id = dc.source_id
+          LEFT JOIN prompts p ON p.id = cs.prompt_id
+          WHERE dc.classification_type = $1
+          ORDER BY dc.domain, dc.created_at DESC
+      )
+      SELECT domain
+      FROM latest
+      WHERE valid_until > NOW()
+        AND source_type = 'llm_prompt'
+        AND (hash IS DISTINCT FROM $2 OR NOT (model = ANY($3)))
+      ORDER BY domain
+      "#,
+    )
+    .bind(classification_type)
+    .bind(prompt_hash)
+    .bind(models)
+    .fetch_all(pool)
+    .await
+  }
+
+  /// The prompt hash and model behind each current LLM classification of
+  /// `domain` among `classification_types`, in one query, for comparing
+  /// against several classifiers' configuration at once.
+  pub async fn current_for_domain(
+    pool: &PgPool,
+    domain: &str,
+    classification_types: &[String],
+  ) -> Result<Vec<CurrentLlmClassification>, sqlx::Error> {
+    sqlx::query_as(
+      r#"
+      WITH latest AS (
+          SELECT DISTINCT ON (dc.classification_type)
+              dc.classification_type,
+              dc.model,
+              dc.valid_until,
+              cs.source_type,
+              p.hash
+          FROM domain_classifications dc
+          LEFT JOIN classification_sources cs ON cs.id = dc.source_id
+          LEFT JOIN prompts p ON p.id = cs.prompt_id
+          WHERE dc.domain = $1
+            AND dc.classification_type = ANY($2)
+          ORDER BY dc.classification_type, dc.created_at DESC
+      )
+      SELECT classification_type, hash AS prompt_hash, model
+      FROM latest
+      WHERE valid_until > NOW()
+        AND source_type = 'llm_prompt'
+      ORDER BY classification_type
+      "#,
+    )
+    .bind(domain)
+    .bind(classification_types)
+    .fetch_all(pool)
+    .await
+  }
+}
+
+/// The prompt and model a current LLM classification was made with.
+#[derive(Debug, Clone, FromRow, PartialEq)]
+pub struct CurrentLlmClassification {
+  pub classification_type: String,
+  pub prompt_hash: Option<String>,
+  pub model: String,
+}
+
+impl CurrentLlmClassification {
+  /// Whether it was made with a prompt other than `prompt_hash` or a model
+  /// not in `models`.
+  pub fn is_stale(&self, prompt_hash: &str, models: &[String]) -> bool {
+    self.prompt_hash.as_deref() != Some(prompt_hash)
+      || !models.contains(&self.model)
+  }
+}
+
+/// The host a domain's landing page redirected to when it was last
+/// classified, with that host's current LLM classification of the same type.
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct RedirectTarget {
+  pub host: String,
+  pub is_matching_site: bool,
+  pub confidence: f32,
+  pub reasoning: Option<String>,
+  pub model: String,
+  pub prompt_content: String,
+  pub prompt_hash: String,
+}
+
+impl RedirectTarget {
+  /// Find the redirect target of `domain` for `classification_type`, if its
+  /// last `classified` event recorded a different final host and that host
+  /// has a current LLM classification.  Events that reused a redirect
+  /// target record no final host, so a reuse is always followed by a fetch.
+  pub async fn find(
+    pool: &PgPool,
+    domain: &str,
+    classification_type: &str,
+  ) -> Result<Option<Self>, sqlx::Error> {
+    sqlx::query_as(
+      r#"
+      WITH last_fetch AS (
+          SELECT action_data->>'final_host' AS host
+          FROM domain_classification_events
+          WHERE domain = $1
+            AND action = 'classified'::classification_action
+            AND action_data->>'classification_type' = $2
+          ORDER BY created_at DESC
+          LIMIT 1
+      )
+      SELECT
+          lf.host,
+          dc.is_matching_site,
+          dc.confidence,
+          dc.reasoning,
+          dc.model,
+          p.content AS prompt_content,
+          p.hash AS prompt_hash
+      FROM last_fetch lf
+      JOIN domain_classifications dc
+        ON dc.domain = lf.host AND dc.classification_type = $2
+      JOIN classification_sources cs
+        ON cs.id = dc.source_id AND cs.source_type = 'llm_prompt'
+      JOIN prompts p ON p.id = cs.prompt_id
+      WHERE lf.host <> $1
+        AND dc.valid_on <= NOW()
+        AND dc.valid_until > NOW()
+      ORDER BY dc.created_at DESC
+      LIMIT 1
+      "#,
+    )
+    .bind(domain)
+    .bind(classification_type)
+    .fetch_optional(pool)
+    .await
+  }
+}
+
+/// A model's answer for a domain, with the key of the evidence, prompt and
+/// generation options it was given.
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct CachedLlmResult {
+  pub model: String,
+  pub cache_key: String,
+  pub is_matching_site: bool,
+  pub confidence: f64,
+  pub reasoning: String,
+}
+
+impl CachedLlmResult {
+  /// The answers cached for `domain` and `classification_type`, one per
+  /// model.
+  pub async fn find(
+    pool: &PgPool,
+    domain: &str,
+    classification_type: &str,
+  ) -> Result<Vec<Self>, sqlx::Error> {
+    sqlx::query_as(
+      r#"
+      SELECT model, cache_key, is_matching_site, confidence, reasoning
+      FROM llm_result_cache
+      WHERE domain = $1 AND classification_type = $2
+      ORDER BY model
+      "#,
+    )
+    .bind(domain)
+    .bind(classification_type)
+    .fetch_all(pool)
+    .await
+  }
+
+  /// Cache this answer, replacing the model's previous one.  Storing an
+  /// answer that was reused only marks it used.
+  pub async fn upsert(
+    &self,
+    executor: impl sqlx::Executor<'_, Database = Postgres>,
+    domain: &str,
+    classification_type: &str,
+  ) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query(
+      r#"
+      INSERT INTO llm_result_cache (
+        domain, classification_type, model, cache_key, is_matching_site,
+        confidence, reasoning
+      )
+      VALUES ($1, $2, $3, $4, $5, $6, $7)
+      ON CONFLICT (domain, classification_type, model) DO UPDATE SET
+        cache_key = EXCLUDED.cache_key,
+        is_matching_site = EXCLUDED.is_matching_site,
+        confidence = EXCLUDED.confidence,
+        reasoning = EXCLUDED.reasoning,
+        created_at = CASE
+          WHEN llm_result_cache.cache_key = EXCLUDED.cache_key
+          THEN llm_result_cache.created_at
+          ELSE NOW()
+        END,
+        last_used_at = NOW()
+      "#,
+    )
+    .bind(domain)
+    .bind(classification_type)
+    .bind(&self.model)
+    .bind(&self.cache_key)
+    .bind(self.is_matching_site)
+    .bind(self.confidence)
+    .bind(&self.reasoning)
+    .execute(executor)
+    .await
+  }
+}
+
+/// Circuit breaker state of an LLM backend, keyed by its URL.
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct LlmBackendStatus {
+  pub backend: String,
+  pub circuit_open: bool,
+  pub changed_at: DateTime<Utc>,
+}
+
+impl LlmBackendStatus {
+  /// Record that the circuit for `backend` was opened or closed.
+  pub async fn record(
+    pool: &PgPool,
+    backend: &str,
+    circuit_open: bool,
+  ) -> Result<(), sqlx::Error> {
+    sqlx::query(
+      r#"
+      INSERT INTO llm_backend_status (backend, circuit_open, changed_at)
+      VALUES ($1, $2, NOW())
+      ON CONFLICT (backend) DO UPDATE
+      SET circuit_open = EXCLUDED.circuit_open,
+          changed_at = EXCLUDED.changed_at
+      "#,
+    )
+    .bind(backend)
+    .bind(circuit_open)
+    .execute(pool)
+    .await?;
+    Ok(())
+  }
+
+  /// Return the recorded state of every backend.
+  pub async fn fetch_all(pool: &PgPool) -> Result<Vec<Self>, sqlx::Error> {
+    sqlx::query_as(
+      "SELECT backend, circuit_open, changed_at FROM llm_backend_status",
+    )
+    .fetch_all(pool)
+    .await
+  }
+}
+
+/// A below-threshold LLM result to record in the review queue.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReviewInsert {
+  pub domain: String,
+  pub classification_type: String,
+  pub is_matching_site: bool,
+  pub confidence: f32,
+  pub reasoning: Option<String>,
+  pub model: String,
+  pub source_id: Option<i32>,
+}
+
+impl ReviewInsert {
+  /// Record this result as a pending review, replacing any review still
+  /// pending for the same domain and classification type.
+  pub async fn upsert(
+    &self,
+    executor: impl sqlx::Executor<'_, Database = Postgres>,
+  ) -> Result<i32, sqlx::Error> {
+    sqlx::query_scalar(
+      r#"
+      INSERT INTO classification_reviews (
+        domain, classification_type, is_matching_site, confidence, reasoning,
+        model, source_id
+      )
+      VALUES ($1, $2, $3, $4, $5, $6, $7)
+      ON CONFLICT (domain, classification_type) WHERE status = 'pending'
+      DO UPDATE SET
+        is_matching_site = EXCLUDED.is_matching_site,
+        confidence = EXCLUDED.confidence,
+        reasoning = EXCLUDED.reasoning,
+        model = EXCLUDED.model,
+        source_id = EXCLUDED.source_id,
+        created_at = NOW()
+      RETURNING id
+      "#,
+    )
+    .bind(&self.domain)
+    .bind(&self.classification_type)
+    .bind(self.is_matching_site)
+    .bind(self.confidence)
+    .bind(&self.reasoning)
+    .bind(&self.model)
+    .bind(self.source_id)
+    .fetch_one(executor)
+    .await
+  }
+}
+
+/// Filters for listing pending reviews.  The confidence band is inclusive on
+/// both ends.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReviewFilter {
+  pub classification_type: Option<String>,
+  pub min_confidence: Option<f64>,
+  pub max_confidence: Option<f64>,
+}
+
+/// A classification in the review queue.
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct ClassificationReview {
+  pub id: i32,
+  pub domain: String,
+  pub classification_type: String,
+  pub is_matching_site: bool,
+  pub confidence: f32,
+  pub reasoning: Option<String>,
+  pub model: String,
+  pub status: String,
+  pub created_at: DateTime<Utc>,
+  pub resolved_at: Option<DateTime<Utc>>,
+  pub resolved_by: Option<i32>,
+}
+
+impl ClassificationReview {
+  /// Return pending reviews matching `filter`, lowest confidence first.
+  pub async fn find_pending(
+    pool: &PgPool,
+    filter: &ReviewFilter,
+  ) -> Result<Vec<Self>, sqlx::Error> {
+    // Compare against REAL so a bound such as 0.7 matches a stored 0.7.
+    sqlx::query_as(
+      r#"
+      SELECT id, domain, classification_type, is_matching_site, confidence,
+             reasoning, model, status, created_at, resolved_at, resolved_by
+      FROM classification_reviews
+      WHERE status = 'pending'
+        AND ($1::text IS NULL OR classification_type = $1)
+        AND ($2::real IS NULL OR confidence >= $2::real)
+        AND ($3::real IS NULL OR confidence <= $3::real)
+      ORDER BY confidence, created_at
+      "#,
+    )
+    .bind(&filter.classification_type)
+    .bind(filter.min_confidence)
+    .bind(filter.max_confidence)
+    .fetch_all(pool)
+    .await
+  }
+
+  /// Mark the pending review `id` as `status` ("approved" or "rejected") on
+  /// behalf of `user_id`.  Returns the review, or `None` when no review with
+  /// that ID is pending.
+  pub async fn resolve(
+    tx: &mut Transaction<'_, Postgres>,
+    id: i32,
+    status: &str,
+    user_id: i32,
+  ) -> Result<Option<Self>, sqlx::Error> {
+    sqlx::query_as(
+      r#"
+      UPDATE classification_reviews
+      SET status = $2, resolved_at = NOW(), resolved_by = $3
+      WHERE id = $1 AND status = 'pending'
+      RETURNING id, domain, classification_type, is_matching_site, confidence,
+                reasoning, model, status, created_at, resolved_at, resolved_by
+      "#,
+    )
+    .bind(id)
+    .bind(status)
+    .bind(user_id)
+    .fetch_optional(&mut **tx)
+    .await
+  }
+
+  /// Close the pending review of a domain and classification type, if any,
+  /// because a newer classification made it moot.
+  pub async fn supersede(
+    executor: impl sqlx::Executor<'_, Database = Postgres>,
+    domain: &str,
+    classification_type: &str,
+  ) -> Result<u64, sqlx::Error> {
+    sqlx::query(
+      r#"
+      UPDATE classification_reviews
+      SET status = 'superseded', resolved_at = NOW()
+      WHERE domain = $1 AND classification_type = $2 AND status = 'pending'
+      "#,
+    )
+    .bind(domain)
+    .bind(classification_type)
+    .execute(executor)
+    .await
+    .map(|result| result.rows_affected())
+  }
+}
+
 /// Full domain record as read from the database.
 #[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
 pub struct Domain {
@@ -1,14 +1,15 @@
 use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use sqlx::{PgPool, Postgres, Row};
+use sqlx::{PgPool, Postgres, Row, Transaction};
 use std::collections::{HashMap, HashSet};
 
 use super::error::DbError;
 use super::models::{
   ActiveProvisionedPattern, ClassificationEventInsert, ClassificationInsert,
-  ClassificationSource, DomainUpsert, PromptInsert,
+  ClassificationReview, ClassificationSource, DomainUpsert, PromptInsert,
 };
+use crate::ttl::TtlPolicy;
 
 /// Insert a domain_classification_event.  Accepts any Postgres executor so
 /// callers can pass either a pool or an in-flight transaction.
@@ -37,6 +38,9 @@ pub async fn insert_event(
 /// Returns the source_id so callers can stamp audit events with the same
 /// provenance (e.g. the classified event in the queue-processor).
 ///
+/// `valid_until` follows `ttl` for the result's verdict and confidence;
+/// `fetch_failed` marks a classification made without the site's content.
+///
 /// This is the canonical way to persist a successful LLM classification.
 pub async fn classification_store(
   pool: &PgPool,
@@ -48,7 +52,8 @@ pub async fn classification_store(
   model: &str,
   prompt_content: &str,
   prompt_hash: &str,
-  ttl_days: i64,
+  ttl: &TtlPolicy,
+  fetch_failed: bool,
 ) -> Result<i32, DbError> {
   let mut tx = pool.begin().await?;
 
@@ -68,7 +73,26 @@ pub async fn classification_store(
   .await?;
 
   let valid_on = Utc::now();
-  let valid_until = valid_on + Duration::days(ttl_days);
+  let valid_until =
+    valid_on + ttl.ttl(is_matching_site, confidence, fetch_failed);
+
+  // The new result supersedes any earlier LLM result for the type, so the
+  // blocklist never mixes an old verdict with a new one.
+  sqlx::query(
+    r#"
+    UPDATE domain_classifications SET valid_until = $1
+    WHERE domain = $2 AND classification_type = $3
+      AND valid_until > $1
+      AND source_id IN (
+        SELECT id FROM classification_sources WHERE source_type = 'llm_prompt'
+      )
+    "#,
+  )
+  .bind(valid_on)
+  .bind(domain)
+  .bind(classification_type)
+  .execute(&mut *tx)
+  .await?;
 
   ClassificationInsert {
     domain: domain.to_string(),
@@ -227,14 +251,42 @@ pub async fn apply_admin_classification(
   ttl_days: Option<i64>,
 ) -> Result<i32, DbError> {
   let mut tx = pool.begin().await?;
+  let source_id = insert_admin_classification(
+    &mut tx,
+    domain,
+    classification_type,
+    is_matching_site,
+    confidence,
+    reasoning,
+    user_id,
+    ttl_days,
+  )
+  .await?;
+  tx.commit().await?;
+  Ok(source_id)
+}
 
+/// Body of [`apply_admin_classification`], run inside the caller's
+/// transaction.  Any pending review of the same domain and type is
+/// superseded by the admin decision.
+#[allow(clippy::too_many_arguments)]
+async fn insert_admin_classification(
+  tx: &mut Transaction<'_, Postgres>,
+  domain: &str,
+  classification_type: &str,
+  is_matching_site: bool,
+  confidence: f64,
+  reasoning: &str,
+  user_id: i32,
+  ttl_days: Option<i64>,
+) -> Result<i32, DbError> {
   DomainUpsert {
     domain: domain.to_string(),
   }
-  .upsert(&mut tx)
+  .upsert(tx)
   .await?;
 
-  let source_id = ClassificationSource::admin_insert(user_id, &mut tx).await?;
+  let source_id = ClassificationSource::admin_insert(user_id, tx).await?;
 
   let now = Utc::now();
   let valid_until = match ttl_days {
@@ -253,11 +305,11 @@ pub async fn apply_admin_classification(
     model: "admin".to_string(),
     source_id: Some(source_id),
   }
-  .insert(&mut tx)
+  .insert(tx)
   .await?;
 
   insert_event(
-    &mut *tx,
+    &mut **tx,
     domain,
     "classified",
     json!({
@@ -271,10 +323,63 @@ pub async fn apply_admin_classification(
   )
   .await?;
 
-  tx.commit().await?;
+  ClassificationReview::supersede(&mut **tx, domain, classification_type)
+    .await?;
+
   Ok(source_id)
 }
 
+/// Approve the pending review `id`: its LLM verdict becomes an admin
+/// classification attributed to `user_id`, expiring after `ttl_days` (never
+/// when `None`).
+///
+/// Returns the `source_id` of the admin classification, or `None` when no
+/// review with that ID is pending.
+pub async fn approve_review(
+  pool: &PgPool,
+  id: i32,
+  user_id: i32,
+  ttl_days: Option<i64>,
+) -> Result<Option<i32>, DbError> {
+  let mut tx = pool.begin().await?;
+  let Some(review) =
+    ClassificationReview::resolve(&mut tx, id, "approved", user_id).await?
+  else {
+    return Ok(None);
+  };
+
+  let source_id = insert_admin_classification(
+    &mut tx,
+    &review.domain,
+    &review.classification_type,
+    review.is_matching_site,
+    f64::from(review.confidence),
+    review.reasoning.as_deref().unwrap_or_default(),
+    user_id,
+    ttl_days,
+  )
+  .await?;
+
+  tx.commit().await?;
+  Ok(Some(source_id))
+}
+
+/// Reject the pending review `id` on behalf of `user_id`, discarding its
+/// verdict.  Returns `false` when no review with that ID is pending.
+pub async fn reject_review(
+  pool: &PgPool,
+  id: i32,
+  user_id: i32,
+) -> Result<bool, DbError> {
+  let mut tx = pool.begin().await?;
+  let rejected =
+    ClassificationReview::resolve(&mut tx, id, "rejected", user_id)
+      .await?
+      .is_some();
+  tx.commit().await?;
+  Ok(rejected)
+}
+
 /// A single desired provisioned classification entry, as declared in NixOS
 /// configuration and submitted via `dns-smart-block-cli domain reconcile`.
 ///
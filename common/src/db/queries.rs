@@ -4,20 +4,36 @@ use sqlx::{PgPool, Row};
 use std::collections::HashMap;
 
 use super::error::DbError;
+use super::models::LlmBackendStatus;
 
 /// Reserved classification type that applies to all classifier types when no
 /// more-specific active record exists.  Domains with an active "all" record
 /// are never sent to the LLM.
 pub const ALL_CLASSIFICATION_TYPE: &str = "all";
 
+/// Prefix of the classification type under which a shadow classifier records
+/// its events.  Shadow results are never projected into
+/// `domain_classifications`, so they cannot affect `/blocklist`.
+pub const SHADOW_TYPE_PREFIX: &str = "shadow:";
+
+/// Classification type recorded by the shadow of `classification_type`.
+pub fn shadow_classification_type(classification_type: &str) -> String {
+  format!("{}{}", SHADOW_TYPE_PREFIX, classification_type)
+}
+
 /// Get all blocked domains for a given classification type at a specific time.
 /// Returns domains where the classification is valid at the given time and
 /// `is_matching_site = true`.  An active "all" record acts as a fallback when
 /// no per-type record exists for a domain.
+///
+/// LLM classifications below `min_confidence` are ignored, as if the domain
+/// had no per-type record.  Admin, rule and provisioned classifications are
+/// never filtered.
 pub async fn get_blocked_domains(
   pool: &PgPool,
   classification_type: &str,
   at_time: Option<DateTime<Utc>>,
+  min_confidence: Option<f64>,
 ) -> Result<Vec<String>, DbError> {
   let check_time = at_time.unwrap_or_else(Utc::now);
 
@@ -38,13 +54,22 @@ pub async fn get_blocked_domains(
   // behavior on a single matching row and tightens the previous
   // implementation's arbitrary-pick on multiple rows (the old
   // `DISTINCT ON` had no tiebreaker beyond the CASE expression).
+  //
+  // The threshold is bound as REAL, the type of `confidence`, so a stored
+  // 0.7 compares equal to a threshold of 0.7.
   let rows = sqlx::query(
     r#"
     WITH per_type AS (
-      SELECT domain, is_matching_site
-      FROM domain_classifications
-      WHERE classification_type = $1
-        AND valid_on <= $2 AND valid_until > $2
+      SELECT dc.domain, dc.is_matching_site
+      FROM domain_classifications dc
+      LEFT JOIN classification_sources cs ON cs.id = dc.source_id
+      WHERE dc.classification_type = $1
+        AND dc.valid_on <= $2 AND dc.valid_until > $2
+        AND (
+          $3::real IS NULL
+          OR dc.confidence >= $3
+          OR cs.source_type IS DISTINCT FROM 'llm_prompt'
+        )
     ),
     all_override AS (
       SELECT a.domain, a.is_matching_site
@@ -63,6 +88,7 @@ pub async fn get_blocked_domains(
   )
   .bind(classification_type)
   .bind(check_time)
+  .bind(min_confidence.map(|c| c as f32))
   .fetch_all(pool)
   .await?;
 
@@ -125,6 +151,10 @@ pub struct MetricsStats {
   pub classifications_created_total: i64,
   /// Count of "classified" events in the last 5 minutes per classification_type.
   pub recent_classified_by_type: HashMap<String, i64>,
+  /// Whether the queue-processor's circuit breaker is open, per LLM backend.
+  pub llm_circuit_open_by_backend: HashMap<String, bool>,
+  /// Count of classifications awaiting human review per classification_type.
+  pub pending_reviews_by_type: HashMap<String, i64>,
 }
 
 /// Get comprehensive metrics statistics from the database.
@@ -331,6 +361,25 @@ pub async fn get_metrics_stats(pool: &PgPool) -> Result<MetricsStats, DbError> {
     recent_classified_by_type.insert(classification_type, count);
   }
 
+  let llm_circuit_open_by_backend = LlmBackendStatus::fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|status| (status.backend, status.circuit_open))
+    .collect();
+
+  let pending_reviews_by_type = sqlx::query_as::<_, (String, i64)>(
+    r#"
+        SELECT classification_type, COUNT(*)
+        FROM classification_reviews
+        WHERE status = 'pending'
+        GROUP BY classification_type
+        "#,
+  )
+  .fetch_all(pool)
+  .await?
+  .into_iter()
+  .collect();
+
   Ok(MetricsStats {
     current_classifications_by_type,
     current_positive_by_type,
@@ -343,6 +392,8 @@ pub async fn get_metrics_stats(pool: &PgPool) -> Result<MetricsStats, DbError> {
     classifications_created_by_type,
     classifications_created_total,
     recent_classified_by_type,
+    llm_circuit_open_by_backend,
+    pending_reviews_by_type,
   })
 }
 
@@ -565,3 +616,94 @@ pub async fn get_domain_status(
     recent_events,
   })
 }
+
+/// A domain on which a shadow classifier disagrees with the live one.
+#[derive(Debug, Clone, Serialize)]
+pub struct ShadowDisagreement {
+  pub domain: String,
+  pub live_is_matching_site: bool,
+  pub live_confidence: f64,
+  pub shadow_is_matching_site: bool,
+  pub shadow_confidence: f64,
+}
+
+/// How a shadow classifier's verdicts compare with the live classifier's.
+#[derive(Debug, Clone, Serialize)]
+pub struct ShadowComparison {
+  pub classification_type: String,
+  pub shadow_type: String,
+  /// Domains classified by both the live and the shadow classifier.
+  pub compared: i64,
+  /// Domains on which both reached the same verdict.
+  pub agreed: i64,
+  /// `agreed / compared`, or `None` before any domain was compared.
+  pub agreement_rate: Option<f64>,
+  pub disagreements: Vec<ShadowDisagreement>,
+}
+
+/// Compare the latest `classified` event of each domain for
+/// `classification_type` with the latest one of its shadow.  Only domains
+/// classified by both are compared.
+pub async fn compare_shadow(
+  pool: &PgPool,
+  classification_type: &str,
+) -> Result<ShadowComparison, DbError> {
+  let shadow_type = shadow_classification_type(classification_type);
+
+  let rows = sqlx::query(
+    r#"
+        WITH latest AS (
+            SELECT DISTINCT ON (domain, action_data->>'classification_type')
+                domain,
+                action_data->>'classification_type' AS classification_type,
+                (action_data->>'is_matching_site')::boolean AS is_matching_site,
+                (action_data->>'confidence')::float8 AS confidence
+            FROM domain_classification_events
+            WHERE action = 'classified'::classification_action
+              AND action_data->>'classification_type' IN ($1, $2)
+            ORDER BY domain, action_data->>'classification_type', created_at DESC
+        )
+        SELECT live.domain,
+               live.is_matching_site AS live_is_matching_site,
+               live.confidence AS live_confidence,
+               shadow.is_matching_site AS shadow_is_matching_site,
+               shadow.confidence AS shadow_confidence
+        FROM latest live
+        JOIN latest shadow ON shadow.domain = live.domain
+        WHERE live.classification_type = $1
+          AND shadow.classification_type = $2
+        ORDER BY live.domain
+        "#,
+  )
+  .bind(classification_type)
+  .bind(&shadow_type)
+  .fetch_all(pool)
+  .await?;
+
+  let compared = rows.len() as i64;
+  let mut disagreements = Vec::new();
+  for row in rows {
+    let disagreement = ShadowDisagreement {
+      domain: row.try_get("domain")?,
+      live_is_matching_site: row.try_get("live_is_matching_site")?,
+      live_confidence: row.try_get("live_confidence")?,
+      shadow_is_matching_site: row.try_get("shadow_is_matching_site")?,
+      shadow_confidence: row.try_get("shadow_confidence")?,
+    };
+    if disagreement.live_is_matching_site
+      != disagreement.shadow_is_matching_site
+    {
+      disagreements.push(disagreement);
+    }
+  }
+  let agreed = compared - disagreements.len() as i64;
+
+  Ok(ShadowComparison {
+    classification_type: classification_type.to_string(),
+    shadow_type,
+    compared,
+    agreed,
+    agreement_rate: (compared > 0).then(|| agreed as f64 / compared as f64),
+    disagreements,
+  })
+}
@@ -0,0 +1,337 @@
+//! JetStream topology shared by the services that touch the domain queue.
+//!
+//! Every service that publishes to or consumes from the queue provisions the
+//! stream on startup, so a fresh NATS server needs no manual `nats stream add`
+//! step and whichever service starts first creates it.  Provisioning is
+//! idempotent: an existing stream is updated in place when its configuration
+//! differs from the one requested, and left alone otherwise.
+
+use async_nats::HeaderMap;
+use async_nats::jetstream::stream::{
+  Config as StreamConfig, RetentionPolicy, Stream,
+};
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use thiserror::Error;
+use tracing::{info, warn};
+
+/// Header JetStream uses to drop duplicate publishes within the stream's
+/// duplicate window.
+pub const MSG_ID_HEADER: &str = "Nats-Msg-Id";
+
+#[derive(Error, Debug)]
+pub enum StreamError {
+  #[error("Failed to create stream {0}: {1}")]
+  Create(String, String),
+
+  #[error("Failed to update stream {0}: {1}")]
+  Update(String, String),
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct StreamArgs {
+  /// Name of the JetStream stream holding the domain queue
+  #[arg(long, env = "NATS_STREAM", default_value = "DNS_SMART_BLOCK")]
+  pub nats_stream: String,
+
+  /// Maximum age of a queued domain message in seconds (0 for no limit)
+  #[arg(long, env = "NATS_STREAM_MAX_AGE_SEC", default_value = "604800")]
+  pub nats_stream_max_age_sec: u64,
+
+  /// Maximum size of the domain queue in bytes (-1 for no limit)
+  #[arg(long, env = "NATS_STREAM_MAX_BYTES", default_value = "-1")]
+  pub nats_stream_max_bytes: i64,
+
+  /// Window in seconds within which repeated publishes of the same domain are
+  /// dropped by JetStream
+  #[arg(long, env = "NATS_DUPLICATE_WINDOW_SEC", default_value = "120")]
+  pub nats_duplicate_window_sec: u64,
+
+  /// Maximum age of a dead-lettered message in seconds (0 for no limit)
+  #[arg(long, env = "NATS_DEAD_LETTER_MAX_AGE_SEC", default_value = "2592000")]
+  pub nats_dead_letter_max_age_sec: u64,
+}
+
+/// Priority lane of a domain message.  Each priority is published on its own
+/// subject and drained by its own consumer; the queue-processor only takes
+/// from a lane when every higher-priority lane is empty.
+#[derive(
+  Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+  /// Domains seen in DNS traffic, which a client is waiting on.  Messages
+  /// published before priorities existed carry no field and land here.
+  #[default]
+  Interactive,
+  /// Background work: admin bulk requeues and refreshes of expiring
+  /// classifications.
+  Low,
+}
+
+impl Priority {
+  /// Every priority, highest first.
+  pub const ALL: [Priority; 2] = [Priority::Interactive, Priority::Low];
+
+  pub fn as_str(self) -> &'static str {
+    match self {
+      Priority::Interactive => "interactive",
+      Priority::Low => "low",
+    }
+  }
+
+  /// Subject carrying messages of this priority.  Interactive messages keep
+  /// using the configured subject itself so existing publishers are
+  /// unaffected.
+  pub fn subject(self, subject: &str) -> String {
+    match self {
+      Priority::Interactive => subject.to_string(),
+      other => format!("{}.{}", subject, other.as_str()),
+    }
+  }
+}
+
+/// What caused a domain message to be published.  Only DNS traffic marks a
+/// domain as seen, which is what keeps it eligible for refreshes.
+#[derive(
+  Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Origin {
+  /// A DNS query for the domain.  Messages published before origins existed
+  /// carry no field and land here.
+  #[default]
+  Dns,
+  /// An admin requeue.
+  Requeue,
+  /// The refresh scheduler.
+  Refresh,
+  /// A requeue after a classifier's prompt or model changed.
+  PromptChange,
+}
+
+impl StreamArgs {
+  /// Configuration for the work-queue stream capturing the subject of every
+  /// priority lane derived from `subject`.  Messages are removed as soon as a
+  /// consumer acknowledges them.
+  pub fn stream_config(&self, subject: &str) -> StreamConfig {
+    StreamConfig {
+      name: self.nats_stream.clone(),
+      subjects: Priority::ALL
+        .iter()
+        .map(|priority| priority.subject(subject))
+        .collect(),
+      retention: RetentionPolicy::WorkQueue,
+      max_age: Duration::from_secs(self.nats_stream_max_age_sec),
+      max_bytes: self.nats_stream_max_bytes,
+      duplicate_window: Duration::from_secs(self.nats_duplicate_window_sec),
+      ..Default::default()
+    }
+  }
+
+  /// Name of the stream capturing the dead-letter subject.
+  pub fn dead_letter_stream_name(&self) -> String {
+    format!("{}_DEAD_LETTER", self.nats_stream)
+  }
+
+  /// Configuration for the stream capturing `subject`, the dead-letter
+  /// subject.  Dead letters are kept for inspection rather than consumed, so
+  /// this stream uses the default limits retention instead of a work queue.
+  pub fn dead_letter_stream_config(&self, subject: &str) -> StreamConfig {
+    StreamConfig {
+      name: self.dead_letter_stream_name(),
+      subjects: vec![subject.to_string()],
+      max_age: Duration::from_secs(self.nats_dead_letter_max_age_sec),
+      max_bytes: -1,
+      duplicate_window: Duration::from_secs(self.nats_duplicate_window_sec),
+      ..Default::default()
+    }
+  }
+}
+
+/// Create `desired` if it does not exist, or bring an existing stream of the
+/// same name in line with it.
+pub async fn ensure_stream(
+  jetstream: &async_nats::jetstream::Context,
+  desired: StreamConfig,
+) -> Result<Stream, StreamError> {
+  let name = desired.name.clone();
+  let stream = jetstream
+    .get_or_create_stream(desired.clone())
+    .await
+    .map_err(|e| StreamError::Create(name.clone(), e.to_string()))?;
+
+  match stream_update(&stream.cached_info().config, &desired) {
+    None => {
+      info!("JetStream stream {} is up to date", name);
+      Ok(stream)
+    }
+    Some(update) => {
+      info!("Updating JetStream stream {}", name);
+      jetstream
+        .update_stream(update)
+        .await
+        .map_err(|e| StreamError::Update(name.clone(), e.to_string()))?;
+      jetstream
+        .get_stream(&name)
+        .await
+        .map_err(|e| StreamError::Update(name, e.to_string()))
+    }
+  }
+}
+
+/// The configuration to send as an update when `existing` differs from
+/// `desired` in any of the fields we manage, or `None` when no update is
+/// needed.  Fields we do not manage keep their existing values.
+///
+/// JetStream cannot change a stream's retention policy in place, so a stream
+/// created with a different policy (e.g. by an older `nats stream add` step)
+/// keeps it; delete the stream to have it recreated as a work queue.
+pub fn stream_update(
+  existing: &StreamConfig,
+  desired: &StreamConfig,
+) -> Option<StreamConfig> {
+  if existing.retention != desired.retention {
+    warn!(
+      "JetStream stream {} has retention {:?} but {:?} was requested; \
+       retention cannot be changed in place, delete the stream to recreate it",
+      existing.name, existing.retention, desired.retention
+    );
+  }
+
+  let mut subjects = existing.subjects.clone();
+  subjects.sort();
+  let mut desired_subjects = desired.subjects.clone();
+  desired_subjects.sort();
+
+  if subjects == desired_subjects
+    && existing.max_age == desired.max_age
+    && existing.max_bytes == desired.max_bytes
+    && existing.duplicate_window == desired.duplicate_window
+  {
+    return None;
+  }
+
+  Some(StreamConfig {
+    subjects: desired.subjects.clone(),
+    max_age: desired.max_age,
+    max_bytes: desired.max_bytes,
+    duplicate_window: desired.duplicate_window,
+    ..existing.clone()
+  })
+}
+
+/// Headers for publishing a domain message.  The domain itself is used as the
+/// message ID so the stream keeps at most one copy of a domain per duplicate
+/// window, however many times it shows up in the DNS logs.
+pub fn domain_headers(domain: &str) -> HeaderMap {
+  let mut headers = HeaderMap::new();
+  headers.insert(MSG_ID_HEADER, domain);
+  headers
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn args() -> StreamArgs {
+    StreamArgs {
+      nats_stream: "DNS_SMART_BLOCK".to_string(),
+      nats_stream_max_age_sec: 3600,
+      nats_stream_max_bytes: 1024,
+      nats_duplicate_window_sec: 120,
+      nats_dead_letter_max_age_sec: 86400,
+    }
+  }
+
+  #[test]
+  fn test_stream_config_is_work_queue() {
+    let config = args().stream_config("dns.domains");
+    assert_eq!(config.name, "DNS_SMART_BLOCK");
+    assert_eq!(
+      config.subjects,
+      vec!["dns.domains".to_string(), "dns.domains.low".to_string()]
+    );
+    assert_eq!(config.retention, RetentionPolicy::WorkQueue);
+    assert_eq!(config.max_age, Duration::from_secs(3600));
+    assert_eq!(config.max_bytes, 1024);
+    assert_eq!(config.duplicate_window, Duration::from_secs(120));
+  }
+
+  #[test]
+  fn test_dead_letter_stream_config() {
+    let config = args().dead_letter_stream_config("dns.dead_letter");
+    assert_eq!(config.name, "DNS_SMART_BLOCK_DEAD_LETTER");
+    assert_eq!(config.subjects, vec!["dns.dead_letter".to_string()]);
+    assert_eq!(config.retention, RetentionPolicy::Limits);
+    assert_eq!(config.max_age, Duration::from_secs(86400));
+    assert_eq!(config.duplicate_window, Duration::from_secs(120));
+  }
+
+  #[test]
+  fn test_matching_stream_needs_no_update() {
+    let desired = args().stream_config("a");
+    let existing = StreamConfig {
+      subjects: vec!["a.low".to_string(), "a".to_string()],
+      ..desired.clone()
+    };
+    assert!(stream_update(&existing, &desired).is_none());
+  }
+
+  #[test]
+  fn test_changed_limits_are_updated() {
+    let desired = args().stream_config("dns.domains");
+    let existing = StreamConfig {
+      max_age: Duration::from_secs(60),
+      num_replicas: 3,
+      ..desired.clone()
+    };
+    let update = stream_update(&existing, &desired).unwrap();
+    assert_eq!(update.max_age, Duration::from_secs(3600));
+    // Unmanaged fields are carried over from the existing stream.
+    assert_eq!(update.num_replicas, 3);
+  }
+
+  #[test]
+  fn test_retention_is_never_changed_in_place() {
+    let desired = args().stream_config("dns.domains");
+    let existing = StreamConfig {
+      retention: RetentionPolicy::Limits,
+      subjects: vec!["dns.old".to_string()],
+      ..desired.clone()
+    };
+    let update = stream_update(&existing, &desired).unwrap();
+    assert_eq!(update.retention, RetentionPolicy::Limits);
+    assert_eq!(update.subjects, desired.subjects);
+  }
+
+  #[test]
+  fn test_priority_subjects() {
+    assert_eq!(Priority::Interactive.subject("dns.domains"), "dns.domains");
+    assert_eq!(Priority::Low.subject("dns.domains"), "dns.domains.low");
+  }
+
+  #[test]
+  fn test_priority_defaults_to_interactive() {
+    assert_eq!(Priority::default(), Priority::Interactive);
+    let low: Priority = serde_json::from_str(r#""low""#).unwrap();
+    assert_eq!(low, Priority::Low);
+  }
+
+  #[test]
+  fn test_origin_defaults_to_dns() {
+    assert_eq!(Origin::default(), Origin::Dns);
+    let origin: Origin = serde_json::from_str(r#""prompt_change""#).unwrap();
+    assert_eq!(origin, Origin::PromptChange);
+  }
+
+  #[test]
+  fn test_domain_headers_set_message_id() {
+    let headers = domain_headers("example.com");
+    assert_eq!(
+      headers.get(MSG_ID_HEADER).map(|v| v.as_str()),
+      Some("example.com")
+    );
+  }
+}
@@ -1,4 +1,6 @@
 pub mod db;
+pub mod jetstream;
 pub mod logging;
 pub mod systemd;
 pub mod test_db;
+pub mod ttl;
@@ -0,0 +1,103 @@
+//! Lifetimes of LLM classifications.
+//!
+//! A confident "this is Steam" can be trusted for longer than a borderline
+//! guess, a verdict that blocks a domain may warrant a different lifetime
+//! than one that lets it through, and a classification made from nothing but
+//! the domain name (because the site could not be fetched) should be retried
+//! soon.  [`TtlPolicy`] captures those choices; `classification_store`
+//! applies it to compute `valid_until`.
+
+use chrono::Duration;
+
+/// How long a classification stays current.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TtlPolicy {
+  /// Lifetime of a matching verdict, in days
+  pub matching_days: i64,
+  /// Lifetime of a non-matching verdict, in days
+  pub not_matching_days: i64,
+  /// Multiply the lifetime by the classification's confidence
+  pub scale_by_confidence: bool,
+  /// Upper bound on the lifetime when the site could not be fetched, in days
+  pub fetch_error_days: Option<i64>,
+}
+
+impl TtlPolicy {
+  /// The same lifetime for every classification.
+  pub fn fixed(days: i64) -> Self {
+    Self {
+      matching_days: days,
+      not_matching_days: days,
+      scale_by_confidence: false,
+      fetch_error_days: None,
+    }
+  }
+
+  /// The lifetime of a classification with the given verdict and confidence.
+  /// `fetch_failed` is set when the classification was made without the
+  /// site's content.
+  pub fn ttl(
+    &self,
+    is_matching_site: bool,
+    confidence: f64,
+    fetch_failed: bool,
+  ) -> Duration {
+    let days = if is_matching_site {
+      self.matching_days
+    } else {
+      self.not_matching_days
+    };
+    let mut ttl = Duration::days(days);
+    if self.scale_by_confidence {
+      let seconds = ttl.num_seconds() as f64 * confidence.clamp(0.0, 1.0);
+      ttl = Duration::seconds(seconds.round() as i64);
+    }
+    if let (true, Some(days)) = (fetch_failed, self.fetch_error_days) {
+      ttl = ttl.min(Duration::days(days));
+    }
+    ttl
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_fixed_policy_ignores_result() {
+    let policy = TtlPolicy::fixed(10);
+    assert_eq!(policy.ttl(true, 0.81, false), Duration::days(10));
+    assert_eq!(policy.ttl(false, 0.99, true), Duration::days(10));
+  }
+
+  #[test]
+  fn test_verdicts_have_separate_lifetimes() {
+    let policy = TtlPolicy {
+      matching_days: 30,
+      not_matching_days: 7,
+      ..TtlPolicy::fixed(10)
+    };
+    assert_eq!(policy.ttl(true, 0.9, false), Duration::days(30));
+    assert_eq!(policy.ttl(false, 0.9, false), Duration::days(7));
+  }
+
+  #[test]
+  fn test_confidence_scales_lifetime() {
+    let policy = TtlPolicy {
+      scale_by_confidence: true,
+      ..TtlPolicy::fixed(10)
+    };
+    assert_eq!(policy.ttl(true, 1.0, false), Duration::days(10));
+    assert_eq!(policy.ttl(true, 0.5, false), Duration::days(5));
+  }
+
+  #[test]
+  fn test_fetch_error_caps_lifetime() {
+    let policy = TtlPolicy {
+      fetch_error_days: Some(1),
+      ..TtlPolicy::fixed(10)
+    };
+    assert_eq!(policy.ttl(true, 0.9, true), Duration::days(1));
+    assert_eq!(policy.ttl(true, 0.9, false), Duration::days(10));
+  }
+}
@@ -1,4 +1,5 @@
 use clap::Parser;
+use dns_smart_block_common::jetstream::StreamArgs;
 use dns_smart_block_common::logging::LoggingArgs;
 use std::path::PathBuf;
 
@@ -9,6 +10,9 @@ pub struct CliArgs {
   #[command(flatten)]
   pub logging: LoggingArgs,
 
+  #[command(flatten)]
+  pub stream: StreamArgs,
+
   /// Log source: either a file path or a command to run (prefix with 'cmd:').
   /// Examples: '/var/log/dns.log' or 'cmd:journalctl --follow --unit=blocky.service'
   #[arg(long, env = "LOG_SOURCE")]
@@ -30,11 +34,11 @@ pub struct CliArgs {
   #[arg(long, env = "LINE_FILTER")]
   pub line_filter: Option<String>,
 
-  /// Optional regex to extract the resolved IP address from a log line.
-  /// When set, the captured IP is included in the NATS message so downstream
-  /// components can fetch the domain's content directly by IP, avoiding a
-  /// second DNS lookup through the local resolver.
-  /// Example for Blocky: 'answer=(?:A|AAAA) \(([0-9a-fA-F:.]+)\)'
+  /// Optional regex to extract the resolved IP addresses from a log line.
+  /// When set, every captured IP is included in the NATS message so
+  /// downstream components can fetch the domain's content directly by IP,
+  /// avoiding a second DNS lookup through the local resolver.
+  /// Example for Blocky: '\b(?:A|AAAA) \(([0-9a-fA-F:.]+)\)'
   #[arg(long, env = "IP_PATTERN")]
   pub ip_pattern: Option<String>,
 
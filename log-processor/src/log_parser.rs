@@ -7,9 +7,9 @@ use tracing::debug;
 pub struct ParsedLine {
   /// Normalised (lowercase) registrable domain.
   pub domain: String,
-  /// Resolved IP address, when the log format includes it and an ip_pattern
-  /// was configured.
-  pub resolved_ip: Option<String>,
+  /// Resolved IP addresses, in log order, when the log format includes them
+  /// and an ip_pattern was configured.
+  pub resolved_ips: Vec<String>,
 }
 
 /// Configurable regex-based parser that extracts domains (and optionally
@@ -24,7 +24,8 @@ pub struct LogParser {
 
 impl LogParser {
   /// Build a parser from regex patterns.  `line_filter` pre-screens lines
-  /// (e.g. only "RESOLVED"), `ip_pattern` optionally captures the answer IP.
+  /// (e.g. only "RESOLVED"), `ip_pattern` optionally captures the answer IPs;
+  /// every match in a line is kept.
   pub fn new(
     domain_pattern: &str,
     capture_group: usize,
@@ -44,7 +45,7 @@ impl LogParser {
     })
   }
 
-  /// Parse a log line and extract a domain (and optionally a resolved IP) if
+  /// Parse a log line and extract a domain (and optionally resolved IPs) if
   /// it passes the line filter and the domain pattern matches.
   pub fn parse_log_line(&self, line: &str) -> Option<ParsedLine> {
     if line.trim().is_empty() {
@@ -65,15 +66,19 @@ impl LogParser {
         let domain = domain.as_str();
         if is_valid_domain(domain) {
           debug!("Extracted domain: {}", domain);
-          let resolved_ip = self.ip_pattern.as_ref().and_then(|pat| {
-            pat
-              .captures(line)
-              .and_then(|c| c.get(self.ip_capture_group))
-              .map(|m| m.as_str().to_string())
-          });
+          let mut resolved_ips: Vec<String> = Vec::new();
+          if let Some(pat) = &self.ip_pattern {
+            for captures in pat.captures_iter(line) {
+              if let Some(ip) = captures.get(self.ip_capture_group) {
+                if !resolved_ips.iter().any(|seen| seen == ip.as_str()) {
+                  resolved_ips.push(ip.as_str().to_string());
+                }
+              }
+            }
+          }
           return Some(ParsedLine {
             domain: domain.to_lowercase(),
-            resolved_ip,
+            resolved_ips,
           });
         }
       }
@@ -242,4 +247,42 @@ mod tests {
     assert_eq!(parser.parse_log_line(""), None);
     assert_eq!(parser.parse_log_line("   "), None);
   }
+
+  #[test]
+  fn test_every_resolved_ip_is_captured() {
+    let parser = LogParser::new(
+      BLOCKY_PATTERN,
+      1,
+      Some(BLOCKY_FILTER),
+      Some(r"\b(?:A|AAAA) \(([0-9a-fA-F:.]+)\)"),
+      1,
+    )
+    .unwrap();
+
+    let line = "[2026-02-04 20:33:21]  INFO queryLog: query resolved \
+      answer=A (13.107.213.69), A (13.107.246.69), A (13.107.213.69) \
+      client_ip=127.0.0.1 question_name=minecraft.net. question_type=A \
+      response_code=NOERROR response_reason=RESOLVED (tcp+udp:1.1.1.1) \
+      response_type=RESOLVED";
+    assert_eq!(
+      parser.parse_log_line(line),
+      Some(ParsedLine {
+        domain: "minecraft.net".to_string(),
+        resolved_ips: vec![
+          "13.107.213.69".to_string(),
+          "13.107.246.69".to_string()
+        ],
+      })
+    );
+
+    let line = "[2026-02-04 20:33:21]  INFO queryLog: query resolved \
+      answer=AAAA (2620:1ec:bdf::69) client_ip=127.0.0.1 \
+      question_name=minecraft.net. question_type=AAAA \
+      response_code=NOERROR response_reason=RESOLVED (tcp+udp:1.1.1.1) \
+      response_type=RESOLVED";
+    assert_eq!(
+      parser.parse_log_line(line).map(|p| p.resolved_ips),
+      Some(vec!["2620:1ec:bdf::69".to_string()])
+    );
+  }
 }
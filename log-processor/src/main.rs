@@ -45,8 +45,12 @@ async fn main() -> Result<()> {
     args.ip_pattern.as_deref(),
     args.ip_capture_group,
   )?;
-  let queue =
-    QueuePublisher::new(&args.nats_url, args.nats_subject.clone()).await?;
+  let queue = QueuePublisher::new(
+    &args.nats_url,
+    args.nats_subject.clone(),
+    &args.stream,
+  )
+  .await?;
 
   // Create log source
   let log_source = if args.is_command_source() {
@@ -85,7 +89,7 @@ async fn main() -> Result<()> {
               info!("Found domain in log: {}", parsed.domain);
 
               match queue
-                .publish_domain(&parsed.domain, parsed.resolved_ip)
+                .publish_domain(&parsed.domain, parsed.resolved_ips)
                 .await
               {
                 Ok(()) => {
@@ -1,5 +1,8 @@
 use crate::{ProcessorError, Result};
 use async_nats::Client;
+use dns_smart_block_common::jetstream::{
+  Origin, Priority, StreamArgs, domain_headers, ensure_stream,
+};
 use serde::{Deserialize, Serialize};
 use tracing::{debug, info};
 
@@ -8,11 +11,22 @@ use tracing::{debug, info};
 pub struct DomainMessage {
   pub domain: String,
   pub timestamp: i64,
-  /// Resolved IP from the DNS log, when available.  Allows the classifier to
-  /// fetch the domain's content directly by IP instead of re-resolving through
-  /// the local DNS stack.
+  /// First resolved IP from the DNS log, for queue processors that predate
+  /// `resolved_ips`.
   #[serde(default, skip_serializing_if = "Option::is_none")]
   pub resolved_ip: Option<String>,
+  /// Resolved IPs from the DNS log, when available.  Allows the classifier to
+  /// fetch the domain's content directly by IP instead of re-resolving
+  /// through the local DNS stack.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub resolved_ips: Vec<String>,
+  /// Priority lane the message was published on.  Domains from DNS logs are
+  /// always interactive.
+  #[serde(default)]
+  pub priority: Priority,
+  /// Always `dns` for domains from DNS logs.
+  #[serde(default)]
+  pub origin: Origin,
 }
 
 /// Publishes domain messages to a NATS subject for downstream classification.
@@ -22,26 +36,39 @@ pub struct QueuePublisher {
 }
 
 impl QueuePublisher {
-  pub async fn new(nats_url: &str, subject: String) -> Result<Self> {
+  /// Connect to NATS and make sure the stream capturing `subject` exists.
+  pub async fn new(
+    nats_url: &str,
+    subject: String,
+    stream: &StreamArgs,
+  ) -> Result<Self> {
     info!("Connecting to NATS at {}", nats_url);
     let client = async_nats::connect(nats_url)
       .await
       .map_err(|e| ProcessorError::NatsError(e.to_string()))?;
     info!("Connected to NATS successfully");
 
+    let jetstream = async_nats::jetstream::new(client.clone());
+    ensure_stream(&jetstream, stream.stream_config(&subject))
+      .await
+      .map_err(|e| ProcessorError::NatsError(e.to_string()))?;
+
     Ok(Self { client, subject })
   }
 
-  /// Publish a domain to the queue, with an optional pre-resolved IP address.
+  /// Publish a domain to the queue, with any pre-resolved IP addresses.
   pub async fn publish_domain(
     &self,
     domain: &str,
-    resolved_ip: Option<String>,
+    resolved_ips: Vec<String>,
   ) -> Result<()> {
     let message = DomainMessage {
       domain: domain.to_string(),
       timestamp: chrono::Utc::now().timestamp(),
-      resolved_ip,
+      resolved_ip: resolved_ips.first().cloned(),
+      resolved_ips,
+      priority: Priority::Interactive,
+      origin: Origin::Dns,
     };
 
     let payload = serde_json::to_vec(&message)?;
@@ -50,7 +77,11 @@ impl QueuePublisher {
 
     self
       .client
-      .publish(self.subject.clone(), payload.into())
+      .publish_with_headers(
+        Priority::Interactive.subject(&self.subject),
+        domain_headers(domain),
+        payload.into(),
+      )
       .await
       .map_err(|e| ProcessorError::NatsError(e.to_string()))?;
 
@@ -62,7 +93,7 @@ impl QueuePublisher {
   /// Publish multiple domains in a batch (no resolved IPs).
   pub async fn publish_domains(&self, domains: &[String]) -> Result<()> {
     for domain in domains {
-      self.publish_domain(domain, None).await?;
+      self.publish_domain(domain, Vec::new()).await?;
     }
     Ok(())
   }
@@ -78,6 +109,9 @@ mod tests {
       domain: "example.com".to_string(),
       timestamp: 1234567890,
       resolved_ip: Some("1.2.3.4".to_string()),
+      resolved_ips: vec!["1.2.3.4".to_string(), "2001:db8::1".to_string()],
+      priority: Priority::Interactive,
+      origin: Origin::Dns,
     };
 
     let json = serde_json::to_string(&message).unwrap();
@@ -89,6 +123,7 @@ mod tests {
     assert_eq!(deserialized.domain, "example.com");
     assert_eq!(deserialized.timestamp, 1234567890);
     assert_eq!(deserialized.resolved_ip, Some("1.2.3.4".to_string()));
+    assert_eq!(deserialized.resolved_ips, vec!["1.2.3.4", "2001:db8::1"]);
   }
 
   #[test]
@@ -97,5 +132,7 @@ mod tests {
     let json = r#"{"domain":"example.com","timestamp":1234567890}"#;
     let msg: DomainMessage = serde_json::from_str(json).unwrap();
     assert_eq!(msg.resolved_ip, None);
+    assert!(msg.resolved_ips.is_empty());
+    assert_eq!(msg.priority, Priority::Interactive);
   }
 }
@@ -0,0 +1,22 @@
+-- Track when a domain last arrived from DNS traffic, separately from
+-- `last_updated`.
+--
+-- `last_updated` is bumped by every event inserted for a domain, including
+-- the `classified` event produced when the refresh scheduler re-enqueues an
+-- expiring classification.  Using it to decide which domains are still in
+-- use would keep every refreshed domain "recent" forever.  `last_seen` is
+-- only advanced when the queue-processor receives a domain from DNS traffic
+-- (origin `dns`); refreshes, admin requeues and prompt-change requeues leave
+-- it alone.  It is what the refresh scheduler filters on.
+--
+-- Existing rows start from `last_updated`, the best signal available.
+ALTER TABLE domains ADD COLUMN IF NOT EXISTS last_seen TIMESTAMPTZ;
+
+UPDATE domains SET last_seen = last_updated WHERE last_seen IS NULL;
+
+ALTER TABLE domains
+    ALTER COLUMN last_seen SET NOT NULL,
+    ALTER COLUMN last_seen SET DEFAULT NOW();
+
+CREATE INDEX IF NOT EXISTS idx_domains_last_seen
+    ON domains(last_seen DESC);
@@ -0,0 +1,11 @@
+-- Circuit breaker state of each LLM backend used by the queue-processor.
+--
+-- The queue-processor stops pulling domains from NATS while a backend is
+-- unreachable and records each open/close transition here.  The blocklist
+-- server exports the state as the `dns_smart_block_llm_circuit_open` metric,
+-- since it is the only component with a metrics endpoint.
+CREATE TABLE IF NOT EXISTS llm_backend_status (
+    backend TEXT PRIMARY KEY,
+    circuit_open BOOLEAN NOT NULL,
+    changed_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
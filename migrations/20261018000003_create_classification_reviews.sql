@@ -0,0 +1,32 @@
+-- Review queue for LLM classifications below the confidence threshold.
+--
+-- Every LLM result is projected, and the blocklist only acts on those at or
+-- above the classifier's `min_confidence`.  A borderline result below it
+-- still deserves an operator's verdict, so each one is recorded here to
+-- approve (turning it into an admin classification) or reject through the
+-- admin API.
+--
+-- At most one review per (domain, classification_type) is pending at a time:
+-- a newer borderline result replaces the pending one, and a confident result
+-- supersedes it.
+CREATE TABLE IF NOT EXISTS classification_reviews (
+    id SERIAL PRIMARY KEY,
+    domain TEXT NOT NULL REFERENCES domains(domain) ON DELETE CASCADE,
+    classification_type TEXT NOT NULL,
+    is_matching_site BOOLEAN NOT NULL,
+    confidence REAL NOT NULL,
+    reasoning TEXT,
+    model TEXT NOT NULL,
+    -- The LLM prompt source that produced the result.
+    source_id INTEGER REFERENCES classification_sources(id),
+    status TEXT NOT NULL DEFAULT 'pending'
+        CHECK (status IN ('pending', 'approved', 'rejected', 'superseded')),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    resolved_at TIMESTAMPTZ,
+    -- The user who approved or rejected the review.
+    resolved_by INTEGER REFERENCES users(id)
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_classification_reviews_pending
+    ON classification_reviews (domain, classification_type)
+    WHERE status = 'pending';
@@ -0,0 +1,22 @@
+-- LLM answers, keyed on everything they depend on.
+--
+-- A TTL refresh usually fetches a landing page that has not changed, and
+-- asking the model again about the same evidence with the same prompt is
+-- wasted time.  The classifier hashes the metadata JSON, prompt hash, model
+-- and generation options of each answer into `cache_key`.  The
+-- queue-processor hands a domain's cached answers to the classifier, which
+-- reuses one whose key matches instead of calling the model.  Only the
+-- latest answer per (domain, classification_type, model) is kept.
+CREATE TABLE IF NOT EXISTS llm_result_cache (
+    domain TEXT NOT NULL REFERENCES domains(domain) ON DELETE CASCADE,
+    classification_type TEXT NOT NULL,
+    model TEXT NOT NULL,
+    cache_key TEXT NOT NULL,
+    is_matching_site BOOLEAN NOT NULL,
+    -- Kept at full precision, so a reused answer is the answer.
+    confidence DOUBLE PRECISION NOT NULL,
+    reasoning TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_used_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (domain, classification_type, model)
+);
@@ -2,6 +2,73 @@
   cfg = config.services.dns-smart-block;
   inherit (lib) mkEnableOption mkOption mkIf types;
 
+  backendType = types.submodule {
+    options = {
+      backend = mkOption {
+        type = types.enum [ "ollama_generate" "ollama_chat" "openai_chat" ];
+        default = "ollama_generate";
+        description = ''
+          API the backend speaks:
+          - "ollama_generate" - Ollama's /api/generate
+          - "ollama_chat" - Ollama's /api/chat
+          - "openai_chat" - the OpenAI-compatible /v1/chat/completions, as
+            served by llama.cpp, vLLM, LocalAI, LM Studio and others
+        '';
+      };
+
+      url = mkOption {
+        type = types.str;
+        example = "http://llm.example:8080";
+        description = "Server URL, without the API path.";
+      };
+
+      model = mkOption {
+        type = types.str;
+        description = "Model to use for classification on this backend.";
+      };
+
+      apiKeyFile = mkOption {
+        type = types.nullOr types.str;
+        default = null;
+        example = "/run/secrets/llm-api-key";
+        description = ''
+          File holding an API key, sent to the backend as a bearer token.
+          Must be readable by the queue processor.
+        '';
+      };
+    };
+  };
+
+  proxyType = types.submodule {
+    options = {
+      url = mkOption {
+        type = types.str;
+        example = "socks5h://127.0.0.1:9050";
+        description = ''
+          Proxy URL: http://, https:// or socks5h://.  The proxy resolves
+          the fetched domains, so pre-resolved IPs are not used.
+        '';
+      };
+
+      credentialsFile = mkOption {
+        type = types.nullOr types.str;
+        default = null;
+        example = "/run/secrets/fetch-proxy";
+        description = ''
+          File holding the proxy credentials as user:password.  Must be
+          readable by the queue processor.
+        '';
+      };
+    };
+  };
+
+  # TOML inline table of a proxyType value.
+  proxyToml = proxy:
+    "{ url = \"${proxy.url}\""
+    + lib.optionalString (proxy.credentialsFile != null)
+      ", credentials_file = \"${proxy.credentialsFile}\""
+    + " }";
+
   classifierType = types.submodule {
     options = {
       enable = mkOption {
@@ -49,6 +116,15 @@
         description = "Maximum KB to download from each domain";
       };
 
+      httpProxy = mkOption {
+        type = types.nullOr proxyType;
+        default = null;
+        description = ''
+          Proxy to fetch this classifier's domains through, instead of
+          queueProcessor.httpProxy.
+        '';
+      };
+
       minConfidence = mkOption {
         type = types.float;
         default = cfg.queueProcessor.minConfidence;
@@ -60,6 +136,148 @@
         default = 30;
         description = "Time-to-live in days for cached classifications. After this many days, a domain will be re-classified.";
       };
+
+      ttl = {
+        matchingDays = mkOption {
+          type = types.nullOr types.ints.unsigned;
+          default = null;
+          description = "TTL of matching results in days.  Defaults to ttlDays.";
+        };
+
+        notMatchingDays = mkOption {
+          type = types.nullOr types.ints.unsigned;
+          default = null;
+          description = "TTL of non-matching results in days.  Defaults to ttlDays.";
+        };
+
+        scaleByConfidence = mkOption {
+          type = types.bool;
+          default = false;
+          description = ''
+            Multiply the TTL by the result's confidence, so borderline results
+            are rechecked sooner than confident ones.
+          '';
+        };
+
+        fetchErrorDays = mkOption {
+          type = types.nullOr types.ints.unsigned;
+          default = null;
+          example = 1;
+          description = ''
+            Upper bound on the TTL in days of results classified from the
+            domain name alone because the site could not be fetched.
+          '';
+        };
+      };
+
+      backend = mkOption {
+        type = types.nullOr types.str;
+        default = null;
+        example = "remote";
+        description = ''
+          Name of the entry in services.dns-smart-block.backends to classify
+          with.  Defaults to the backend configured in ollama.  Model options
+          of this classifier refer to models on the chosen backend.
+        '';
+      };
+
+      ensembleModels = mkOption {
+        type = types.listOf types.str;
+        default = [];
+        example = [ "llama3.1:8b-instruct-q4_K_M" "mistral:7b-instruct" "qwen2.5:7b-instruct" ];
+        description = ''
+          Classify each domain with all of these models instead of the global
+          ollama.model, and combine their votes with ensembleStrategy.  Every
+          model's vote is recorded in the classified event.
+        '';
+      };
+
+      ensembleStrategy = mkOption {
+        type = types.enum [ "majority" "unanimous" "confidence_weighted" ];
+        default = "majority";
+        description = ''
+          How to combine the votes of ensembleModels:
+          - "majority" - block when more than half of the models say so
+          - "unanimous" - block only when every model says so
+          - "confidence_weighted" - block when the mean confidence-weighted
+            vote favours blocking
+        '';
+      };
+
+      escalationModels = mkOption {
+        type = types.listOf types.str;
+        default = [];
+        example = [ "llama3.1:70b-instruct-q4_K_M" ];
+        description = ''
+          Larger models to re-run with, smallest first, when the result's
+          confidence lies within escalationMargin of minConfidence.  The
+          classified event records which tier decided.
+        '';
+      };
+
+      escalationMargin = mkOption {
+        type = types.float;
+        default = 0.1;
+        description = "Half-width of the confidence band around minConfidence that triggers escalation.";
+      };
+
+      onPromptChange = mkOption {
+        type = types.enum [ "ignore" "lazy" "requeue" ];
+        default = "ignore";
+        description = ''
+          What to do with current classifications made with a different
+          prompt template or model.  "ignore" keeps them until their TTL
+          expires, "lazy" reclassifies a domain the next time it is queued,
+          and "requeue" also requeues every such domain at low priority when
+          the queue processor starts.
+        '';
+      };
+
+      reuseLlmResults = mkOption {
+        type = types.bool;
+        default = true;
+        description = ''
+          Reuse a model's earlier answer for a domain when the page evidence,
+          prompt and model are unchanged, instead of asking the LLM again.
+        '';
+      };
+
+      reuseRedirectTarget = mkOption {
+        type = types.bool;
+        default = false;
+        description = ''
+          Take over the current classification of the host a domain
+          redirected to when it was last classified, instead of fetching it
+          and asking the LLM again.  Only reused while the target was
+          classified with the current prompt and model.
+        '';
+      };
+
+      shadow = {
+        enable = mkOption {
+          type = types.bool;
+          default = false;
+          description = ''
+            Run a shadow of this classifier on the same domains.  Its results
+            are recorded as events under the "shadow:<name>" type and never
+            reach the blocklist; compare them with the live results through
+            the admin API's /shadow/compare endpoint before switching over.
+          '';
+        };
+
+        customTemplate = mkOption {
+          type = types.nullOr types.path;
+          default = null;
+          description = "Prompt template for the shadow.  Defaults to the live classifier's template.";
+        };
+
+        ollamaModel = mkOption {
+          type = types.nullOr types.str;
+          default = null;
+          example = "qwen2.5:7b-instruct";
+          description = "Ollama model for the shadow.  Defaults to the global ollama.model.";
+        };
+      };
     };
   };
 
@@ -131,6 +349,72 @@ in {
           concurrency will be (number of enabled classifiers × maxAckPending).
         '';
       };
+
+      maxDeliver = mkOption {
+        type = types.int;
+        default = 5;
+        description = ''
+          Maximum number of delivery attempts for a domain message that keeps
+          failing with transient errors (database, NATS, or LLM backend
+          unreachable).  Once exhausted, the message is forwarded to
+          deadLetterSubject.
+        '';
+      };
+
+      nakDelaySec = mkOption {
+        type = types.int;
+        default = 30;
+        description = "Delay in seconds before a NAK'd domain message is redelivered.";
+      };
+
+      deadLetterSubject = mkOption {
+        type = types.str;
+        default = "dns.smart-block.dead-letter";
+        description = ''
+          NATS subject that receives domain messages which exhausted their
+          delivery attempts.  Captured by the dead-letter stream so they can
+          be inspected and replayed with natscli.
+        '';
+      };
+
+      stream = {
+        name = mkOption {
+          type = types.str;
+          default = "DNS_SMART_BLOCK";
+          description = ''
+            Name of the JetStream work-queue stream holding domain messages.
+            The services create or update it on startup; dead letters go to a
+            companion stream named after it with a _DEAD_LETTER suffix.
+          '';
+        };
+
+        maxAgeSec = mkOption {
+          type = types.int;
+          default = 604800;
+          description = "Maximum age in seconds of a queued domain message (0 for no limit).";
+        };
+
+        maxBytes = mkOption {
+          type = types.int;
+          default = -1;
+          description = "Maximum size of the domain queue in bytes (-1 for no limit).";
+        };
+
+        duplicateWindowSec = mkOption {
+          type = types.int;
+          default = 120;
+          description = ''
+            Window in seconds within which repeated publishes of the same
+            domain are dropped by JetStream.
+          '';
+        };
+
+        deadLetterMaxAgeSec = mkOption {
+          type = types.int;
+          default = 2592000;
+          description = "Maximum age in seconds of a dead-lettered message (0 for no limit).";
+        };
+      };
     };
 
     # Log Processor Configuration
@@ -202,12 +486,12 @@ in {
       ipPattern = mkOption {
         type = types.nullOr types.str;
         default = null;
-        example = ''answer=(?:A|AAAA) \(([0-9a-fA-F:.]+)\)'';
+        example = ''\b(?:A|AAAA) \(([0-9a-fA-F:.]+)\)'';
         description = ''
-          Optional regex to extract the resolved IP address from a log line.
-          When set, the captured IP is forwarded through the pipeline so the
+          Optional regex to extract the resolved IP addresses from a log line.
+          When set, every captured IP is forwarded through the pipeline so the
           classifier can fetch the domain's content by connecting directly to
-          that IP, avoiding a second DNS lookup through the local resolver.
+          those IPs, avoiding a second DNS lookup through the local resolver.
         '';
       };
 
@@ -280,19 +564,221 @@ in {
         description = "Default maximum KB to download from each domain";
       };
 
+      httpProxy = mkOption {
+        type = types.nullOr proxyType;
+        default = null;
+        description = ''
+          Proxy to fetch domains through, so that they do not see this host's
+          address.  Only page fetches use it, not LLM requests.
+        '';
+      };
+
+      httpCache = {
+        enable = mkOption {
+          type = types.bool;
+          default = true;
+          description = ''
+            Cache fetched landing pages under /var/cache/dns-smart-block, so
+            that requeues after a prompt change and other classifiers of the
+            same domain only cost LLM time.
+          '';
+        };
+
+        maxAgeSec = mkOption {
+          type = types.ints.positive;
+          default = 86400;
+          description = "Maximum age of a cached page (seconds).";
+        };
+
+        maxMb = mkOption {
+          type = types.ints.positive;
+          default = 100;
+          description = "Maximum size of the page cache; the oldest pages are evicted beyond it.";
+        };
+      };
+
       minConfidence = mkOption {
         type = types.float;
         default = 0.8;
         description = "Default minimum confidence threshold to block (0.0 to 1.0)";
       };
+
+      refresh = {
+        enable = mkOption {
+          type = types.bool;
+          default = true;
+          description = ''
+            Re-enqueue matching classifications shortly before they expire, for
+            domains still seen in DNS traffic, so blocked domains do not
+            resolve between expiry and reclassification.  Refreshes are
+            published on the low-priority subject and never delay domains
+            seen for the first time.
+          '';
+        };
+
+        intervalSec = mkOption {
+          type = types.ints.positive;
+          default = 3600;
+          description = "How often to look for expiring classifications (seconds).";
+        };
+
+        windowHours = mkOption {
+          type = types.ints.positive;
+          default = 24;
+          description = "Refresh classifications expiring within this many hours.";
+        };
+
+        seenWithinDays = mkOption {
+          type = types.ints.positive;
+          default = 7;
+          description = "Only refresh domains seen in DNS traffic within this many days.";
+        };
+
+        batchSize = mkOption {
+          type = types.ints.positive;
+          default = 500;
+          description = "Maximum number of domains to re-enqueue per run.";
+        };
+      };
+
+      circuitBreaker = {
+        failureThreshold = mkOption {
+          type = types.ints.positive;
+          default = 3;
+          description = ''
+            Consecutive LLM backend connection failures after which the
+            queue processor stops pulling messages, leaving them in
+            JetStream, until a health probe succeeds.
+          '';
+        };
+
+        probeIntervalSec = mkOption {
+          type = types.ints.positive;
+          default = 30;
+          description = "How often to probe the LLM backend while the circuit is open (seconds).";
+        };
+
+        probeTimeoutSec = mkOption {
+          type = types.ints.positive;
+          default = 10;
+          description = "Timeout of a single LLM backend health probe (seconds).";
+        };
+      };
+
+      extraction = {
+        bodyTextChars = mkOption {
+          type = types.ints.unsigned;
+          default = 1000;
+          description = "Characters of visible page text passed to the LLM (0 to omit).";
+        };
+
+        maxHeadings = mkOption {
+          type = types.ints.unsigned;
+          default = 10;
+          description = "Number of h1/h2 headings passed to the LLM.";
+        };
+
+        fieldChars = mkOption {
+          type = types.ints.unsigned;
+          default = 300;
+          description = ''
+            Characters of each heading, keyword list, Twitter card field and
+            JSON-LD name or description passed to the LLM.
+          '';
+        };
+
+        maxJsonLd = mkOption {
+          type = types.ints.unsigned;
+          default = 3;
+          description = "Number of schema.org JSON-LD entities passed to the LLM.";
+        };
+
+        maxLinkDomains = mkOption {
+          type = types.ints.unsigned;
+          default = 10;
+          description = "Number of outbound link domains passed to the LLM.";
+        };
+      };
+
+      egress.allow = mkOption {
+        type = types.listOf types.str;
+        default = [ ];
+        example = [ "192.168.10.0/24" ];
+        description = ''
+          Networks, in CIDR notation, the classifier may fetch pages from even
+          though they are private, loopback, link-local, CGNAT, benchmarking,
+          reserved or multicast.
+          Fetches to those ranges are refused otherwise, including on
+          redirects, and recorded as EgressBlocked errors.
+        '';
+      };
+
+      promptChange.requeueRatePerSec = mkOption {
+        type = types.ints.positive;
+        default = 10;
+        description = ''
+          Maximum number of domains per second requeued at startup for
+          classifiers with onPromptChange = "requeue".
+        '';
+      };
+
+      nxdomain = {
+        enable = mkOption {
+          type = types.bool;
+          default = false;
+          description = ''
+            Classify domains that do not exist (NXDOMAIN) as not matching
+            without fetching them or invoking the LLM.  Requires resolver.
+          '';
+        };
+
+        resolver = mkOption {
+          type = types.nullOr types.str;
+          default = null;
+          example = "9.9.9.9:53";
+          description = ''
+            Upstream resolver (IP:port) used only for the NXDOMAIN check.  It
+            must not be the local DNS server whose logs feed the queue, or
+            every check is logged and the domain enqueued again.
+          '';
+        };
+
+        timeoutSec = mkOption {
+          type = types.ints.positive;
+          default = 5;
+          description = "Timeout of a single NXDOMAIN lookup (seconds).";
+        };
+      };
     };
 
-    # Ollama Configuration
+    # Default LLM backend Configuration
     ollama = {
+      backend = mkOption {
+        type = types.enum [ "ollama_generate" "ollama_chat" "openai_chat" ];
+        default = "ollama_generate";
+        description = ''
+          API the backend speaks:
+          - "ollama_generate" - Ollama's /api/generate
+          - "ollama_chat" - Ollama's /api/chat
+          - "openai_chat" - the OpenAI-compatible /v1/chat/completions, as
+            served by llama.cpp, vLLM, LocalAI, LM Studio and others
+        '';
+      };
+
       url = mkOption {
         type = types.str;
         default = "http://localhost:11434";
-        description = "Ollama server URL";
+        description = "Server URL of the default LLM backend";
+      };
+
+      apiKeyFile = mkOption {
+        type = types.nullOr types.str;
+        default = null;
+        example = "/run/secrets/llm-api-key";
+        description = ''
+          File holding an API key, sent to the backend as a bearer token.
+          Must be readable by the queue processor.
+        '';
       };
 
       model = mkOption {
@@ -314,6 +800,23 @@ in {
       };
     };
 
+    backends = mkOption {
+      type = types.attrsOf backendType;
+      default = {};
+      example = {
+        remote = {
+          backend = "openai_chat";
+          url = "http://llm.example:8080";
+          model = "qwen2.5-7b-instruct";
+          apiKeyFile = "/run/secrets/llm-api-key";
+        };
+      };
+      description = ''
+        Additional LLM backends, by name.  Classifiers select one with their
+        backend option; the rest use the backend configured in ollama.
+      '';
+    };
+
     # Provisioned Classification Overrides
     provisionedClassifications = mkOption {
       type = types.listOf (types.submodule {
@@ -551,6 +1054,16 @@ in {
       # Systemd interprets % as a specifier, so escape it as %%.
       lib.replaceStrings ["%"] ["%%"] rawUrl;
 
+    # JetStream stream settings shared by every service that provisions the
+    # domain queue stream.
+    natsStreamArgs = [
+      "--nats-stream '${cfg.nats.stream.name}'"
+      "--nats-stream-max-age-sec ${toString cfg.nats.stream.maxAgeSec}"
+      "--nats-stream-max-bytes ${toString cfg.nats.stream.maxBytes}"
+      "--nats-duplicate-window-sec ${toString cfg.nats.stream.duplicateWindowSec}"
+      "--nats-dead-letter-max-age-sec ${toString cfg.nats.stream.deadLetterMaxAgeSec}"
+    ];
+
     # Get list of enabled classifiers.
     enabledClassifiers = lib.filterAttrs (name: classifier: classifier.enable) cfg.classifiers;
 
@@ -574,14 +1087,51 @@ in {
           [[classifier]]
           name = "${classifierName}"
           prompt_template = "${promptTemplate}"
+          ${lib.optionalString (classifier.backend != null)
+            "backend = \"${classifier.backend}\""}
           ${lib.optionalString (classifier.minConfidence != cfg.queueProcessor.minConfidence)
             "min_confidence = ${toString classifier.minConfidence}"}
           ${lib.optionalString (classifier.ttlDays != 7)
             "ttl_days = ${toString classifier.ttlDays}"}
+          ${lib.optionalString (classifier.ttl.matchingDays != null)
+            "ttl_days_matching = ${toString classifier.ttl.matchingDays}"}
+          ${lib.optionalString (classifier.ttl.notMatchingDays != null)
+            "ttl_days_not_matching = ${toString classifier.ttl.notMatchingDays}"}
+          ${lib.optionalString classifier.ttl.scaleByConfidence
+            "ttl_scale_by_confidence = true"}
+          ${lib.optionalString (classifier.ttl.fetchErrorDays != null)
+            "ttl_days_fetch_error = ${toString classifier.ttl.fetchErrorDays}"}
           ${lib.optionalString (classifier.httpTimeoutSec != cfg.queueProcessor.httpTimeoutSec)
             "http_timeout_sec = ${toString classifier.httpTimeoutSec}"}
           ${lib.optionalString (classifier.httpMaxKb != cfg.queueProcessor.httpMaxKb)
             "http_max_kb = ${toString classifier.httpMaxKb}"}
+          ${lib.optionalString (classifier.httpProxy != null)
+            "http_proxy = ${proxyToml classifier.httpProxy}"}
+          ${lib.optionalString (classifier.ensembleModels != [])
+            "ollama_models = ${builtins.toJSON classifier.ensembleModels}"}
+          ${lib.optionalString (classifier.ensembleModels != [])
+            "ensemble_strategy = \"${classifier.ensembleStrategy}\""}
+          ${lib.optionalString (classifier.escalationModels != [])
+            "escalation_models = ${builtins.toJSON classifier.escalationModels}"}
+          ${lib.optionalString (classifier.escalationModels != [])
+            "escalation_margin = ${toString classifier.escalationMargin}"}
+          ${lib.optionalString (classifier.onPromptChange != "ignore")
+            "on_prompt_change = \"${classifier.onPromptChange}\""}
+          ${lib.optionalString classifier.reuseRedirectTarget
+            "reuse_redirect_target = true"}
+          ${lib.optionalString (!classifier.reuseLlmResults)
+            "reuse_llm_results = false"}
+          ${lib.optionalString classifier.shadow.enable ''
+
+          [[classifier]]
+          name = "${classifierName}"
+          shadow = true
+          prompt_template = "${if classifier.shadow.customTemplate != null then classifier.shadow.customTemplate else promptTemplate}"
+          ${lib.optionalString (classifier.backend != null)
+            "backend = \"${classifier.backend}\""}
+          ${lib.optionalString (classifier.shadow.ollamaModel != null)
+            "ollama_model = \"${classifier.shadow.ollamaModel}\""}
+          ''}
         '') enabledClassifiers
       );
     in pkgs.writeText "dns-smart-block-queue-processor.toml" ''
@@ -589,13 +1139,32 @@ in {
       # Generated by NixOS module
 
       [ollama]
+      backend = "${cfg.ollama.backend}"
       url = "${cfg.ollama.url}"
       model = "${cfg.ollama.model}"
       ${lib.optionalString (cfg.ollama.numCtx != null) "num_ctx = ${toString cfg.ollama.numCtx}"}
+      ${lib.optionalString (cfg.ollama.apiKeyFile != null)
+        "api_key_file = \"${cfg.ollama.apiKeyFile}\""}
+
+      ${lib.concatStringsSep "\n" (lib.mapAttrsToList (name: backend: ''
+      [backends.${name}]
+      backend = "${backend.backend}"
+      url = "${backend.url}"
+      model = "${backend.model}"
+      ${lib.optionalString (backend.apiKeyFile != null)
+        "api_key_file = \"${backend.apiKeyFile}\""}
+      '') cfg.backends)}
 
       [http]
       timeout_sec = ${toString cfg.queueProcessor.httpTimeoutSec}
       max_kb = ${toString cfg.queueProcessor.httpMaxKb}
+      ${lib.optionalString (cfg.queueProcessor.httpProxy != null)
+        "proxy = ${proxyToml cfg.queueProcessor.httpProxy}"}
+      ${lib.optionalString cfg.queueProcessor.httpCache.enable ''
+        cache_dir = "/var/cache/dns-smart-block"
+        cache_max_age_sec = ${toString cfg.queueProcessor.httpCache.maxAgeSec}
+        cache_max_mb = ${toString cfg.queueProcessor.httpCache.maxMb}
+      ''}
 
       [defaults]
       min_confidence = ${toString cfg.queueProcessor.minConfidence}
@@ -605,9 +1174,55 @@ in {
       exclude_suffixes = ${builtins.toJSON cfg.excludeSuffixes}
       ''}
 
+      [refresh]
+      enabled = ${lib.boolToString cfg.queueProcessor.refresh.enable}
+      interval_sec = ${toString cfg.queueProcessor.refresh.intervalSec}
+      window_hours = ${toString cfg.queueProcessor.refresh.windowHours}
+      seen_within_days = ${toString cfg.queueProcessor.refresh.seenWithinDays}
+      batch_size = ${toString cfg.queueProcessor.refresh.batchSize}
+
+      [circuit_breaker]
+      failure_threshold = ${toString cfg.queueProcessor.circuitBreaker.failureThreshold}
+      probe_interval_sec = ${toString cfg.queueProcessor.circuitBreaker.probeIntervalSec}
+      probe_timeout_sec = ${toString cfg.queueProcessor.circuitBreaker.probeTimeoutSec}
+
+      [extraction]
+      body_text_chars = ${toString cfg.queueProcessor.extraction.bodyTextChars}
+      max_headings = ${toString cfg.queueProcessor.extraction.maxHeadings}
+      field_chars = ${toString cfg.queueProcessor.extraction.fieldChars}
+      max_json_ld = ${toString cfg.queueProcessor.extraction.maxJsonLd}
+      max_link_domains = ${toString cfg.queueProcessor.extraction.maxLinkDomains}
+
+      [egress]
+      allow = ${builtins.toJSON cfg.queueProcessor.egress.allow}
+
+      [prompt_change]
+      requeue_rate_per_sec = ${toString cfg.queueProcessor.promptChange.requeueRatePerSec}
+
+      [nxdomain]
+      enabled = ${lib.boolToString cfg.queueProcessor.nxdomain.enable}
+      ${lib.optionalString (cfg.queueProcessor.nxdomain.resolver != null)
+        "resolver = \"${cfg.queueProcessor.nxdomain.resolver}\""}
+      timeout_sec = ${toString cfg.queueProcessor.nxdomain.timeoutSec}
+
       ${classifierSections}
     '';
 
+    # API key files of all LLM backends (for ReadOnlyPaths).
+    allApiKeyFiles = lib.filter (f: f != null) (
+      [ cfg.ollama.apiKeyFile ]
+      ++ lib.mapAttrsToList (_: backend: backend.apiKeyFile) cfg.backends
+    );
+
+    # Credential files of all fetch proxies (for ReadOnlyPaths).
+    allProxyCredentialFiles = lib.filter (f: f != null) (
+      map (proxy: proxy.credentialsFile) (lib.filter (p: p != null) (
+        [ cfg.queueProcessor.httpProxy ]
+        ++ lib.mapAttrsToList (_: classifier: classifier.httpProxy)
+          enabledClassifiers
+      ))
+    );
+
     # All prompt templates referenced in the config (for ReadOnlyPaths).
     allPromptTemplates = lib.flatten (
       lib.mapAttrsToList (classifierName: classifier:
@@ -620,6 +1235,11 @@ in {
         else
           classifier.customTemplate
       ) enabledClassifiers
+      ++ lib.mapAttrsToList (classifierName: classifier:
+        lib.optional
+          (classifier.shadow.enable && classifier.shadow.customTemplate != null)
+          classifier.shadow.customTemplate
+      ) enabledClassifiers
     );
 
   in {
@@ -689,9 +1309,9 @@ in {
         description = "DNS Smart Block Queue Processor";
         wantedBy = [ "multi-user.target" ];
         after = [ "network.target" ]
-                ++ lib.optional cfg.nats.enable "dns-smart-block-nats-init.service"
+                ++ lib.optional cfg.nats.enable "nats.service"
                 ++ lib.optional cfg.database.enable "postgresql.service";
-        wants = lib.optional cfg.nats.enable "dns-smart-block-nats-init.service"
+        wants = lib.optional cfg.nats.enable "nats.service"
                 ++ lib.optional cfg.database.enable "postgresql.service";
         requires = lib.optional cfg.database.enable "postgresql.service";
 
@@ -707,10 +1327,13 @@ in {
               "--nats-url '${cfg.nats.url}'"
               "--nats-subject '${cfg.nats.subject}'"
               "--nats-max-ack-pending ${toString cfg.nats.maxAckPending}"
+              "--nats-max-deliver ${toString cfg.nats.maxDeliver}"
+              "--nats-nak-delay-sec ${toString cfg.nats.nakDelaySec}"
+              "--nats-dead-letter-subject '${cfg.nats.deadLetterSubject}'"
               "--database-url '${databaseUrl}'"
               "--classifier-path '${packages.classifier}/bin/dns-smart-block-classifier'"
               "--config-file '${queueProcessorTomlConfig}'"
-            ] ++ lib.optionals (cfg.database.passwordFile != null) [
+            ] ++ natsStreamArgs ++ lib.optionals (cfg.database.passwordFile != null) [
               "--database-password-file '${cfg.database.passwordFile}'"
             ]);
           in args;
@@ -724,8 +1347,13 @@ in {
           ProtectSystem = "strict";
           ProtectHome = true;
 
-          # Read access to config file and all prompt templates.
-          ReadOnlyPaths = [ queueProcessorTomlConfig ] ++ allPromptTemplates;
+          # Read access to config file, all prompt templates, API keys and
+          # proxy credentials.
+          ReadOnlyPaths = [ queueProcessorTomlConfig ] ++ allPromptTemplates
+            ++ allApiKeyFiles ++ allProxyCredentialFiles;
+
+          # Fetched page cache, shared by the classifier runs.
+          CacheDirectory = mkIf cfg.queueProcessor.httpCache.enable "dns-smart-block";
         };
 
         environment = {
@@ -739,12 +1367,12 @@ in {
         wantedBy = [ "multi-user.target" ];
         after =
           [ "network.target" ]
-          ++ lib.optional cfg.nats.enable "dns-smart-block-nats-init.service"
+          ++ lib.optional cfg.nats.enable "nats.service"
           ++ lib.optional
             (lib.hasPrefix "cmd:journalctl" cfg.logProcessor.logSource)
             "systemd-journald.service"
         ;
-        wants = lib.optional cfg.nats.enable "dns-smart-block-nats-init.service";
+        wants = lib.optional cfg.nats.enable "nats.service";
 
         serviceConfig = {
           Type = "notify";
@@ -765,7 +1393,8 @@ in {
               "--domain-capture-group ${toString cfg.logProcessor.domainCaptureGroup}"
               "--nats-url '${cfg.nats.url}'"
               "--nats-subject '${cfg.nats.subject}'"
-            ] ++ lib.optional (cfg.logProcessor.lineFilter != null)
+            ] ++ natsStreamArgs
+            ++ lib.optional (cfg.logProcessor.lineFilter != null)
               "--line-filter '${cfg.logProcessor.lineFilter}'"
             ++ lib.optional (cfg.logProcessor.ipPattern != null)
               "--ip-pattern '${cfg.logProcessor.ipPattern}'"
@@ -805,37 +1434,6 @@ in {
         };
       };
 
-      # Idempotently create the DNS_SMART_BLOCK JetStream stream after NATS
-      # starts.  The || true makes the command succeed even if the stream
-      # already exists, so this is safe to run on every activation.
-      dns-smart-block-nats-init = mkIf cfg.nats.enable {
-        description = "Initialize DNS Smart Block NATS stream";
-        wantedBy = [ "multi-user.target" ];
-        after = [ "nats.service" ];
-        requires = [ "nats.service" ];
-        serviceConfig = {
-          Type = "oneshot";
-          RemainAfterExit = true;
-          ExecStart = pkgs.writeShellScript "dns-smart-block-nats-init" ''
-            # Poll until the NATS TCP port accepts connections.
-            until (echo >/dev/tcp/localhost/4222) 2>/dev/null; do
-              sleep 1
-            done
-            ${pkgs.natscli}/bin/nats --server=${cfg.nats.url} stream add DNS_SMART_BLOCK \
-              --subjects="${cfg.nats.subject}" \
-              --storage=file \
-              --retention=limits \
-              --max-age=7d \
-              --dupe-window=2m \
-              --replicas=1 \
-              --defaults || true
-          '';
-          NoNewPrivileges = true;
-          PrivateTmp = true;
-          ProtectSystem = "strict";
-          ProtectHome = true;
-        };
-      };
       # Reconcile provisioned classifications after the blocklist server is up.
       dns-smart-block-provisioned-classifications =
         mkIf (cfg.blocklistServer.enable
@@ -924,11 +1522,11 @@ in {
         wantedBy = [ "multi-user.target" ];
         after = [ "network.target" ]
                 ++ lib.optional cfg.database.enable "postgresql.service"
-                ++ lib.optional cfg.nats.enable "dns-smart-block-nats-init.service"
+                ++ lib.optional cfg.nats.enable "nats.service"
                 ++ lib.optional usePublicSocket
                      "dns-smart-block-blocklist-server.socket";
         wants = lib.optional cfg.database.enable "postgresql.service"
-                ++ lib.optional cfg.nats.enable "dns-smart-block-nats-init.service";
+                ++ lib.optional cfg.nats.enable "nats.service";
         requires = lib.optional cfg.database.enable "postgresql.service"
                    ++ lib.optional usePublicSocket
                         "dns-smart-block-blocklist-server.socket";
@@ -953,7 +1551,12 @@ in {
               "--admin-listen '${adminListen}'"
               "--nats-url '${cfg.nats.url}'"
               "--nats-subject '${cfg.nats.subject}'"
-            ] ++ lib.optionals (cfg.database.passwordFile != null) [
+              "--min-confidence ${toString cfg.queueProcessor.minConfidence}"
+            ] ++ natsStreamArgs
+            ++ lib.mapAttrsToList (name: classifier:
+              "--type-min-confidence '${name}=${toString classifier.minConfidence}'"
+            ) enabledClassifiers
+            ++ lib.optionals (cfg.database.passwordFile != null) [
               "--database-password-file '${cfg.database.passwordFile}'"
             ]);
           in args;
@@ -985,6 +1588,23 @@ in {
           override classification type.  Choose a different name.
         '';
       }
+      {
+        assertion = lib.all (classifier:
+          classifier.backend == null || cfg.backends ? ${classifier.backend}
+        ) (lib.attrValues enabledClassifiers);
+        message = ''
+          dns-smart-block: every classifier's backend must name an entry in
+          services.dns-smart-block.backends.
+        '';
+      }
+      {
+        assertion = cfg.queueProcessor.nxdomain.enable
+          -> cfg.queueProcessor.nxdomain.resolver != null;
+        message = ''
+          dns-smart-block: queueProcessor.nxdomain.resolver must be set when
+          queueProcessor.nxdomain.enable is true.
+        '';
+      }
       {
         assertion = lib.all (mc:
           (mc.domain != null) != (mc.pattern != null)
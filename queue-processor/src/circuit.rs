@@ -0,0 +1,88 @@
+//! Circuit breaker around the LLM backend.
+//!
+//! When the backend is down every domain fails with a transient error after
+//! its page has already been fetched, and the message is NAK'd only to fail
+//! the same way on redelivery.  After `failure_threshold` consecutive
+//! backend failures the circuit opens: the main loop stops pulling messages,
+//! leaving them in JetStream, and probes the backend until it answers again.
+
+/// Tracks consecutive backend failures.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+  failure_threshold: u32,
+  consecutive_failures: u32,
+  open: bool,
+}
+
+impl CircuitBreaker {
+  pub fn new(failure_threshold: u32) -> Self {
+    Self {
+      failure_threshold,
+      consecutive_failures: 0,
+      open: false,
+    }
+  }
+
+  /// The backend answered; any run of failures is over.
+  pub fn record_success(&mut self) {
+    self.consecutive_failures = 0;
+  }
+
+  /// The backend could not be reached.  Returns `true` when this failure
+  /// opens the circuit.
+  pub fn record_failure(&mut self) -> bool {
+    self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+    if !self.open && self.consecutive_failures >= self.failure_threshold {
+      self.open = true;
+      return true;
+    }
+    false
+  }
+
+  pub fn is_open(&self) -> bool {
+    self.open
+  }
+
+  /// The backend passed a health probe; resume consumption.
+  pub fn close(&mut self) {
+    self.open = false;
+    self.consecutive_failures = 0;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_opens_after_consecutive_failures() {
+    let mut breaker = CircuitBreaker::new(3);
+    assert!(!breaker.record_failure());
+    assert!(!breaker.record_failure());
+    assert!(!breaker.is_open());
+    assert!(breaker.record_failure());
+    assert!(breaker.is_open());
+    // Further failures do not re-open an open circuit.
+    assert!(!breaker.record_failure());
+  }
+
+  #[test]
+  fn test_success_resets_failure_count() {
+    let mut breaker = CircuitBreaker::new(2);
+    assert!(!breaker.record_failure());
+    breaker.record_success();
+    assert!(!breaker.record_failure());
+    assert!(!breaker.is_open());
+  }
+
+  #[test]
+  fn test_close_resumes_with_fresh_count() {
+    let mut breaker = CircuitBreaker::new(2);
+    breaker.record_failure();
+    assert!(breaker.record_failure());
+    breaker.close();
+    assert!(!breaker.is_open());
+    assert!(!breaker.record_failure());
+    assert!(breaker.record_failure());
+  }
+}
@@ -1,5 +1,19 @@
-use dns_smart_block_common::db::ALL_CLASSIFICATION_TYPE;
+use dns_smart_block_classifier::backend::{
+  BackendKind, LlmBackend, read_api_key,
+};
+use dns_smart_block_classifier::cache::FetchCacheConfig;
+use dns_smart_block_classifier::egress::EgressPolicy;
+use dns_smart_block_classifier::ensemble::EnsembleStrategy;
+use dns_smart_block_classifier::escalation::UncertaintyBand;
+use dns_smart_block_classifier::proxy::ProxyConfig;
+use dns_smart_block_classifier::web_classify::ExtractionLimits;
+use dns_smart_block_common::db::{
+  ALL_CLASSIFICATION_TYPE, shadow_classification_type,
+};
+use dns_smart_block_common::ttl::TtlPolicy;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
 use thiserror::Error;
 
@@ -15,14 +29,33 @@ pub enum ConfigError {
   ValidationError(String),
 }
 
-/// Global Ollama LLM configuration shared by all classifiers.
+/// An LLM backend: the default one in `[ollama]`, or a named one in
+/// `[backends.<name>]` that classifiers select with `backend`.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct OllamaConfig {
-  /// Ollama server URL (e.g., "http://localhost:11434")
+pub struct BackendConfig {
+  /// API the backend speaks
+  #[serde(default)]
+  pub backend: BackendKind,
+
+  /// Server URL, without the API path (e.g., "http://localhost:11434")
   pub url: String,
 
-  /// Default Ollama model to use for classification (e.g., "llama3.2:3b")
+  /// Default model to use for classification (e.g., "llama3.2:3b")
   pub model: String,
+
+  /// File holding an API key, sent as a bearer token (optional)
+  pub api_key_file: Option<PathBuf>,
+}
+
+impl BackendConfig {
+  /// The client for this backend, with its API key read from disk.
+  pub fn llm_backend(&self) -> Result<LlmBackend, String> {
+    let api_key = match &self.api_key_file {
+      Some(path) => Some(read_api_key(path).map_err(|e| e.to_string())?),
+      None => None,
+    };
+    Ok(LlmBackend::new(self.backend, &self.url, api_key))
+  }
 }
 
 /// Global HTTP fetching configuration.
@@ -35,6 +68,17 @@ pub struct HttpConfig {
   /// Maximum KB to download from each domain
   #[serde(default = "default_http_max_kb")]
   pub max_kb: usize,
+
+  /// Proxy to fetch domains through (optional)
+  pub proxy: Option<ProxyConfig>,
+
+  /// On-disk cache of fetched pages, shared by all classifiers
+  #[serde(flatten)]
+  pub cache: FetchCacheConfig,
+}
+
+fn default_reuse_llm_results() -> bool {
+  true
 }
 
 fn default_http_timeout_sec() -> u64 {
@@ -65,6 +109,131 @@ fn default_ttl_days() -> i64 {
   10
 }
 
+/// Proactive refresh of matching classifications that are about to expire.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RefreshConfig {
+  /// Enable the refresh scheduler
+  #[serde(default = "default_refresh_enabled")]
+  pub enabled: bool,
+
+  /// How often to look for expiring classifications (seconds)
+  #[serde(default = "default_refresh_interval_sec")]
+  pub interval_sec: u64,
+
+  /// Refresh classifications expiring within this many hours
+  #[serde(default = "default_refresh_window_hours")]
+  pub window_hours: i64,
+
+  /// Only refresh domains seen in DNS traffic within this many days
+  #[serde(default = "default_refresh_seen_within_days")]
+  pub seen_within_days: i64,
+
+  /// Maximum number of domains to re-enqueue per run
+  #[serde(default = "default_refresh_batch_size")]
+  pub batch_size: i64,
+}
+
+fn default_refresh_enabled() -> bool {
+  true
+}
+
+fn default_refresh_interval_sec() -> u64 {
+  3600
+}
+
+fn default_refresh_window_hours() -> i64 {
+  24
+}
+
+fn default_refresh_seen_within_days() -> i64 {
+  7
+}
+
+fn default_refresh_batch_size() -> i64 {
+  500
+}
+
+/// Circuit breaker around the LLM backend.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CircuitBreakerConfig {
+  /// Consecutive backend failures that open the circuit
+  #[serde(default = "default_circuit_failure_threshold")]
+  pub failure_threshold: u32,
+
+  /// How often to probe the backend while the circuit is open (seconds)
+  #[serde(default = "default_circuit_probe_interval_sec")]
+  pub probe_interval_sec: u64,
+
+  /// Timeout of a single health probe (seconds)
+  #[serde(default = "default_circuit_probe_timeout_sec")]
+  pub probe_timeout_sec: u64,
+}
+
+fn default_circuit_failure_threshold() -> u32 {
+  3
+}
+
+fn default_circuit_probe_interval_sec() -> u64 {
+  30
+}
+
+fn default_circuit_probe_timeout_sec() -> u64 {
+  10
+}
+
+/// NXDOMAIN check against a dedicated upstream resolver.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NxdomainConfig {
+  /// Check whether a domain exists before classifying it
+  #[serde(default)]
+  pub enabled: bool,
+
+  /// Upstream resolver (IP:port) used only for the check.  Must not be the
+  /// local DNS server whose logs feed the queue.
+  pub resolver: Option<SocketAddr>,
+
+  /// Timeout of a single lookup (seconds)
+  #[serde(default = "default_nxdomain_timeout_sec")]
+  pub timeout_sec: u64,
+}
+
+fn default_nxdomain_timeout_sec() -> u64 {
+  5
+}
+
+/// Reclassification of domains whose current classification was made with a
+/// prompt or model the classifier no longer uses.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptChangeConfig {
+  /// Maximum number of stale domains requeued per second at startup
+  #[serde(default = "default_prompt_change_requeue_rate_per_sec")]
+  pub requeue_rate_per_sec: u32,
+}
+
+fn default_prompt_change_requeue_rate_per_sec() -> u32 {
+  10
+}
+
+/// What to do with current classifications once a classifier's prompt or
+/// model changes.
+#[derive(
+  Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum OnPromptChange {
+  /// Keep serving old classifications until their TTL expires
+  #[default]
+  Ignore,
+  /// Reclassify a domain the next time it is queued
+  Lazy,
+  /// Like `lazy`, and also requeue every stale domain at low priority on
+  /// startup
+  Requeue,
+}
+
+/// Default half-width of the escalation uncertainty band.
+const DEFAULT_ESCALATION_MARGIN: f64 = 0.1;
+
 /// Individual classifier configuration.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClassifierConfig {
@@ -74,29 +243,128 @@ pub struct ClassifierConfig {
   /// Path to prompt template file
   pub prompt_template: PathBuf,
 
+  /// Name of the `[backends.<name>]` entry to classify with (optional,
+  /// defaults to `[ollama]`)
+  pub backend: Option<String>,
+
   /// Override Ollama model for this classifier (optional)
   pub ollama_model: Option<String>,
 
+  /// Classify with an ensemble of models instead of a single one (optional).
+  /// Mutually exclusive with `ollama_model`.
+  pub ollama_models: Option<Vec<String>>,
+
+  /// How to combine the votes of `ollama_models`
+  #[serde(default)]
+  pub ensemble_strategy: EnsembleStrategy,
+
+  /// Larger models to re-run with, smallest first, while the result's
+  /// confidence lies within `escalation_margin` of the effective
+  /// `min_confidence` (optional)
+  #[serde(default)]
+  pub escalation_models: Vec<String>,
+
+  /// Half-width of the uncertainty band around the effective
+  /// `min_confidence` that triggers escalation (optional)
+  pub escalation_margin: Option<f64>,
+
+  /// Run as a shadow of the live classifier with the same name: results are
+  /// recorded as events under the shadow classification type and never
+  /// projected, so they cannot affect the blocklist
+  #[serde(default)]
+  pub shadow: bool,
+
+  /// What to do with current classifications made with a different prompt
+  /// or model
+  #[serde(default)]
+  pub on_prompt_change: OnPromptChange,
+
+  /// Take over the current classification of the host a domain redirected
+  /// to when it was last classified, instead of classifying it again
+  #[serde(default)]
+  pub reuse_redirect_target: bool,
+
+  /// Reuse a model's earlier answer for this domain when the evidence,
+  /// prompt and model are unchanged, instead of asking it again
+  #[serde(default = "default_reuse_llm_results")]
+  pub reuse_llm_results: bool,
+
   /// Override minimum confidence for this classifier (optional)
   pub min_confidence: Option<f64>,
 
   /// Override TTL for this classifier (optional)
   pub ttl_days: Option<i64>,
 
+  /// TTL of matching results, overriding `ttl_days` (optional)
+  pub ttl_days_matching: Option<i64>,
+
+  /// TTL of non-matching results, overriding `ttl_days` (optional)
+  pub ttl_days_not_matching: Option<i64>,
+
+  /// Multiply the TTL by the result's confidence
+  #[serde(default)]
+  pub ttl_scale_by_confidence: bool,
+
+  /// Upper bound on the TTL of results classified without the site's
+  /// content because fetching it failed (optional)
+  pub ttl_days_fetch_error: Option<i64>,
+
   /// Override HTTP timeout for this classifier (optional)
   pub http_timeout_sec: Option<u64>,
 
   /// Override HTTP max KB for this classifier (optional)
   pub http_max_kb: Option<usize>,
+
+  /// Override the HTTP proxy for this classifier (optional)
+  pub http_proxy: Option<ProxyConfig>,
 }
 
 impl ClassifierConfig {
-  /// Get the effective Ollama model for this classifier.
-  pub fn effective_ollama_model(&self, global: &OllamaConfig) -> String {
+  /// The classification type this classifier records its results under.
+  pub fn classification_type(&self) -> String {
+    if self.shadow {
+      shadow_classification_type(&self.name)
+    } else {
+      self.name.clone()
+    }
+  }
+
+  /// Get the backend this classifier runs on.
+  pub fn effective_backend<'a>(&self, config: &'a Config) -> &'a BackendConfig {
     self
-      .ollama_model
-      .clone()
-      .unwrap_or_else(|| global.model.clone())
+      .backend
+      .as_ref()
+      .and_then(|name| config.backends.get(name))
+      .unwrap_or(&config.ollama)
+  }
+
+  /// Get the effective LLM models for this classifier: the ensemble if
+  /// one is configured, otherwise the single effective model.
+  pub fn effective_llm_models(&self, global: &BackendConfig) -> Vec<String> {
+    match &self.ollama_models {
+      Some(models) => models.clone(),
+      None => vec![
+        self
+          .ollama_model
+          .clone()
+          .unwrap_or_else(|| global.model.clone()),
+      ],
+    }
+  }
+
+  /// Get the effective LLM model for this classifier, as recorded in
+  /// events and projections.  An ensemble is recorded as its comma-separated
+  /// model list.
+  pub fn effective_llm_model(&self, global: &BackendConfig) -> String {
+    self.effective_llm_models(global).join(",")
+  }
+
+  /// Models a current classification may have been recorded with: the
+  /// effective model and any escalation tier that decided it.
+  pub fn recorded_models(&self, global: &BackendConfig) -> Vec<String> {
+    std::iter::once(self.effective_llm_model(global))
+      .chain(self.escalation_models.iter().cloned())
+      .collect()
   }
 
   /// Get the effective minimum confidence for this classifier.
@@ -104,11 +372,34 @@ impl ClassifierConfig {
     self.min_confidence.unwrap_or(defaults.min_confidence)
   }
 
+  /// Get the confidence band within which a result is escalated to the next
+  /// model in `escalation_models`.
+  pub fn effective_uncertainty_band(
+    &self,
+    defaults: &DefaultsConfig,
+  ) -> UncertaintyBand {
+    UncertaintyBand::around(
+      self.effective_min_confidence(defaults),
+      self.escalation_margin.unwrap_or(DEFAULT_ESCALATION_MARGIN),
+    )
+  }
+
   /// Get the effective TTL for this classifier.
   pub fn effective_ttl_days(&self, defaults: &DefaultsConfig) -> i64 {
     self.ttl_days.unwrap_or(defaults.ttl_days)
   }
 
+  /// Get the TTL policy for this classifier's LLM results.
+  pub fn effective_ttl_policy(&self, defaults: &DefaultsConfig) -> TtlPolicy {
+    let ttl_days = self.effective_ttl_days(defaults);
+    TtlPolicy {
+      matching_days: self.ttl_days_matching.unwrap_or(ttl_days),
+      not_matching_days: self.ttl_days_not_matching.unwrap_or(ttl_days),
+      scale_by_confidence: self.ttl_scale_by_confidence,
+      fetch_error_days: self.ttl_days_fetch_error,
+    }
+  }
+
   /// Get the effective HTTP timeout for this classifier.
   pub fn effective_http_timeout_sec(&self, http: &HttpConfig) -> u64 {
     self.http_timeout_sec.unwrap_or(http.timeout_sec)
@@ -118,22 +409,58 @@ impl ClassifierConfig {
   pub fn effective_http_max_kb(&self, http: &HttpConfig) -> usize {
     self.http_max_kb.unwrap_or(http.max_kb)
   }
+
+  /// Get the effective HTTP proxy for this classifier.
+  pub fn effective_http_proxy<'a>(
+    &'a self,
+    http: &'a HttpConfig,
+  ) -> Option<&'a ProxyConfig> {
+    self.http_proxy.as_ref().or(http.proxy.as_ref())
+  }
 }
 
 /// Main configuration file structure.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
-  /// Global Ollama configuration
-  pub ollama: OllamaConfig,
+  /// Default LLM backend
+  pub ollama: BackendConfig,
+
+  /// Additional LLM backends, by name
+  #[serde(default)]
+  pub backends: BTreeMap<String, BackendConfig>,
 
   /// Global HTTP fetching configuration
   #[serde(default)]
   pub http: HttpConfig,
 
+  /// Size budgets of the page evidence passed to the LLM
+  #[serde(default)]
+  pub extraction: ExtractionLimits,
+
+  /// Internal networks the classifier may fetch pages from
+  #[serde(default)]
+  pub egress: EgressPolicy,
+
   /// Global classification defaults
   #[serde(default)]
   pub defaults: DefaultsConfig,
 
+  /// Proactive refresh of expiring classifications
+  #[serde(default)]
+  pub refresh: RefreshConfig,
+
+  /// Circuit breaker around the LLM backend
+  #[serde(default)]
+  pub circuit_breaker: CircuitBreakerConfig,
+
+  /// Reclassification after prompt or model changes
+  #[serde(default)]
+  pub prompt_change: PromptChangeConfig,
+
+  /// NXDOMAIN check before classification
+  #[serde(default)]
+  pub nxdomain: NxdomainConfig,
+
   /// List of classifiers to run on each domain
   #[serde(rename = "classifier", default)]
   pub classifiers: Vec<ClassifierConfig>,
@@ -158,6 +485,8 @@ impl Default for HttpConfig {
     Self {
       timeout_sec: default_http_timeout_sec(),
       max_kb: default_http_max_kb(),
+      proxy: None,
+      cache: FetchCacheConfig::default(),
     }
   }
 }
@@ -171,7 +500,59 @@ impl Default for DefaultsConfig {
   }
 }
 
+impl Default for RefreshConfig {
+  fn default() -> Self {
+    Self {
+      enabled: default_refresh_enabled(),
+      interval_sec: default_refresh_interval_sec(),
+      window_hours: default_refresh_window_hours(),
+      seen_within_days: default_refresh_seen_within_days(),
+      batch_size: default_refresh_batch_size(),
+    }
+  }
+}
+
+impl Default for CircuitBreakerConfig {
+  fn default() -> Self {
+    Self {
+      failure_threshold: default_circuit_failure_threshold(),
+      probe_interval_sec: default_circuit_probe_interval_sec(),
+      probe_timeout_sec: default_circuit_probe_timeout_sec(),
+    }
+  }
+}
+
+impl Default for NxdomainConfig {
+  fn default() -> Self {
+    Self {
+      enabled: false,
+      resolver: None,
+      timeout_sec: default_nxdomain_timeout_sec(),
+    }
+  }
+}
+
+impl Default for PromptChangeConfig {
+  fn default() -> Self {
+    Self {
+      requeue_rate_per_sec: default_prompt_change_requeue_rate_per_sec(),
+    }
+  }
+}
+
 impl Config {
+  /// The backends used by at least one classifier, one per URL.
+  pub fn backends_in_use(&self) -> Vec<&BackendConfig> {
+    let mut backends: Vec<&BackendConfig> = Vec::new();
+    for classifier in &self.classifiers {
+      let backend = classifier.effective_backend(self);
+      if !backends.iter().any(|b| b.url == backend.url) {
+        backends.push(backend);
+      }
+    }
+    backends
+  }
+
   /// Load configuration from a TOML file.
   pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
     let content = std::fs::read_to_string(path)?;
@@ -216,6 +597,67 @@ impl Config {
         )));
       }
 
+      if let Some(name) = &classifier.backend {
+        if !self.backends.contains_key(name) {
+          return Err(ConfigError::ValidationError(format!(
+            "Classifier '{}': backend '{}' is not defined in [backends]",
+            classifier.name, name
+          )));
+        }
+      }
+
+      if let Some(models) = &classifier.ollama_models {
+        if classifier.ollama_model.is_some() {
+          return Err(ConfigError::ValidationError(format!(
+            "Classifier '{}': set either ollama_model or ollama_models, not both",
+            classifier.name
+          )));
+        }
+        if models.is_empty() || models.iter().any(|m| m.trim().is_empty()) {
+          return Err(ConfigError::ValidationError(format!(
+            "Classifier '{}': ollama_models must list at least one non-empty model",
+            classifier.name
+          )));
+        }
+      }
+
+      if classifier
+        .escalation_models
+        .iter()
+        .any(|m| m.trim().is_empty())
+      {
+        return Err(ConfigError::ValidationError(format!(
+          "Classifier '{}': escalation_models must not contain empty names",
+          classifier.name
+        )));
+      }
+
+      if let Some(margin) = classifier.escalation_margin {
+        if !(margin > 0.0 && margin <= 1.0) {
+          return Err(ConfigError::ValidationError(format!(
+            "Classifier '{}': escalation_margin must be in (0.0, 1.0], got {}",
+            classifier.name, margin
+          )));
+        }
+      }
+
+      // Shadow results are never projected, so there is nothing to go stale.
+      if classifier.shadow
+        && classifier.on_prompt_change != OnPromptChange::Ignore
+      {
+        return Err(ConfigError::ValidationError(format!(
+          "Shadow classifier '{}' does not support on_prompt_change",
+          classifier.name
+        )));
+      }
+
+      if classifier.shadow && classifier.reuse_redirect_target {
+        return Err(ConfigError::ValidationError(format!(
+          "Shadow classifier '{}' does not support reuse_redirect_target",
+          classifier.name
+        )));
+      }
+
       // Validate min_confidence if specified.
       if let Some(conf) = classifier.min_confidence {
         if !(0.0..=1.0).contains(&conf) {
@@ -226,17 +668,89 @@ impl Config {
         }
       }
 
-      // Validate TTL if specified.
-      if let Some(ttl) = classifier.ttl_days {
-        if ttl < 0 {
+      // Validate TTLs if specified.
+      for (key, ttl) in [
+        ("ttl_days", classifier.ttl_days),
+        ("ttl_days_matching", classifier.ttl_days_matching),
+        ("ttl_days_not_matching", classifier.ttl_days_not_matching),
+        ("ttl_days_fetch_error", classifier.ttl_days_fetch_error),
+      ] {
+        if let Some(ttl) = ttl {
+          if ttl < 0 {
+            return Err(ConfigError::ValidationError(format!(
+              "Classifier '{}': {} must be non-negative, got {}",
+              classifier.name, key, ttl
+            )));
+          }
+        }
+      }
+    }
+
+    for (name, backend) in std::iter::once(("ollama", &self.ollama))
+      .chain(self.backends.iter().map(|(n, b)| (n.as_str(), b)))
+    {
+      if let Some(path) = &backend.api_key_file {
+        if !path.exists() {
+          return Err(ConfigError::ValidationError(format!(
+            "Backend '{}': api_key_file does not exist: {}",
+            name,
+            path.display()
+          )));
+        }
+      }
+    }
+
+    let proxies =
+      std::iter::once(("[http] proxy".to_string(), &self.http.proxy)).chain(
+        self.classifiers.iter().map(|c| {
+          (
+            format!("Classifier '{}': http_proxy", c.name),
+            &c.http_proxy,
+          )
+        }),
+      );
+    for (label, proxy) in proxies {
+      let Some(proxy) = proxy else { continue };
+      proxy.validate().map_err(|e| {
+        ConfigError::ValidationError(format!("{}: {}", label, e))
+      })?;
+      if let Some(path) = &proxy.credentials_file {
+        if !path.exists() {
           return Err(ConfigError::ValidationError(format!(
-            "Classifier '{}': ttl_days must be non-negative, got {}",
-            classifier.name, ttl
+            "{}: credentials_file does not exist: {}",
+            label,
+            path.display()
           )));
         }
       }
     }
 
+    // Each shadow evaluates exactly one live classifier.
+    for shadow in self.classifiers.iter().filter(|c| c.shadow) {
+      if !self
+        .classifiers
+        .iter()
+        .any(|c| !c.shadow && c.name == shadow.name)
+      {
+        return Err(ConfigError::ValidationError(format!(
+          "Shadow classifier '{}' has no live classifier of the same name",
+          shadow.name
+        )));
+      }
+      if self
+        .classifiers
+        .iter()
+        .filter(|c| c.shadow && c.name == shadow.name)
+        .count()
+        > 1
+      {
+        return Err(ConfigError::ValidationError(format!(
+          "Classifier '{}' has more than one shadow",
+          shadow.name
+        )));
+      }
+    }
+
     // Validate global defaults.
     if !(0.0..=1.0).contains(&self.defaults.min_confidence) {
       return Err(ConfigError::ValidationError(format!(
@@ -252,6 +766,65 @@ impl Config {
       )));
     }
 
+    if self.refresh.enabled {
+      if self.refresh.interval_sec == 0 {
+        return Err(ConfigError::ValidationError(
+          "refresh.interval_sec must be positive".to_string(),
+        ));
+      }
+      for (name, value) in [
+        ("window_hours", self.refresh.window_hours),
+        ("seen_within_days", self.refresh.seen_within_days),
+        ("batch_size", self.refresh.batch_size),
+      ] {
+        if value <= 0 {
+          return Err(ConfigError::ValidationError(format!(
+            "refresh.{} must be positive, got {}",
+            name, value
+          )));
+        }
+      }
+    }
+
+    for (name, value) in [
+      (
+        "failure_threshold",
+        u64::from(self.circuit_breaker.failure_threshold),
+      ),
+      (
+        "probe_interval_sec",
+        self.circuit_breaker.probe_interval_sec,
+      ),
+      ("probe_timeout_sec", self.circuit_breaker.probe_timeout_sec),
+    ] {
+      if value == 0 {
+        return Err(ConfigError::ValidationError(format!(
+          "circuit_breaker.{} must be positive",
+          name
+        )));
+      }
+    }
+
+    if self.nxdomain.enabled {
+      if self.nxdomain.resolver.is_none() {
+        return Err(ConfigError::ValidationError(
+          "nxdomain.resolver is required when nxdomain.enabled is true"
+            .to_string(),
+        ));
+      }
+      if self.nxdomain.timeout_sec == 0 {
+        return Err(ConfigError::ValidationError(
+          "nxdomain.timeout_sec must be positive".to_string(),
+        ));
+      }
+    }
+
+    if self.prompt_change.requeue_rate_per_sec == 0 {
+      return Err(ConfigError::ValidationError(
+        "prompt_change.requeue_rate_per_sec must be positive".to_string(),
+      ));
+    }
+
     for suffix in &self.exclude_suffixes {
       if suffix.is_empty() {
         return Err(ConfigError::ValidationError(
@@ -269,6 +842,23 @@ mod tests {
   use super::*;
   use tempfile::NamedTempFile;
 
+  /// A config with the default Ollama backend and a single `gaming`
+  /// classifier, to append classifier settings or sections to.
+  fn base_config(prompt: &Path) -> String {
+    format!(
+      r#"
+[ollama]
+url = "http://localhost:11434"
+model = "llama3.2:3b"
+
+[[classifier]]
+name = "gaming"
+prompt_template = "{}"
+"#,
+      prompt.display()
+    )
+  }
+
   #[test]
   fn test_parse_valid_config() {
     let gaming = NamedTempFile::new().unwrap();
@@ -433,4 +1023,484 @@ ttl_days = 30
       30
     );
   }
+
+  #[test]
+  fn test_refresh_defaults_and_overrides() {
+    let gaming = NamedTempFile::new().unwrap();
+
+    let base = base_config(gaming.path());
+
+    let config: Config = toml::from_str(&base).unwrap();
+    assert!(config.refresh.enabled);
+    assert_eq!(config.refresh.window_hours, 24);
+
+    let config: Config = toml::from_str(&format!(
+      "{}\n[refresh]\nwindow_hours = 48\nbatch_size = 0\n",
+      base
+    ))
+    .unwrap();
+    assert_eq!(config.refresh.window_hours, 48);
+    let error_msg = config.validate().unwrap_err().to_string();
+    assert!(
+      error_msg.contains("refresh.batch_size must be positive"),
+      "Expected refresh error, got: {}",
+      error_msg
+    );
+  }
+
+  #[test]
+  fn test_circuit_breaker_defaults_and_validation() {
+    let gaming = NamedTempFile::new().unwrap();
+
+    let base = base_config(gaming.path());
+
+    let config: Config = toml::from_str(&base).unwrap();
+    assert_eq!(config.circuit_breaker.failure_threshold, 3);
+    assert_eq!(config.circuit_breaker.probe_interval_sec, 30);
+
+    let config: Config = toml::from_str(&format!(
+      "{}\n[circuit_breaker]\nfailure_threshold = 0\n",
+      base
+    ))
+    .unwrap();
+    let error_msg = config.validate().unwrap_err().to_string();
+    assert!(
+      error_msg.contains("circuit_breaker.failure_threshold must be positive"),
+      "Expected circuit breaker error, got: {}",
+      error_msg
+    );
+  }
+
+  #[test]
+  fn test_ensemble_models() {
+    let gaming = NamedTempFile::new().unwrap();
+
+    let base = base_config(gaming.path());
+
+    let config: Config = toml::from_str(&base).unwrap();
+    assert_eq!(
+      config.classifiers[0].effective_llm_models(&config.ollama),
+      vec!["llama3.2:3b".to_string()]
+    );
+
+    let config: Config = toml::from_str(&format!(
+      "{}ollama_models = [\"a\", \"b\", \"c\"]\nensemble_strategy = \"unanimous\"\n",
+      base
+    ))
+    .unwrap();
+    config.validate().unwrap();
+    let classifier = &config.classifiers[0];
+    assert_eq!(classifier.ensemble_strategy, EnsembleStrategy::Unanimous);
+    assert_eq!(classifier.effective_llm_model(&config.ollama), "a,b,c");
+
+    let config: Config = toml::from_str(&format!(
+      "{}ollama_model = \"a\"\nollama_models = [\"b\"]\n",
+      base
+    ))
+    .unwrap();
+    let error_msg = config.validate().unwrap_err().to_string();
+    assert!(
+      error_msg.contains("either ollama_model or ollama_models"),
+      "Expected ensemble error, got: {}",
+      error_msg
+    );
+  }
+
+  #[test]
+  fn test_escalation_band_follows_min_confidence() {
+    let gaming = NamedTempFile::new().unwrap();
+
+    let config: Config = toml::from_str(&format!(
+      "{}min_confidence = 0.7
+escalation_models = [\"llama3.1:8b\", \"llama3.1:70b\"]
+escalation_margin = 0.2
+",
+      base_config(gaming.path())
+    ))
+    .unwrap();
+    config.validate().unwrap();
+
+    let band =
+      config.classifiers[0].effective_uncertainty_band(&config.defaults);
+    assert!((band.min - 0.5).abs() < 1e-9);
+    assert!((band.max - 0.9).abs() < 1e-9);
+  }
+
+  #[test]
+  fn test_shadow_classifier() {
+    let live = NamedTempFile::new().unwrap();
+    let candidate = NamedTempFile::new().unwrap();
+
+    let base = format!("{}shadow = true\n", base_config(candidate.path()));
+
+    let config: Config = toml::from_str(&base).unwrap();
+    assert_eq!(config.classifiers[0].classification_type(), "shadow:gaming");
+    let error_msg = config.validate().unwrap_err().to_string();
+    assert!(
+      error_msg.contains("no live classifier"),
+      "Expected shadow error, got: {}",
+      error_msg
+    );
+
+    let config: Config = toml::from_str(&format!(
+      "{}\n[[classifier]]\nname = \"gaming\"\nprompt_template = \"{}\"\n",
+      base,
+      live.path().display()
+    ))
+    .unwrap();
+    config.validate().unwrap();
+    assert_eq!(config.classifiers[1].classification_type(), "gaming");
+  }
+
+  #[test]
+  fn test_on_prompt_change() {
+    let gaming = NamedTempFile::new().unwrap();
+
+    let base = format!(
+      "{}escalation_models = [\"llama3.1:8b\"]\n",
+      base_config(gaming.path())
+    );
+
+    let config: Config = toml::from_str(&base).unwrap();
+    assert_eq!(
+      config.classifiers[0].on_prompt_change,
+      OnPromptChange::Ignore
+    );
+    assert_eq!(config.prompt_change.requeue_rate_per_sec, 10);
+    assert_eq!(
+      config.classifiers[0].recorded_models(&config.ollama),
+      vec!["llama3.2:3b".to_string(), "llama3.1:8b".to_string()]
+    );
+
+    let config: Config = toml::from_str(&format!(
+      "{}on_prompt_change = \"requeue\"\n\n[prompt_change]\nrequeue_rate_per_sec = 0\n",
+      base
+    ))
+    .unwrap();
+    assert_eq!(
+      config.classifiers[0].on_prompt_change,
+      OnPromptChange::Requeue
+    );
+    let error_msg = config.validate().unwrap_err().to_string();
+    assert!(
+      error_msg.contains("requeue_rate_per_sec must be positive"),
+      "Expected prompt change error, got: {}",
+      error_msg
+    );
+  }
+
+  #[test]
+  fn test_named_backends() {
+    let gaming = NamedTempFile::new().unwrap();
+    let key = NamedTempFile::new().unwrap();
+
+    let base = base_config(gaming.path());
+
+    let config: Config = toml::from_str(&base).unwrap();
+    let backend = config.classifiers[0].effective_backend(&config);
+    assert_eq!(backend.backend, BackendKind::OllamaGenerate);
+    assert_eq!(backend.url, "http://localhost:11434");
+
+    let config: Config = toml::from_str(&format!(
+      r#"{}backend = "remote"
+
+[backends.remote]
+backend = "openai_chat"
+url = "http://llm.example:8080"
+model = "qwen2.5-7b-instruct"
+api_key_file = "{}"
+"#,
+      base,
+      key.path().display()
+    ))
+    .unwrap();
+    config.validate().unwrap();
+    let classifier = &config.classifiers[0];
+    let backend = classifier.effective_backend(&config);
+    assert_eq!(backend.backend, BackendKind::OpenaiChat);
+    assert_eq!(
+      classifier.effective_llm_model(backend),
+      "qwen2.5-7b-instruct"
+    );
+    assert_eq!(config.backends_in_use().len(), 1);
+    assert_eq!(config.backends_in_use()[0].url, "http://llm.example:8080");
+
+    let config: Config =
+      toml::from_str(&format!("{}backend = \"missing\"\n", base)).unwrap();
+    let error_msg = config.validate().unwrap_err().to_string();
+    assert!(
+      error_msg.contains("backend 'missing' is not defined"),
+      "Expected backend error, got: {}",
+      error_msg
+    );
+
+    let config: Config = toml::from_str(&format!(
+      "{}\n[backends.remote]\nurl = \"http://llm.example\"\nmodel = \"m\"\napi_key_file = \"/nonexistent/key\"\n",
+      base
+    ))
+    .unwrap();
+    let error_msg = config.validate().unwrap_err().to_string();
+    assert!(
+      error_msg.contains("api_key_file does not exist"),
+      "Expected api_key_file error, got: {}",
+      error_msg
+    );
+  }
+
+  #[test]
+  fn test_extraction_limits() {
+    let gaming = NamedTempFile::new().unwrap();
+
+    let base = base_config(gaming.path());
+
+    let config: Config = toml::from_str(&base).unwrap();
+    assert_eq!(config.extraction, ExtractionLimits::default());
+
+    let config: Config = toml::from_str(&format!(
+      "[extraction]\nbody_text_chars = 0\nmax_headings = 3\n{}",
+      base
+    ))
+    .unwrap();
+    assert_eq!(config.extraction.body_text_chars, 0);
+    assert_eq!(config.extraction.max_headings, 3);
+    assert_eq!(
+      config.extraction.field_chars,
+      ExtractionLimits::default().field_chars
+    );
+  }
+
+  #[test]
+  fn test_egress_allowlist() {
+    let gaming = NamedTempFile::new().unwrap();
+
+    let base = base_config(gaming.path());
+
+    let config: Config = toml::from_str(&base).unwrap();
+    assert!(config.egress.allow.is_empty());
+
+    let config: Config = toml::from_str(&format!(
+      "[egress]\nallow = [\"192.168.10.0/24\", \"fd00::5/128\"]\n{}",
+      base
+    ))
+    .unwrap();
+    assert_eq!(
+      config.egress.allow,
+      vec![
+        "192.168.10.0/24".parse().unwrap(),
+        "fd00::5/128".parse().unwrap()
+      ]
+    );
+
+    let result: Result<Config, _> = toml::from_str(&format!(
+      "[egress]\nallow = [\"192.168.10.0\"]\n{}",
+      base
+    ));
+    assert!(result.is_err());
+  }
+
+  #[test]
+  fn test_http_proxy() {
+    let gaming = NamedTempFile::new().unwrap();
+    let credentials = NamedTempFile::new().unwrap();
+
+    let base = base_config(gaming.path());
+
+    let config: Config = toml::from_str(&base).unwrap();
+    assert_eq!(
+      config.classifiers[0].effective_http_proxy(&config.http),
+      None
+    );
+
+    let config: Config = toml::from_str(&format!(
+      r#"[http.proxy]
+url = "http://proxy.lan:3128"
+credentials_file = "{}"
+{}
+[[classifier]]
+name = "adult"
+prompt_template = "{}"
+http_proxy = {{ url = "socks5h://tor.lan:9050" }}
+"#,
+      credentials.path().display(),
+      base,
+      gaming.path().display()
+    ))
+    .unwrap();
+    config.validate().unwrap();
+    let gaming_proxy = config.classifiers[0]
+      .effective_http_proxy(&config.http)
+      .unwrap();
+    assert_eq!(gaming_proxy.url, "http://proxy.lan:3128");
+    assert_eq!(
+      gaming_proxy.credentials_file.as_deref(),
+      Some(credentials.path())
+    );
+    let adult_proxy = config.classifiers[1]
+      .effective_http_proxy(&config.http)
+      .unwrap();
+    assert_eq!(adult_proxy.url, "socks5h://tor.lan:9050");
+    assert_eq!(adult_proxy.credentials_file, None);
+
+    for (proxy, expected) in [
+      (r#"url = "socks5://tor.lan:9050""#, "use socks5h://"),
+      (
+        r#"url = "http://proxy.lan"
+credentials_file = "/nonexistent/proxy""#,
+        "credentials_file does not exist",
+      ),
+    ] {
+      let config: Config =
+        toml::from_str(&format!("[http.proxy]\n{}\n{}", proxy, base)).unwrap();
+      let error_msg = config.validate().unwrap_err().to_string();
+      assert!(
+        error_msg.contains(expected),
+        "Expected {} error, got: {}",
+        expected,
+        error_msg
+      );
+    }
+  }
+
+  #[test]
+  fn test_fetch_cache() {
+    let gaming = NamedTempFile::new().unwrap();
+
+    let base = base_config(gaming.path());
+
+    let config: Config = toml::from_str(&base).unwrap();
+    assert_eq!(config.http.cache, FetchCacheConfig::default());
+    assert!(config.http.cache.open(None).is_none());
+
+    let config: Config = toml::from_str(&format!(
+      "[http]\ntimeout_sec = 30\ncache_dir = \"/var/cache/dns-smart-block\"\ncache_max_age_sec = 3600\n{}",
+      base
+    ))
+    .unwrap();
+    assert_eq!(config.http.timeout_sec, 30);
+    assert_eq!(
+      config.http.cache.cache_dir,
+      Some(PathBuf::from("/var/cache/dns-smart-block"))
+    );
+    assert_eq!(config.http.cache.cache_max_age_sec, 3600);
+    assert_eq!(
+      config.http.cache.cache_max_mb,
+      FetchCacheConfig::default().cache_max_mb
+    );
+  }
+
+  #[test]
+  fn test_reuse_redirect_target() {
+    let gaming = NamedTempFile::new().unwrap();
+
+    let base = base_config(gaming.path());
+
+    let config: Config = toml::from_str(&base).unwrap();
+    assert!(!config.classifiers[0].reuse_redirect_target);
+
+    let config: Config = toml::from_str(&format!(
+      "{}reuse_redirect_target = true
+",
+      base
+    ))
+    .unwrap();
+    config.validate().unwrap();
+    assert!(config.classifiers[0].reuse_redirect_target);
+
+    let config: Config = toml::from_str(&format!(
+      "{}reuse_redirect_target = true\nshadow = true\n",
+      base
+    ))
+    .unwrap();
+    let error_msg = config.validate().unwrap_err().to_string();
+    assert!(
+      error_msg.contains("does not support reuse_redirect_target"),
+      "Expected shadow error, got: {}",
+      error_msg
+    );
+  }
+
+  #[test]
+  fn test_reuse_llm_results() {
+    let gaming = NamedTempFile::new().unwrap();
+
+    let base = base_config(gaming.path());
+
+    let config: Config = toml::from_str(&base).unwrap();
+    assert!(config.classifiers[0].reuse_llm_results);
+
+    let config: Config =
+      toml::from_str(&format!("{}reuse_llm_results = false\n", base)).unwrap();
+    config.validate().unwrap();
+    assert!(!config.classifiers[0].reuse_llm_results);
+  }
+
+  #[test]
+  fn test_nxdomain_requires_resolver() {
+    let gaming = NamedTempFile::new().unwrap();
+
+    let base = base_config(gaming.path());
+
+    let config: Config = toml::from_str(&base).unwrap();
+    assert!(!config.nxdomain.enabled);
+    assert_eq!(config.nxdomain.timeout_sec, 5);
+
+    let config: Config =
+      toml::from_str(&format!("{}\n[nxdomain]\nenabled = true\n", base))
+        .unwrap();
+    let error_msg = config.validate().unwrap_err().to_string();
+    assert!(
+      error_msg.contains("nxdomain.resolver is required"),
+      "Expected nxdomain error, got: {}",
+      error_msg
+    );
+
+    let config: Config = toml::from_str(&format!(
+      "{}\n[nxdomain]\nenabled = true\nresolver = \"9.9.9.9:53\"\n",
+      base
+    ))
+    .unwrap();
+    config.validate().unwrap();
+    assert_eq!(
+      config.nxdomain.resolver,
+      Some("9.9.9.9:53".parse().unwrap())
+    );
+  }
+
+  #[test]
+  fn test_ttl_policy() {
+    let gaming = NamedTempFile::new().unwrap();
+
+    let base = format!("{}ttl_days = 14\n", base_config(gaming.path()));
+
+    let config: Config = toml::from_str(&base).unwrap();
+    assert_eq!(
+      config.classifiers[0].effective_ttl_policy(&config.defaults),
+      TtlPolicy::fixed(14)
+    );
+
+    let config: Config = toml::from_str(&format!(
+      "{}ttl_days_matching = 30\nttl_scale_by_confidence = true\nttl_days_fetch_error = 1\n",
+      base
+    ))
+    .unwrap();
+    config.validate().unwrap();
+    assert_eq!(
+      config.classifiers[0].effective_ttl_policy(&config.defaults),
+      TtlPolicy {
+        matching_days: 30,
+        not_matching_days: 14,
+        scale_by_confidence: true,
+        fetch_error_days: Some(1),
+      }
+    );
+
+    let config: Config =
+      toml::from_str(&format!("{}ttl_days_not_matching = -1\n", base)).unwrap();
+    let error_msg = config.validate().unwrap_err().to_string();
+    assert!(
+      error_msg.contains("ttl_days_not_matching must be non-negative"),
+      "Expected TTL error, got: {}",
+      error_msg
+    );
+  }
 }
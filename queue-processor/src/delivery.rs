@@ -0,0 +1,134 @@
+//! Settlement policy for JetStream messages.
+//!
+//! Every message pulled from the domain queue ends in exactly one of three
+//! ways: it is acknowledged, NAK'd for redelivery after a delay, or forwarded
+//! to the dead-letter subject and terminated.  Which one applies depends on
+//! whether processing failed at all, whether the failure was a transient
+//! infrastructure problem (PostgreSQL, NATS, or the LLM backend being
+//! unreachable), and how many times the message has already been delivered.
+//!
+//! Permanent per-domain failures (a site that cannot be fetched, an LLM answer
+//! that cannot be parsed) are recorded as `error` events by `process_domain`
+//! and acknowledged: redelivering them would only produce the same result.
+//! Transient failures leave the database untouched and ask JetStream to try
+//! again later, until `max_deliver` attempts have been spent.
+//!
+//! The delivery limit is enforced here rather than through the consumer's own
+//! `max_deliver` setting.  JetStream silently stops delivering a message once
+//! its limit is hit, which would skip the dead-letter forward; counting in the
+//! processor guarantees the message is always handed to the dead-letter
+//! subject, including when the process crashed mid-attempt and the message
+//! came back via the ack-wait timeout.
+
+use std::time::Duration;
+
+/// What to do with a message once processing has finished (or been skipped).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Settlement {
+  /// Processing finished, successfully or with a permanent failure.
+  Ack,
+  /// A transient failure occurred; redeliver after the given delay.
+  Nak(Duration),
+  /// The delivery budget is spent; forward to the dead-letter subject.
+  DeadLetter,
+}
+
+/// Decide whether a message should be processed at all.  Returns
+/// `Some(Settlement::DeadLetter)` when a previous attempt already used the
+/// last delivery (for example because the process was killed before it could
+/// settle the message).
+pub fn settle_before_processing(
+  delivered: i64,
+  max_deliver: i64,
+) -> Option<Settlement> {
+  if delivered > max_deliver {
+    Some(Settlement::DeadLetter)
+  } else {
+    None
+  }
+}
+
+/// Decide how to settle a message whose processing returned an error.
+pub fn settle_failure(
+  transient: bool,
+  delivered: i64,
+  max_deliver: i64,
+  nak_delay: Duration,
+) -> Settlement {
+  if !transient {
+    Settlement::Ack
+  } else if delivered >= max_deliver {
+    Settlement::DeadLetter
+  } else {
+    Settlement::Nak(nak_delay)
+  }
+}
+
+/// Whether a sqlx error reflects an infrastructure problem that may clear up
+/// on its own (connection loss, pool exhaustion, server restart) as opposed
+/// to a bug or bad data that will fail the same way on every attempt.
+pub fn is_transient_sqlx_error(error: &sqlx::Error) -> bool {
+  match error {
+    sqlx::Error::Io(_)
+    | sqlx::Error::Tls(_)
+    | sqlx::Error::Protocol(_)
+    | sqlx::Error::PoolTimedOut
+    | sqlx::Error::PoolClosed
+    | sqlx::Error::WorkerCrashed => true,
+    // SQLSTATE classes: 08 connection exception, 40 transaction rollback
+    // (serialization failure, deadlock), 53 insufficient resources, 57
+    // operator intervention (admin shutdown, cannot connect now).
+    sqlx::Error::Database(db_error) => db_error
+      .code()
+      .map(|code| {
+        ["08", "40", "53", "57"]
+          .iter()
+          .any(|class| code.starts_with(class))
+      })
+      .unwrap_or(false),
+    _ => false,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const DELAY: Duration = Duration::from_secs(30);
+
+  #[test]
+  fn test_permanent_failure_is_acked() {
+    assert_eq!(settle_failure(false, 1, 5, DELAY), Settlement::Ack);
+    assert_eq!(settle_failure(false, 5, 5, DELAY), Settlement::Ack);
+  }
+
+  #[test]
+  fn test_transient_failure_is_naked_with_delay() {
+    assert_eq!(settle_failure(true, 1, 5, DELAY), Settlement::Nak(DELAY));
+    assert_eq!(settle_failure(true, 4, 5, DELAY), Settlement::Nak(DELAY));
+  }
+
+  #[test]
+  fn test_transient_failure_on_last_delivery_is_dead_lettered() {
+    assert_eq!(settle_failure(true, 5, 5, DELAY), Settlement::DeadLetter);
+    assert_eq!(settle_failure(true, 1, 1, DELAY), Settlement::DeadLetter);
+  }
+
+  #[test]
+  fn test_over_delivered_message_is_dead_lettered_before_processing() {
+    assert_eq!(settle_before_processing(5, 5), None);
+    assert_eq!(settle_before_processing(6, 5), Some(Settlement::DeadLetter));
+  }
+
+  #[test]
+  fn test_sqlx_error_classification() {
+    assert!(is_transient_sqlx_error(&sqlx::Error::PoolTimedOut));
+    assert!(is_transient_sqlx_error(&sqlx::Error::Io(
+      std::io::Error::from(std::io::ErrorKind::ConnectionRefused)
+    )));
+    assert!(!is_transient_sqlx_error(&sqlx::Error::RowNotFound));
+    assert!(!is_transient_sqlx_error(&sqlx::Error::ColumnNotFound(
+      "confidence".to_string()
+    )));
+  }
+}
@@ -1,51 +1,34 @@
 //! DNS resolution check for domain validation.
 //!
-//! This module contains a DNS-based NXDOMAIN guard that was originally used
-//! in the queue-processor to detect non-existent domains before spending LLM
-//! resources on them.  It is currently **disabled** because performing a DNS
-//! lookup from inside the queue-processor routes through the local DNS stack
-//! (Blocky, or whatever the system resolver points at), generating a new log
-//! entry that the log-processor then enqueues — effectively doubling every
-//! domain processed.  With multiple queue-processor workers the problem
-//! compounds further: each worker fires its own lookup before any
-//! classification is written, and the resulting log entries cascade until the
-//! first classification lands.
+//! A domain that returns NXDOMAIN does not exist, so there is nothing to
+//! fetch and no point spending LLM time on it; the queue processor records a
+//! `dns_nxdomain` classification instead.
 //!
-//! There is no clean way to "colour" a DNS query so that the log-processor
-//! can recognise and ignore it: per-client filtering in Blocky relies on
-//! source IP, and making the queue-processor use a distinct loopback address
-//! for that purpose is fragile and host-specific.  Using a reverse PTR lookup
-//! on the already-resolved IP (which would produce a different
-//! `question_name`) avoids re-queuing the same domain name, but PTR records
-//! are too unreliable in practice (CDN IPs return their own infrastructure
-//! hostnames, not the origin domain) to be useful for validation.
-//!
-//! In practice the `response_type=RESOLVED` filter applied by the
-//! log-processor already excludes NXDOMAIN entries: Blocky only emits
-//! `RESOLVED` for queries that returned a real address.  So the guard is
-//! redundant for the current log-tailing architecture (Mode A).
-//!
-//! Roadmap context: Mode B (DNS proxy) and Mode C (standalone DNS server)
-//! would put dns-smart-block directly in the resolution path.  At that point
-//! the NXDOMAIN signal is available from the DNS response object itself with
-//! no re-resolution required, and this code could be revived.  For the
-//! foreseeable future it remains unused.
-
-#![allow(dead_code)]
+//! The lookup must never go through the local DNS stack: Blocky (or whatever
+//! `/etc/resolv.conf` points at) would log the query, the log-processor would
+//! enqueue the domain again, and every processed domain would come back
+//! around.  The resolver built here therefore talks only to the upstream
+//! configured in `[nxdomain]`, ignoring the system configuration and
+//! `/etc/hosts`, and is used for nothing but this check.  The upstream must
+//! not be the local DNS server itself, or the loop returns.
 
 use hickory_resolver::TokioAsyncResolver;
-use hickory_resolver::config::{ResolverConfig, ResolverOpts};
+use hickory_resolver::config::{
+  NameServerConfig, Protocol, ResolverConfig, ResolverOpts,
+};
 use hickory_resolver::error::ResolveErrorKind;
 use hickory_resolver::proto::op::ResponseCode;
-use tracing::warn;
+use std::net::SocketAddr;
+use std::time::Duration;
 
+#[derive(Debug, PartialEq)]
 pub enum DnsOutcome {
   /// Domain has at least one address record — proceed to LLM classification.
   Resolves,
   /// Authoritative NXDOMAIN — domain does not exist.
   Nxdomain,
-  /// Transient failure (SERVFAIL, timeout, …) — skip this message and let it
-  /// be retried naturally on the next log entry for the domain.
+  /// Transient failure (SERVFAIL, timeout, …) — the check is inconclusive,
+  /// so classification proceeds as if the domain resolved.
   TransientError(String),
 }
 
@@ -53,37 +36,134 @@ pub async fn dns_check(
   resolver: &TokioAsyncResolver,
   domain: &str,
 ) -> DnsOutcome {
-  match resolver.lookup_ip(domain).await {
+  // Fully qualified, so no search domain is ever appended.
+  let fqdn = format!("{}.", domain.trim_end_matches('.'));
+  match resolver.lookup_ip(fqdn).await {
     Ok(_) => DnsOutcome::Resolves,
     Err(e) => match e.kind() {
-      ResolveErrorKind::NoRecordsFound { response_code, .. }
-        if *response_code == ResponseCode::NXDomain =>
-      {
-        DnsOutcome::Nxdomain
+      ResolveErrorKind::NoRecordsFound { response_code, .. } => {
+        match *response_code {
+          ResponseCode::NXDomain => DnsOutcome::Nxdomain,
+          // NODATA — domain exists but has no A/AAAA records — is treated
+          // as "resolves" so we attempt LLM classification rather than
+          // silently discarding the domain.
+          ResponseCode::NoError => DnsOutcome::Resolves,
+          // SERVFAIL, REFUSED and the like say nothing about the domain.
+          _ => DnsOutcome::TransientError(e.to_string()),
+        }
       }
-      // Any other NoRecordsFound (e.g. NODATA — domain exists but has no
-      // A/AAAA records) is treated as "resolves" so we attempt LLM
-      // classification rather than silently discarding the domain.
-      ResolveErrorKind::NoRecordsFound { .. } => DnsOutcome::Resolves,
       _ => DnsOutcome::TransientError(e.to_string()),
     },
   }
 }
 
-/// Build a DNS resolver from the system configuration, falling back to
-/// public resolvers if `/etc/resolv.conf` cannot be read.
-pub fn build_resolver() -> TokioAsyncResolver {
-  match hickory_resolver::system_conf::read_system_conf() {
-    Ok((config, opts)) => TokioAsyncResolver::tokio(config, opts),
-    Err(e) => {
-      warn!(
-        "Could not read system DNS config ({}), falling back to defaults",
-        e
-      );
-      TokioAsyncResolver::tokio(
-        ResolverConfig::default(),
-        ResolverOpts::default(),
-      )
-    }
+/// Build a resolver that sends every query to `upstream`, over UDP with a TCP
+/// fallback for truncated answers, and consults nothing else.
+pub fn build_resolver(
+  upstream: SocketAddr,
+  timeout: Duration,
+) -> TokioAsyncResolver {
+  let config = ResolverConfig::from_parts(
+    None,
+    vec![],
+    vec![
+      NameServerConfig::new(upstream, Protocol::Udp),
+      NameServerConfig::new(upstream, Protocol::Tcp),
+    ],
+  );
+  let mut opts = ResolverOpts::default();
+  opts.timeout = timeout;
+  opts.attempts = 1;
+  opts.use_hosts_file = false;
+  TokioAsyncResolver::tokio(config, opts)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use hickory_resolver::proto::op::{Message, MessageType};
+  use hickory_resolver::proto::rr::{RData, Record, rdata::A};
+  use std::net::Ipv4Addr;
+  use tokio::net::UdpSocket;
+
+  /// Answer queries on a local UDP socket: `gone.test` is NXDOMAIN,
+  /// `broken.test` is SERVFAIL and everything else resolves to 192.0.2.1.
+  async fn spawn_stub_server() -> SocketAddr {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+    tokio::spawn(async move {
+      let mut buf = [0u8; 512];
+      loop {
+        let (len, peer) = socket.recv_from(&mut buf).await.unwrap();
+        let query = Message::from_vec(&buf[..len]).unwrap();
+        let name = query.queries()[0].name().clone();
+        let mut response = Message::new();
+        response
+          .set_id(query.id())
+          .set_message_type(MessageType::Response)
+          .set_recursion_desired(true)
+          .set_recursion_available(true)
+          .add_queries(query.queries().to_vec());
+        match name.to_ascii().as_str() {
+          "gone.test." => {
+            response.set_response_code(ResponseCode::NXDomain);
+          }
+          "broken.test." => {
+            response.set_response_code(ResponseCode::ServFail);
+          }
+          _ => {
+            if query.queries()[0].query_type()
+              == hickory_resolver::proto::rr::RecordType::A
+            {
+              response.add_answer(Record::from_rdata(
+                name,
+                60,
+                RData::A(A(Ipv4Addr::new(192, 0, 2, 1))),
+              ));
+            }
+          }
+        }
+        socket
+          .send_to(&response.to_vec().unwrap(), peer)
+          .await
+          .unwrap();
+      }
+    });
+    addr
+  }
+
+  #[tokio::test]
+  async fn test_dns_check_against_stub_server() {
+    let upstream = spawn_stub_server().await;
+    let resolver = build_resolver(upstream, Duration::from_secs(2));
+
+    assert_eq!(
+      dns_check(&resolver, "example.test").await,
+      DnsOutcome::Resolves
+    );
+    assert_eq!(
+      dns_check(&resolver, "gone.test").await,
+      DnsOutcome::Nxdomain
+    );
+    assert!(matches!(
+      dns_check(&resolver, "broken.test").await,
+      DnsOutcome::TransientError(_)
+    ));
+  }
+
+  #[tokio::test]
+  async fn test_unreachable_upstream_is_transient() {
+    // Bind and drop a socket to get a port nothing is listening on.
+    let upstream = UdpSocket::bind("127.0.0.1:0")
+      .await
+      .unwrap()
+      .local_addr()
+      .unwrap();
+    let resolver = build_resolver(upstream, Duration::from_millis(200));
+
+    assert!(matches!(
+      dns_check(&resolver, "example.test").await,
+      DnsOutcome::TransientError(_)
+    ));
   }
 }
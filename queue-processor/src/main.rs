@@ -1,29 +1,50 @@
+mod circuit;
 mod config;
 mod database_url;
 mod db;
+mod delivery;
 mod dns;
-
+mod prompt_change;
+mod redirect;
+mod refresh;
+
+use async_nats::jetstream::AckKind;
+use async_nats::jetstream::consumer::PullConsumer;
+use chrono::{DateTime, Utc};
+use circuit::CircuitBreaker;
 use clap::Parser;
-use config::{ClassifierConfig, Config};
+use config::{ClassifierConfig, Config, OnPromptChange};
 use database_url::{construct_database_url, sanitize_database_url};
 use db::DbError;
+use delivery::{
+  Settlement, is_transient_sqlx_error, settle_before_processing, settle_failure,
+};
+use dns::DnsOutcome;
 use dns_smart_block_classifier::{
-  compute_prompt_hash, output::ClassificationOutput,
+  compute_prompt_hash,
+  error::ClassifierErrorType,
+  output::{Classification, ClassificationOutput, LlmResult},
 };
 use dns_smart_block_common::db::{
-  ActiveProvisionedPattern, ClassificationSource, ClassifierState,
-  PromptInsert, apply_pattern_classification, classification_store,
-  fetch_all_override,
+  ActiveProvisionedPattern, CachedLlmResult, ClassificationReview,
+  ClassificationSource, ClassifierState, DomainSeen, ExpiringClassification,
+  LlmBackendStatus, PromptInsert, ReviewInsert, apply_pattern_classification,
+  classification_store, dns_nxdomain_classify, fetch_all_override,
+};
+use dns_smart_block_common::jetstream::{
+  Origin, Priority, StreamArgs, ensure_stream,
 };
 use dns_smart_block_common::logging::LoggingArgs;
 use futures::StreamExt;
+use prompt_change::PromptVersions;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use sqlx::PgPool;
 use std::path::PathBuf;
 use std::process::Stdio;
+use std::time::Duration;
 use thiserror::Error;
-use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
 use tokio::process::Command;
 use tracing::{error, info, warn};
 
@@ -34,6 +55,9 @@ struct CliArgs {
   #[command(flatten)]
   logging: LoggingArgs,
 
+  #[command(flatten)]
+  stream: StreamArgs,
+
   /// NATS server URL
   #[arg(long, env = "NATS_URL", default_value = "nats://localhost:4222")]
   nats_url: String,
@@ -46,6 +70,24 @@ struct CliArgs {
   #[arg(long, env = "NATS_MAX_ACK_PENDING", default_value = "1")]
   nats_max_ack_pending: i64,
 
+  /// Maximum number of delivery attempts for a message that keeps failing
+  /// with transient errors before it is forwarded to the dead-letter subject
+  #[arg(long, env = "NATS_MAX_DELIVER", default_value = "5")]
+  nats_max_deliver: i64,
+
+  /// Delay in seconds before a NAK'd message is redelivered
+  #[arg(long, env = "NATS_NAK_DELAY_SEC", default_value = "30")]
+  nats_nak_delay_sec: u64,
+
+  /// NATS subject that receives messages which exhausted their delivery
+  /// attempts.  Captured by a companion stream created on startup.
+  #[arg(
+    long,
+    env = "NATS_DEAD_LETTER_SUBJECT",
+    default_value = "dns.dead-letter"
+  )]
+  nats_dead_letter_subject: String,
+
   /// PostgreSQL connection URL (without password if using password file)
   #[arg(long, env = "DATABASE_URL")]
   database_url: String,
@@ -71,8 +113,29 @@ struct CliArgs {
 struct DomainMessage {
   domain: String,
   timestamp: i64,
+  /// Single resolved IP sent by log processors that predate `resolved_ips`.
   #[serde(default)]
   resolved_ip: Option<String>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  resolved_ips: Vec<String>,
+  #[serde(default)]
+  priority: Priority,
+  #[serde(default)]
+  origin: Origin,
+  /// Set by the refresh scheduler: reclassify only the matching
+  /// classifications that expire before this Unix timestamp.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  refresh_before: Option<i64>,
+}
+
+impl DomainMessage {
+  /// Pre-resolved IPs of the domain, from either message format.
+  fn resolved_ips(&self) -> &[String] {
+    match (&self.resolved_ips[..], &self.resolved_ip) {
+      ([], Some(ip)) => std::slice::from_ref(ip),
+      (ips, _) => ips,
+    }
+  }
 }
 
 #[derive(Error, Debug)]
@@ -92,6 +155,9 @@ enum ProcessorError {
   #[error("Classifier execution error: {0}")]
   ClassifierError(String),
 
+  #[error("LLM backend unavailable: {0}")]
+  BackendUnavailable(String),
+
   #[error("SQL error: {0}")]
   SqlxError(#[from] sqlx::Error),
 
@@ -107,9 +173,27 @@ enum ProcessorError {
 
 type Result<T> = std::result::Result<T, ProcessorError>;
 
+impl ProcessorError {
+  /// Whether this failure comes from infrastructure that may recover on its
+  /// own (PostgreSQL, NATS, the LLM backend, spawning the classifier) rather
+  /// than from the domain being processed.  Transient failures are NAK'd for
+  /// redelivery instead of being recorded as `error` events.
+  fn is_transient(&self) -> bool {
+    match self {
+      ProcessorError::NatsError(_)
+      | ProcessorError::IoError(_)
+      | ProcessorError::BackendUnavailable(_) => true,
+      ProcessorError::DatabaseError(DbError::SqlxError(e))
+      | ProcessorError::SqlxError(e) => is_transient_sqlx_error(e),
+      _ => false,
+    }
+  }
+}
+
 async fn run_classifier(
   domain: &str,
-  resolved_ip: Option<&str>,
+  resolved_ips: &[String],
+  cached_results: &[CachedLlmResult],
   classifier_config: &ClassifierConfig,
   config: &Config,
   classifier_path: &str,
@@ -119,7 +203,8 @@ async fn run_classifier(
     classifier_config.name, domain
   );
 
-  let ollama_model = classifier_config.effective_ollama_model(&config.ollama);
+  let backend = classifier_config.effective_backend(config);
+  let models = classifier_config.effective_llm_models(backend);
   let http_timeout_sec =
     classifier_config.effective_http_timeout_sec(&config.http);
   let http_max_kb = classifier_config.effective_http_max_kb(&config.http);
@@ -128,10 +213,14 @@ async fn run_classifier(
   cmd
     .arg("--domain")
     .arg(domain)
-    .arg("--ollama-url")
-    .arg(&config.ollama.url)
-    .arg("--ollama-model")
-    .arg(&ollama_model)
+    .arg("--llm-backend")
+    .arg(backend.backend.as_str())
+    .arg("--llm-url")
+    .arg(&backend.url)
+    .arg("--llm-model")
+    .arg(models.join(","))
+    .arg("--ensemble-strategy")
+    .arg(classifier_config.ensemble_strategy.as_str())
     .arg("--prompt-template")
     .arg(&classifier_config.prompt_template)
     .arg("--classification-type")
@@ -140,19 +229,101 @@ async fn run_classifier(
     .arg(http_timeout_sec.to_string())
     .arg("--http-max-kb")
     .arg(http_max_kb.to_string())
+    .arg("--body-text-chars")
+    .arg(config.extraction.body_text_chars.to_string())
+    .arg("--max-headings")
+    .arg(config.extraction.max_headings.to_string())
+    .arg("--field-chars")
+    .arg(config.extraction.field_chars.to_string())
+    .arg("--max-json-ld")
+    .arg(config.extraction.max_json_ld.to_string())
+    .arg("--max-link-domains")
+    .arg(config.extraction.max_link_domains.to_string())
     .arg("--output")
     .arg("json");
 
-  if let Some(ip) = resolved_ip {
+  for ip in resolved_ips {
     cmd.arg("--resolved-ip").arg(ip);
   }
 
+  // Cached answers go through stdin rather than argv, where they would be
+  // visible to every local user and could exceed the argument size limit.
+  let mut cached_lines = String::new();
+  for cached in cached_results {
+    let result = LlmResult {
+      key: cached.cache_key.clone(),
+      model: cached.model.clone(),
+      classification: Classification {
+        is_matching_site: cached.is_matching_site,
+        confidence: cached.confidence,
+        reasoning: cached.reasoning.clone(),
+      },
+      reused: false,
+    };
+    cached_lines.push_str(&serde_json::to_string(&result)?);
+    cached_lines.push('\n');
+  }
+  if !cached_lines.is_empty() {
+    cmd
+      .arg("--cached-results-file")
+      .arg("-")
+      .stdin(Stdio::piped());
+  }
+
+  for network in &config.egress.allow {
+    cmd.arg("--egress-allow").arg(network.to_string());
+  }
+
+  if let Some(dir) = &config.http.cache.cache_dir {
+    cmd
+      .arg("--cache-dir")
+      .arg(dir)
+      .arg("--cache-max-age-sec")
+      .arg(config.http.cache.cache_max_age_sec.to_string())
+      .arg("--cache-max-mb")
+      .arg(config.http.cache.cache_max_mb.to_string());
+  }
+
+  if let Some(proxy) = classifier_config.effective_http_proxy(&config.http) {
+    cmd.arg("--http-proxy").arg(&proxy.url);
+    if let Some(path) = &proxy.credentials_file {
+      cmd.arg("--http-proxy-credentials-file").arg(path);
+    }
+  }
+
+  if let Some(path) = &backend.api_key_file {
+    cmd.arg("--llm-api-key-file").arg(path);
+  }
+
+  if !classifier_config.escalation_models.is_empty() {
+    let band = classifier_config.effective_uncertainty_band(&config.defaults);
+    cmd
+      .arg("--escalation-model")
+      .arg(classifier_config.escalation_models.join(","))
+      .arg("--uncertainty-min")
+      .arg(band.min.to_string())
+      .arg("--uncertainty-max")
+      .arg(band.max.to_string());
+  }
+
   let mut child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
 
-  // Read stdout and stderr concurrently.
+  // Write stdin and read stdout and stderr concurrently.
+  // stdin: the cached answers, then closed
   // stdout: buffered until completion (small JSON payload)
   // stderr: streamed line-by-line for live logging
-  let (stdout_result, stderr_result) = tokio::join!(
+  let (stdin_result, stdout_result, stderr_result) = tokio::join!(
+    async {
+      if let Some(mut stdin) = child.stdin.take() {
+        match stdin.write_all(cached_lines.as_bytes()).await {
+          // The classifier exited before reading them, and its output says
+          // why.
+          Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => {}
+          result => result?,
+        }
+      }
+      Ok::<(), std::io::Error>(())
+    },
     async {
       let mut buf = String::new();
       if let Some(mut stdout) = child.stdout.take() {
@@ -179,6 +350,7 @@ async fn run_classifier(
 
   let stdout_buf = stdout_result?;
   stderr_result?;
+  stdin_result?;
 
   let _status = child.wait().await?;
 
@@ -213,12 +385,22 @@ async fn run_classifier(
         dns_smart_block_classifier::output::ErrorOutput,
       >(&stdout_buf)
       {
-        Ok(error_output) => Err(ProcessorError::ClassifierError(format!(
-          "Classifier '{}': {}: {}",
-          classifier_config.name,
-          error_output.error.error_type,
-          error_output.error.message
-        ))),
+        Ok(error_output) => {
+          let message = format!(
+            "Classifier '{}': {}: {}",
+            classifier_config.name,
+            error_output.error.error_type,
+            error_output.error.message
+          );
+          match error_output.error.error_type {
+            ClassifierErrorType::LlmApiConnectionError
+            | ClassifierErrorType::LlmApiTimeoutError
+            | ClassifierErrorType::LlmApiUnavailableError => {
+              Err(ProcessorError::BackendUnavailable(message))
+            }
+            _ => Err(ProcessorError::ClassifierError(message)),
+          }
+        }
         Err(e) => Err(ProcessorError::ClassifierError(format!(
           "Classifier '{}': Failed to parse output: {}. Output was: {}",
           classifier_config.name, e, stdout_buf
@@ -228,23 +410,90 @@ async fn run_classifier(
   }
 }
 
+/// Bring every classifier's result for `domain` up to date.  Returns whether
+/// an LLM backend answered, which is what the circuit breaker counts as a
+/// success; pattern, override, NXDOMAIN and reused results never reach it.
+#[allow(clippy::too_many_arguments)]
 async fn process_domain(
   domain: &str,
-  resolved_ip: Option<&str>,
+  resolved_ips: &[String],
+  refresh_before: Option<DateTime<Utc>>,
+  origin: Origin,
   config: &Config,
+  prompt_versions: &PromptVersions,
   pool: &PgPool,
   classifier_path: &str,
   compiled_patterns: &[(regex::Regex, ActiveProvisionedPattern)],
-) -> Result<()> {
+  resolver: Option<&hickory_resolver::TokioAsyncResolver>,
+) -> Result<bool> {
   info!("Processing domain: {}", domain);
 
-  // Get all classifier states in a single query.
-  let classification_types: Vec<String> =
-    config.classifiers.iter().map(|c| c.name.clone()).collect();
+  // Get all live classifier states in a single query.
+  let classification_types: Vec<String> = config
+    .classifiers
+    .iter()
+    .filter(|c| !c.shadow)
+    .map(|c| c.name.clone())
+    .collect();
 
   let states =
     ClassifierState::domain_states(pool, domain, &classification_types).await?;
 
+  // A refresh only reclassifies the types whose matching classification is
+  // still about to expire, so a refresh that was overtaken by another message
+  // is a no-op.  Domains arriving from DNS traffic are marked as seen instead,
+  // which is what keeps them eligible for refreshes; requeues are not, or a
+  // bulk requeue would make every domain look active.
+  let states = match refresh_before {
+    Some(before) => {
+      let due =
+        ExpiringClassification::types_for_domain(pool, domain, before).await?;
+      if due.is_empty() {
+        info!(
+          "Skipping refresh for domain {}: no classification is due",
+          domain
+        );
+        return Ok(false);
+      }
+      states
+        .into_iter()
+        .filter(|(classification_type, _)| due.contains(classification_type))
+        .map(|(classification_type, _)| {
+          (classification_type, ClassifierState::Expired)
+        })
+        .collect()
+    }
+    None => {
+      if origin == Origin::Dns {
+        DomainSeen {
+          domain: domain.to_string(),
+        }
+        .touch(pool)
+        .await?;
+      }
+      states
+    }
+  };
+
+  // A current classification made with a prompt or model the classifier no
+  // longer uses is redone when the classifier's policy asks for it.
+  let current: Vec<String> = states
+    .iter()
+    .filter(|(_, state)| *state == ClassifierState::Current)
+    .map(|(classification_type, _)| classification_type.clone())
+    .collect();
+  let stale = prompt_versions.stale_types(pool, domain, &current).await?;
+  let mut states = states;
+  for (classification_type, state) in states.iter_mut() {
+    if stale.contains(classification_type) {
+      info!(
+        "Classification '{}' of {} was made with a previous prompt or model",
+        classification_type, domain
+      );
+      *state = ClassifierState::Expired;
+    }
+  }
+
   // Check active provisioned pattern rules (DB-backed, supersedes the old
   // in-memory exclude_suffixes mechanism).  If any pattern matches the domain,
   // apply pattern-sourced classifications for each classifier that still needs
@@ -272,7 +521,7 @@ async fn process_domain(
       )
       .await?;
     }
-    return Ok(());
+    return Ok(false);
   }
 
   // Fall back to the old in-memory exclude_suffixes for backward compatibility
@@ -310,7 +559,34 @@ async fn process_domain(
       )
       .await?;
     }
-    return Ok(());
+    return Ok(false);
+  }
+
+  // Shadow classifiers run alongside the live ones on domains from DNS
+  // traffic.  They have no projection, so their state comes from their own
+  // events, with a `classified` event counting as current for the
+  // classifier's TTL.
+  let shadows: Vec<&ClassifierConfig> =
+    config.classifiers.iter().filter(|c| c.shadow).collect();
+  if refresh_before.is_none() && !shadows.is_empty() {
+    let now = Utc::now();
+    let shadow_types: Vec<String> =
+      shadows.iter().map(|c| c.classification_type()).collect();
+    let classified_since: Vec<DateTime<Utc>> = shadows
+      .iter()
+      .map(|c| {
+        now - chrono::Duration::days(c.effective_ttl_days(&config.defaults))
+      })
+      .collect();
+    states.extend(
+      ClassifierState::shadow_states(
+        pool,
+        domain,
+        &shadow_types,
+        &classified_since,
+      )
+      .await?,
+    );
   }
 
   // Check for an active "all" override.  When present, the stored
@@ -325,12 +601,49 @@ async fn process_domain(
     );
   }
 
+  // A domain that does not exist is classified as not matching without
+  // fetching it or asking the LLM.  Shadows are left alone: they record
+  // only LLM results.
+  let pending: Vec<&ClassifierConfig> = states
+    .iter()
+    .filter(|(_, state)| *state != ClassifierState::Current)
+    .filter_map(|(classification_type, _)| {
+      config
+        .classifiers
+        .iter()
+        .find(|c| !c.shadow && &c.name == classification_type)
+    })
+    .collect();
+  let resolver = resolver.filter(|_| all_override.is_none());
+  if let (Some(resolver), false) = (resolver, pending.is_empty()) {
+    match dns::dns_check(resolver, domain).await {
+      DnsOutcome::Nxdomain => {
+        info!("Domain {} does not exist (NXDOMAIN), skipping LLM", domain);
+        for classifier in pending {
+          dns_nxdomain_classify(
+            domain,
+            &classifier.name,
+            pool,
+            classifier.effective_ttl_days(&config.defaults),
+          )
+          .await?;
+        }
+        return Ok(false);
+      }
+      DnsOutcome::TransientError(e) => {
+        warn!("NXDOMAIN check for {} was inconclusive: {}", domain, e);
+      }
+      DnsOutcome::Resolves => {}
+    }
+  }
+
   // Process each classifier based on its state.
+  let mut backend_answered = false;
   for (classification_type, state) in states {
     let classifier_config = match config
       .classifiers
       .iter()
-      .find(|c| c.name == classification_type)
+      .find(|c| c.classification_type() == classification_type)
     {
       Some(cfg) => cfg,
       None => {
@@ -398,7 +711,7 @@ async fn process_domain(
             domain,
             "error",
             json!({
-                "classification_type": classifier_config.name,
+                "classification_type": classification_type,
                 "error": format!("Failed to read prompt template: {}", e),
             }),
             None, // No prompt_id for error events
@@ -408,24 +721,47 @@ async fn process_domain(
         }
       };
 
+    if classifier_config.reuse_redirect_target
+      && redirect::reuse_redirect_target(
+        pool,
+        domain,
+        classifier_config,
+        config,
+        &compute_prompt_hash(&prompt_template),
+      )
+      .await?
+    {
+      continue;
+    }
+
     // Insert "classifying" event.
     db::insert_event(
       pool,
       domain,
       "classifying",
       json!({
-          "classification_type": classifier_config.name,
-          "model": classifier_config.effective_ollama_model(&config.ollama),
+          "classification_type": classification_type,
+          "model": classifier_config
+            .effective_llm_model(classifier_config.effective_backend(config)),
           "prompt_hash": compute_prompt_hash(&prompt_template),
       }),
       None, // No prompt_id yet for "classifying" events (not finished)
     )
     .await?;
 
+    // Answers already given for this domain, which the classifier reuses
+    // when the evidence, prompt and model have not changed.
+    let cached_results = if classifier_config.reuse_llm_results {
+      CachedLlmResult::find(pool, domain, &classification_type).await?
+    } else {
+      Vec::new()
+    };
+
     // Run the classifier.
     match run_classifier(
       domain,
-      resolved_ip,
+      resolved_ips,
+      &cached_results,
       classifier_config,
       config,
       classifier_path,
@@ -433,6 +769,11 @@ async fn process_domain(
     .await
     {
       Ok(output) => {
+        backend_answered |= output
+          .metadata
+          .llm_results
+          .iter()
+          .any(|result| !result.reused);
         info!(
           "Classifier '{}' successful for {}: is_matching={}, confidence={}",
           classifier_config.name,
@@ -455,67 +796,108 @@ async fn process_domain(
         let source_id =
           ClassificationSource::ensure_for_prompt(prompt_id, &mut tx).await?;
 
+        let mut action_data = json!({
+            "classification_type": classification_type,
+            "is_matching_site": output.classification.is_matching_site,
+            "confidence": output.classification.confidence,
+            "reasoning": output.classification.reasoning,
+            "http_status": output.metadata.http_status,
+            "model": output.metadata.model,
+        });
+        // Keep every model's vote so ensemble disagreements can be audited.
+        if let Some(ensemble) = &output.ensemble {
+          action_data["ensemble_strategy"] = json!(ensemble.strategy);
+          action_data["votes"] = json!(ensemble.votes);
+        }
+        // Record where the fetch landed, so a redirect to another site can
+        // be audited and its classification reused.
+        if let Some(final_host) = &output.metadata.final_host {
+          action_data["final_host"] = json!(final_host);
+        }
+        if !output.metadata.redirect_chain.is_empty() {
+          action_data["redirect_chain"] = json!(output.metadata.redirect_chain);
+        }
+        // Record when the evidence came from the parent domain's page.
+        if let Some(parent) = &output.metadata.evidence_inherited_from {
+          action_data["evidence_inherited_from"] = json!(parent);
+        }
+        // Record which tier decided when the result was escalated.
+        if let Some(escalation) = &output.escalation {
+          action_data["decided_by_tier"] = json!(escalation.decided_by_tier);
+          action_data["tiers"] = json!(escalation.tiers);
+        }
+        // Record the models whose cached answer was reused.
+        let reused: Vec<&str> = output
+          .metadata
+          .llm_results
+          .iter()
+          .filter(|result| result.reused)
+          .map(|result| result.model.as_str())
+          .collect();
+        if !reused.is_empty() {
+          action_data["llm_results_reused"] = json!(reused);
+        }
         db::insert_event(
           &mut *tx,
           domain,
           "classified",
-          json!({
-              "classification_type": classifier_config.name,
-              "is_matching_site": output.classification.is_matching_site,
-              "confidence": output.classification.confidence,
-              "reasoning": output.classification.reasoning,
-              "http_status": output.metadata.http_status,
-              "model": classifier_config.effective_ollama_model(&config.ollama),
-          }),
+          action_data,
           Some(source_id),
         )
         .await?;
+        for result in &output.metadata.llm_results {
+          CachedLlmResult {
+            model: result.model.clone(),
+            cache_key: result.key.clone(),
+            is_matching_site: result.classification.is_matching_site,
+            confidence: result.classification.confidence,
+            reasoning: result.classification.reasoning.clone(),
+          }
+          .upsert(&mut *tx, domain, &classification_type)
+          .await?;
+        }
         tx.commit().await?;
 
-        // Update projections for ALL classifications (positive and negative).
-        // This allows /classifications endpoint to show everything, while
-        // /blocklist filters to only positive matches.
-        let min_confidence =
-          classifier_config.effective_min_confidence(&config.defaults);
-        let ttl_days = classifier_config.effective_ttl_days(&config.defaults);
-
-        // Only store if confidence meets minimum threshold
-        if output.classification.confidence >= min_confidence {
+        // A shadow's result lives only in its event; it never reaches the
+        // projections or the review queue.
+        if classifier_config.shadow {
           info!(
-            "Updating projections for {} (classifier '{}'): is_matching={}, confidence={}",
+            "Recorded shadow result for {} ({}): is_matching={}, confidence={}",
             domain,
-            classifier_config.name,
+            classification_type,
             output.classification.is_matching_site,
             output.classification.confidence
           );
-
-          classification_store(
-            pool,
-            domain,
-            &classifier_config.name,
-            output.classification.is_matching_site,
-            output.classification.confidence,
-            &output.classification.reasoning,
-            &classifier_config.effective_ollama_model(&config.ollama),
-            &prompt_template,
-            &output.metadata.prompt_hash,
-            ttl_days,
-          )
-          .await?;
-
-          info!(
-            "Projections updated successfully for {} (classifier '{}')",
-            domain, classifier_config.name
-          );
-        } else {
-          info!(
-            "Domain {} below confidence threshold ({} < {}) for classifier '{}', skipping projection",
-            domain,
-            output.classification.confidence,
-            min_confidence,
-            classifier_config.name
-          );
+          continue;
         }
+
+        store_result(
+          pool,
+          domain,
+          classifier_config,
+          config,
+          ClassifierResult {
+            is_matching_site: output.classification.is_matching_site,
+            confidence: output.classification.confidence,
+            reasoning: &output.classification.reasoning,
+            model: &output.metadata.model,
+            prompt_content: &prompt_template,
+            prompt_hash: &output.metadata.prompt_hash,
+            fetch_failed: output.metadata.fetch_error.is_some(),
+            source_id,
+          },
+        )
+        .await?;
+      }
+      // The backend (or the classifier binary itself) is unreachable, so the
+      // failure says nothing about this domain.  Bail out without recording
+      // an error event and let the caller NAK the message for redelivery.
+      Err(e) if e.is_transient() => {
+        warn!(
+          "Classifier '{}' hit a transient failure for {}: {}",
+          classifier_config.name, domain, e
+        );
+        return Err(e);
       }
       Err(e) => {
         error!(
@@ -529,7 +911,7 @@ async fn process_domain(
           domain,
           "error",
           json!({
-              "classification_type": classifier_config.name,
+              "classification_type": classification_type,
               "error": e.to_string(),
           }),
           None, // No prompt_id for error events
@@ -543,6 +925,253 @@ async fn process_domain(
     }
   }
 
+  Ok(backend_answered)
+}
+
+/// A classifier's result for a domain, recorded by the `classified` event
+/// of `source_id`.
+struct ClassifierResult<'a> {
+  is_matching_site: bool,
+  confidence: f64,
+  reasoning: &'a str,
+  model: &'a str,
+  prompt_content: &'a str,
+  prompt_hash: &'a str,
+  fetch_failed: bool,
+  source_id: i32,
+}
+
+/// Store a classifier's result in the projections, then supersede the
+/// domain's pending review if the blocklist acts on the result at the
+/// classifier's threshold, or queue the result for review otherwise.
+async fn store_result(
+  pool: &PgPool,
+  domain: &str,
+  classifier: &ClassifierConfig,
+  config: &Config,
+  result: ClassifierResult<'_>,
+) -> Result<()> {
+  // Update projections for ALL classifications (positive and negative),
+  // whatever their confidence.  The blocklist applies the confidence
+  // threshold when it is read, so changing a threshold takes effect
+  // without reclassifying anything.
+  info!(
+    "Updating projections for {} (classifier '{}'): is_matching={}, confidence={}",
+    domain, classifier.name, result.is_matching_site, result.confidence
+  );
+  classification_store(
+    pool,
+    domain,
+    &classifier.name,
+    result.is_matching_site,
+    result.confidence,
+    result.reasoning,
+    result.model,
+    result.prompt_content,
+    result.prompt_hash,
+    &classifier.effective_ttl_policy(&config.defaults),
+    result.fetch_failed,
+  )
+  .await?;
+  info!(
+    "Projections updated successfully for {} (classifier '{}')",
+    domain, classifier.name
+  );
+
+  // Results the blocklist will not act on at the classifier's threshold go
+  // to the review queue.
+  let min_confidence = classifier.effective_min_confidence(&config.defaults);
+  if result.confidence >= min_confidence {
+    ClassificationReview::supersede(pool, domain, &classifier.name).await?;
+  } else {
+    info!(
+      "Domain {} below confidence threshold ({} < {}) for classifier '{}', queueing for review",
+      domain, result.confidence, min_confidence, classifier.name
+    );
+    ReviewInsert {
+      domain: domain.to_string(),
+      classification_type: classifier.name.clone(),
+      is_matching_site: result.is_matching_site,
+      confidence: result.confidence as f32,
+      reasoning: Some(result.reasoning.to_string()),
+      model: result.model.to_string(),
+      source_id: Some(result.source_id),
+    }
+    .upsert(pool)
+    .await?;
+  }
+  Ok(())
+}
+
+/// Durable consumer name for a priority lane.  The interactive lane keeps the
+/// name of the single consumer used before lanes existed.
+fn consumer_name(priority: Priority) -> String {
+  match priority {
+    Priority::Interactive => "dns-smart-block-queue-processor".to_string(),
+    other => format!("dns-smart-block-queue-processor-{}", other.as_str()),
+  }
+}
+
+/// How long to wait on the interactive lane when no lane has messages before
+/// checking the lower-priority lanes again.
+const IDLE_WAIT: Duration = Duration::from_secs(5);
+
+/// Pull the next message, strictly preferring earlier consumers in
+/// `consumers`.  Each lane is asked for whatever it has without waiting; when
+/// all are empty, wait up to `idle_wait` for the first (interactive) lane.
+/// Returns `None` when that wait expires with nothing delivered.
+async fn next_message(
+  consumers: &[PullConsumer],
+  idle_wait: Duration,
+) -> Result<Option<async_nats::jetstream::Message>> {
+  let nats_error =
+    |e: async_nats::Error| ProcessorError::NatsError(e.to_string());
+
+  for consumer in consumers {
+    let mut batch = consumer
+      .fetch()
+      .max_messages(1)
+      .messages()
+      .await
+      .map_err(|e| ProcessorError::NatsError(e.to_string()))?;
+    if let Some(message) = batch.next().await {
+      return message.map(Some).map_err(nats_error);
+    }
+  }
+
+  let mut batch = consumers[0]
+    .batch()
+    .max_messages(1)
+    .expires(idle_wait)
+    .messages()
+    .await
+    .map_err(|e| ProcessorError::NatsError(e.to_string()))?;
+  batch.next().await.transpose().map_err(nats_error)
+}
+
+/// Record the circuit breaker state of the LLM backend for the metrics
+/// endpoint.  Failing to record it only costs visibility, so errors are
+/// logged rather than propagated.
+async fn record_circuit_state(pool: &PgPool, backend: &str, open: bool) {
+  if let Err(e) = LlmBackendStatus::record(pool, backend, open).await {
+    warn!("Failed to record circuit state of {}: {}", backend, e);
+  }
+}
+
+/// Block while the circuit is open: probe the LLM backends in use every
+/// `circuit_breaker.probe_interval_sec` until they all answer.  No messages
+/// are pulled in the meantime, so they stay in JetStream.
+async fn wait_for_backend(config: &Config, pool: &PgPool) {
+  let backends = config.backends_in_use();
+  let probe_interval =
+    Duration::from_secs(config.circuit_breaker.probe_interval_sec);
+  let probe_timeout =
+    Duration::from_secs(config.circuit_breaker.probe_timeout_sec);
+
+  for backend in &backends {
+    record_circuit_state(pool, &backend.url, true).await;
+  }
+  let mut unavailable = backends;
+  while !unavailable.is_empty() {
+    tokio::time::sleep(probe_interval).await;
+    let mut still_unavailable = Vec::new();
+    for backend in unavailable {
+      let health = match backend.llm_backend() {
+        Ok(client) => client
+          .check_health(probe_timeout)
+          .await
+          .map_err(|e| e.to_string()),
+        Err(e) => Err(e),
+      };
+      match health {
+        Ok(()) => {
+          info!("LLM backend {} is reachable again", backend.url);
+          record_circuit_state(pool, &backend.url, false).await;
+        }
+        Err(e) => {
+          warn!("LLM backend {} still unavailable: {}", backend.url, e);
+          still_unavailable.push(backend);
+        }
+      }
+    }
+    unavailable = still_unavailable;
+  }
+  info!("All LLM backends are reachable, resuming consumption");
+}
+
+/// Apply a settlement decision to a JetStream message.  Dead-lettered
+/// messages are republished to `dead_letter_subject` with their original
+/// payload and terminated; if that publish fails the message is NAK'd
+/// instead so it is never dropped.
+async fn settle_message(
+  message: &async_nats::jetstream::Message,
+  settlement: Settlement,
+  dead_letter_subject: &str,
+  reason: &str,
+  nak_delay: Duration,
+) {
+  let ack_kind = match settlement {
+    Settlement::Ack => AckKind::Ack,
+    Settlement::Nak(delay) => {
+      info!("NAK'ing message, redelivery in {}s", delay.as_secs());
+      AckKind::Nak(Some(delay))
+    }
+    Settlement::DeadLetter => {
+      match forward_to_dead_letter(message, dead_letter_subject, reason).await {
+        Ok(()) => {
+          warn!(
+            "Forwarded message to dead-letter subject '{}'",
+            dead_letter_subject
+          );
+          AckKind::Term
+        }
+        Err(e) => {
+          error!(
+            "Failed to forward message to dead-letter subject '{}': {}",
+            dead_letter_subject, e
+          );
+          AckKind::Nak(Some(nak_delay))
+        }
+      }
+    }
+  };
+
+  if let Err(e) = message.ack_with(ack_kind).await {
+    error!("Failed to settle message: {}", e);
+  }
+}
+
+/// Publish a copy of `message` to the dead-letter subject and wait for the
+/// JetStream ack.  Headers record where the message came from and why it was
+/// given up on, so an operator can inspect it and republish the payload to
+/// the original subject to replay it.
+async fn forward_to_dead_letter(
+  message: &async_nats::jetstream::Message,
+  dead_letter_subject: &str,
+  reason: &str,
+) -> Result<()> {
+  let delivered = message.info().map(|info| info.delivered).unwrap_or(0);
+  // Header values cannot contain line breaks, and classifier errors may
+  // embed multi-line stdout.
+  let reason = reason.replace(['\r', '\n'], " ");
+
+  let mut headers = async_nats::HeaderMap::new();
+  headers.insert("Dns-Smart-Block-Original-Subject", message.subject.as_str());
+  headers.insert("Dns-Smart-Block-Delivered", delivered.to_string().as_str());
+  headers.insert("Dns-Smart-Block-Error", reason.as_str());
+
+  message
+    .context
+    .publish_with_headers(
+      dead_letter_subject.to_string(),
+      headers,
+      message.payload.clone(),
+    )
+    .await
+    .map_err(|e| ProcessorError::NatsError(e.to_string()))?
+    .await
+    .map_err(|e| ProcessorError::NatsError(e.to_string()))?;
+
   Ok(())
 }
 
@@ -556,16 +1185,36 @@ async fn main() -> Result<()> {
   info!("Starting DNS Smart Block Queue Processor");
   info!("NATS URL: {}", args.nats_url);
   info!("NATS subject: {}", args.nats_subject);
+  info!(
+    "NATS max deliver: {} (NAK delay {}s, dead-letter subject: {})",
+    args.nats_max_deliver,
+    args.nats_nak_delay_sec,
+    args.nats_dead_letter_subject
+  );
   info!("Classifier path: {}", args.classifier_path);
   info!("Config file: {}", args.config_file.display());
 
   // Load configuration file
   info!("Loading configuration from {:?}", args.config_file);
   let config = Config::from_file(&args.config_file)?;
+  let prompt_versions = PromptVersions::load(&config);
 
   info!("Configuration loaded successfully");
-  info!("Ollama URL: {}", config.ollama.url);
-  info!("Ollama model (default): {}", config.ollama.model);
+  info!(
+    "Default LLM backend: {} at {}",
+    config.ollama.backend.as_str(),
+    config.ollama.url
+  );
+  info!("Default model: {}", config.ollama.model);
+  for (name, backend) in &config.backends {
+    info!(
+      "LLM backend '{}': {} at {} (model {})",
+      name,
+      backend.backend.as_str(),
+      backend.url,
+      backend.model
+    );
+  }
   info!("Number of classifiers: {}", config.classifiers.len());
   for classifier in &config.classifiers {
     info!(
@@ -601,37 +1250,70 @@ async fn main() -> Result<()> {
   info!("Connected to NATS successfully");
 
   // Get JetStream context
-  let jetstream = async_nats::jetstream::new(client);
-
-  // Create or get a single durable consumer for all classifiers
-  let consumer_name = "dns-smart-block-queue-processor";
-  info!("Creating JetStream consumer: {}", consumer_name);
-
-  let stream = jetstream.get_stream("DNS_SMART_BLOCK").await.map_err(|e| {
-    ProcessorError::NatsError(format!("Failed to get stream: {}", e))
-  })?;
-
-  let consumer = stream
-    .get_or_create_consumer(
-      consumer_name,
-      async_nats::jetstream::consumer::pull::Config {
-        durable_name: Some(consumer_name.to_string()),
+  let jetstream = async_nats::jetstream::new(client.clone());
+
+  // Provision the domain queue stream so a fresh NATS server works without
+  // any manual setup.
+  let stream =
+    ensure_stream(&jetstream, args.stream.stream_config(&args.nats_subject))
+      .await
+      .map_err(|e| ProcessorError::NatsError(e.to_string()))?;
+
+  ensure_stream(
+    &jetstream,
+    args
+      .stream
+      .dead_letter_stream_config(&args.nats_dead_letter_subject),
+  )
+  .await
+  .map_err(|e| ProcessorError::NatsError(e.to_string()))?;
+
+  // One durable consumer per priority lane, shared by all classifiers, in
+  // priority order.  The consumers are created or updated in place so that a
+  // consumer created before the lanes existed picks up its subject filter; a
+  // work-queue stream does not allow consumers with overlapping filters.
+  let mut consumers: Vec<PullConsumer> = Vec::new();
+  for priority in Priority::ALL {
+    let consumer_name = consumer_name(priority);
+    let filter_subject = priority.subject(&args.nats_subject);
+    info!(
+      "Creating JetStream consumer: {} ({})",
+      consumer_name, filter_subject
+    );
+    let consumer = stream
+      .create_consumer(async_nats::jetstream::consumer::pull::Config {
+        durable_name: Some(consumer_name),
+        filter_subject,
         ack_policy: async_nats::jetstream::consumer::AckPolicy::Explicit,
         max_ack_pending: args.nats_max_ack_pending,
         ..Default::default()
-      },
-    )
-    .await
-    .map_err(|e| {
-      ProcessorError::NatsError(format!("Failed to create consumer: {}", e))
-    })?;
+      })
+      .await
+      .map_err(|e| {
+        ProcessorError::NatsError(format!("Failed to create consumer: {}", e))
+      })?;
+    consumers.push(consumer);
+  }
 
-  info!("JetStream consumer created, waiting for messages...");
+  info!("JetStream consumers created, waiting for messages...");
 
-  // Process messages from JetStream
-  let mut messages = consumer.messages().await.map_err(|e| {
-    ProcessorError::NatsError(format!("Failed to get message stream: {}", e))
-  })?;
+  let nxdomain_resolver = match config.nxdomain.resolver {
+    Some(upstream) if config.nxdomain.enabled => {
+      if upstream.ip().is_loopback() {
+        warn!(
+          "NXDOMAIN resolver {} is a loopback address; make sure it is not \
+           the local DNS server whose logs feed the queue",
+          upstream
+        );
+      }
+      info!("NXDOMAIN check enabled via resolver {}", upstream);
+      Some(dns::build_resolver(
+        upstream,
+        Duration::from_secs(config.nxdomain.timeout_sec),
+      ))
+    }
+    _ => None,
+  };
 
   // Load active provisioned pattern rules from the database and compile them.
   // These replace the in-memory exclude_suffixes for domains that match a regex.
@@ -660,63 +1342,171 @@ async fn main() -> Result<()> {
   dns_smart_block_common::systemd::notify_ready();
   dns_smart_block_common::systemd::spawn_watchdog();
 
-  while let Some(message) = messages.next().await {
-    let message = match message {
-      Ok(msg) => msg,
+  let nak_delay = Duration::from_secs(args.nats_nak_delay_sec);
+
+  if config.refresh.enabled {
+    info!(
+      "Refresh scheduler enabled: every {}s, window {}h, seen within {}d",
+      config.refresh.interval_sec,
+      config.refresh.window_hours,
+      config.refresh.seen_within_days
+    );
+    tokio::spawn(refresh::run_refresh_scheduler(
+      pool.clone(),
+      client.clone(),
+      args.nats_subject.clone(),
+      config.refresh.clone(),
+      config
+        .classifiers
+        .iter()
+        .filter(|c| !c.shadow)
+        .map(|c| c.name.clone())
+        .collect(),
+    ));
+  }
+
+  if config
+    .classifiers
+    .iter()
+    .any(|c| c.on_prompt_change == OnPromptChange::Requeue)
+  {
+    tokio::spawn(prompt_change::requeue_stale(
+      pool.clone(),
+      client.clone(),
+      args.nats_subject.clone(),
+      config.clone(),
+      prompt_versions.clone(),
+    ));
+  }
+
+  let mut breaker =
+    CircuitBreaker::new(config.circuit_breaker.failure_threshold);
+  for backend in config.backends_in_use() {
+    record_circuit_state(&pool, &backend.url, false).await;
+  }
+
+  loop {
+    if breaker.is_open() {
+      wait_for_backend(&config, &pool).await;
+      breaker.close();
+    }
+
+    let message = match next_message(&consumers, IDLE_WAIT).await {
+      Ok(Some(msg)) => msg,
+      Ok(None) => continue,
       Err(e) => {
         error!("Error receiving message: {}", e);
+        tokio::time::sleep(IDLE_WAIT).await;
         continue;
       }
     };
 
+    let delivered = message.info().map(|info| info.delivered).unwrap_or(1);
+
+    // A previous attempt used up the last delivery without settling the
+    // message (e.g. the process was killed mid-classification).
+    if let Some(settlement) =
+      settle_before_processing(delivered, args.nats_max_deliver)
+    {
+      warn!(
+        "Message delivered {} times (max {}), forwarding to dead-letter subject",
+        delivered, args.nats_max_deliver
+      );
+      settle_message(
+        &message,
+        settlement,
+        &args.nats_dead_letter_subject,
+        "delivery limit exceeded before processing completed",
+        nak_delay,
+      )
+      .await;
+      continue;
+    }
+
     let payload = message.payload.clone();
 
     // Deserialize domain message
-    match serde_json::from_slice::<DomainMessage>(&payload) {
-      Ok(domain_msg) => {
-        info!(
-          "Received domain: {} (timestamp: {})",
-          domain_msg.domain, domain_msg.timestamp
-        );
+    let (settlement, failure) =
+      match serde_json::from_slice::<DomainMessage>(&payload) {
+        Ok(domain_msg) => {
+          info!(
+            "Received domain: {} (timestamp: {}, priority: {}, delivery: {})",
+            domain_msg.domain,
+            domain_msg.timestamp,
+            domain_msg.priority.as_str(),
+            delivered
+          );
 
-        // Process the domain (runs all needed classifiers).
-        // We always ACK the message regardless of success or failure.
-        // Errors are recorded in the database and will be retried on
-        // the next DNS query for this domain.
-        match process_domain(
-          &domain_msg.domain,
-          domain_msg.resolved_ip.as_deref(),
-          &config,
-          &pool,
-          &args.classifier_path,
-          &compiled_patterns,
-        )
-        .await
-        {
-          Ok(_) => {
-            info!("Successfully processed domain: {}", domain_msg.domain);
-          }
-          Err(e) => {
-            error!("Error processing domain {}: {}", domain_msg.domain, e);
+          // Process the domain (runs all needed classifiers).  Permanent
+          // per-domain failures are recorded in the database and the message
+          // is acknowledged; transient infrastructure failures are NAK'd so
+          // JetStream redelivers the message after a delay.
+          match process_domain(
+            &domain_msg.domain,
+            domain_msg.resolved_ips(),
+            domain_msg
+              .refresh_before
+              .and_then(|ts| DateTime::<Utc>::from_timestamp(ts, 0)),
+            domain_msg.origin,
+            &config,
+            &prompt_versions,
+            &pool,
+            &args.classifier_path,
+            &compiled_patterns,
+            nxdomain_resolver.as_ref(),
+          )
+          .await
+          {
+            Ok(backend_answered) => {
+              info!("Successfully processed domain: {}", domain_msg.domain);
+              if backend_answered {
+                breaker.record_success();
+              }
+              (Settlement::Ack, None)
+            }
+            Err(e) => {
+              if matches!(e, ProcessorError::BackendUnavailable(_))
+                && breaker.record_failure()
+              {
+                warn!(
+                  "LLM backend failed {} times in a row, pausing consumption",
+                  config.circuit_breaker.failure_threshold
+                );
+              }
+              let transient = e.is_transient();
+              error!(
+                "Error processing domain {} ({}): {}",
+                domain_msg.domain,
+                if transient { "transient" } else { "permanent" },
+                e
+              );
+              (
+                settle_failure(
+                  transient,
+                  delivered,
+                  args.nats_max_deliver,
+                  nak_delay,
+                ),
+                Some(e.to_string()),
+              )
+            }
           }
         }
-
-        // Always acknowledge the message.
-        if let Err(e) = message.ack().await {
-          error!("Failed to acknowledge message: {}", e);
-        }
-      }
-      Err(e) => {
-        error!("Failed to deserialize message: {}", e);
-        warn!("Raw payload: {:?}", String::from_utf8_lossy(&payload));
-        // Acknowledge malformed messages so they don't get redelivered.
-        if let Err(ack_err) = message.ack().await {
-          error!("Failed to acknowledge malformed message: {}", ack_err);
+        Err(e) => {
+          error!("Failed to deserialize message: {}", e);
+          warn!("Raw payload: {:?}", String::from_utf8_lossy(&payload));
+          // Acknowledge malformed messages so they don't get redelivered.
+          (Settlement::Ack, None)
         }
-      }
-    }
-  }
+      };
 
-  info!("NATS subscription ended");
-  Ok(())
+    settle_message(
+      &message,
+      settlement,
+      &args.nats_dead_letter_subject,
+      failure.as_deref().unwrap_or(""),
+      nak_delay,
+    )
+    .await;
+  }
 }
@@ -0,0 +1,187 @@
+//! Reclassification after a classifier's prompt or model changes.
+//!
+//! A classification stays current until its TTL expires, so editing a
+//! prompt or switching models would otherwise leave the blocklist serving
+//! the old judgement for weeks.  Classifiers opt in per `on_prompt_change`:
+//! under `lazy` a current classification made with a different prompt hash
+//! or model is treated as expired the next time its domain is processed, and
+//! `requeue` additionally re-enqueues every such domain on the low-priority
+//! subject at startup, rate limited by `prompt_change.requeue_rate_per_sec`.
+
+use crate::DomainMessage;
+use crate::config::{Config, OnPromptChange};
+use chrono::Utc;
+use dns_smart_block_classifier::compute_prompt_hash;
+use dns_smart_block_common::db::StaleClassification;
+use dns_smart_block_common::jetstream::{MSG_ID_HEADER, Origin, Priority};
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// The prompt hash and models a classifier currently classifies with.
+#[derive(Debug, Clone)]
+struct PromptVersion {
+  prompt_hash: String,
+  models: Vec<String>,
+}
+
+/// Current prompt hashes and models of the classifiers that redo
+/// classifications made with a previous prompt or model, keyed by classifier
+/// name.  Computed once when the config is loaded, so checking a domain costs
+/// neither a file read nor a query per classification.
+#[derive(Debug, Clone, Default)]
+pub struct PromptVersions(BTreeMap<String, PromptVersion>);
+
+impl PromptVersions {
+  /// Hash the prompt template of every opted-in classifier.  A template that
+  /// cannot be read is reported when the classifier runs, so its classifier
+  /// is just left out of staleness checks.
+  pub fn load(config: &Config) -> Self {
+    let versions = config
+      .classifiers
+      .iter()
+      .filter(|c| !c.shadow && c.on_prompt_change != OnPromptChange::Ignore)
+      .filter_map(|classifier| {
+        match std::fs::read_to_string(&classifier.prompt_template) {
+          Ok(content) => Some((
+            classifier.name.clone(),
+            PromptVersion {
+              prompt_hash: compute_prompt_hash(&content),
+              models: classifier
+                .recorded_models(classifier.effective_backend(config)),
+            },
+          )),
+          Err(e) => {
+            warn!(
+              "Cannot check classifier '{}' for prompt changes: failed to read {:?}: {}",
+              classifier.name, classifier.prompt_template, e
+            );
+            None
+          }
+        }
+      })
+      .collect();
+    Self(versions)
+  }
+
+  /// Of the classifiers in `current`, those whose current classification of
+  /// `domain` was made with a different prompt or model.
+  pub async fn stale_types(
+    &self,
+    pool: &PgPool,
+    domain: &str,
+    current: &[String],
+  ) -> Result<Vec<String>, sqlx::Error> {
+    let checked: Vec<String> = current
+      .iter()
+      .filter(|classification_type| self.0.contains_key(*classification_type))
+      .cloned()
+      .collect();
+    if checked.is_empty() {
+      return Ok(Vec::new());
+    }
+    let classifications =
+      StaleClassification::current_for_domain(pool, domain, &checked).await?;
+    Ok(
+      classifications
+        .into_iter()
+        .filter(|classification| {
+          let version = &self.0[&classification.classification_type];
+          classification.is_stale(&version.prompt_hash, &version.models)
+        })
+        .map(|classification| classification.classification_type)
+        .collect(),
+    )
+  }
+}
+
+/// Requeue the stale domains of every classifier with the `requeue` policy.
+/// Runs once, in the background, at startup.
+pub async fn requeue_stale(
+  pool: PgPool,
+  client: async_nats::Client,
+  subject: String,
+  config: Config,
+  versions: PromptVersions,
+) {
+  let low_subject = Priority::Low.subject(&subject);
+  let mut interval = tokio::time::interval(Duration::from_secs_f64(
+    1.0 / f64::from(config.prompt_change.requeue_rate_per_sec),
+  ));
+
+  for classifier in config
+    .classifiers
+    .iter()
+    .filter(|c| c.on_prompt_change == OnPromptChange::Requeue)
+  {
+    let Some(version) = versions.0.get(&classifier.name) else {
+      continue;
+    };
+    let domains = match StaleClassification::find_domains(
+      &pool,
+      &classifier.name,
+      &version.prompt_hash,
+      &version.models,
+    )
+    .await
+    {
+      Ok(domains) => domains,
+      Err(e) => {
+        error!(
+          "Failed to find stale classifications for '{}': {}",
+          classifier.name, e
+        );
+        continue;
+      }
+    };
+    if domains.is_empty() {
+      continue;
+    }
+
+    info!(
+      "Requeueing {} domain(s) classified by '{}' with a previous prompt or model",
+      domains.len(),
+      classifier.name
+    );
+    let mut requeued = 0;
+    for domain in &domains {
+      interval.tick().await;
+      match publish(&client, &low_subject, domain).await {
+        Ok(()) => requeued += 1,
+        Err(e) => error!("Failed to requeue {}: {}", domain, e),
+      }
+    }
+    info!(
+      "Requeued {} of {} domain(s) for '{}'",
+      requeued,
+      domains.len(),
+      classifier.name
+    );
+  }
+}
+
+async fn publish(
+  client: &async_nats::Client,
+  low_subject: &str,
+  domain: &str,
+) -> Result<(), String> {
+  let message = DomainMessage {
+    domain: domain.to_string(),
+    timestamp: Utc::now().timestamp(),
+    resolved_ip: None,
+    resolved_ips: Vec::new(),
+    priority: Priority::Low,
+    origin: Origin::PromptChange,
+    refresh_before: None,
+  };
+  let payload = serde_json::to_vec(&message).map_err(|e| e.to_string())?;
+  // Deduplicated separately from DNS-driven and refresh messages, like
+  // refreshes.
+  let mut headers = async_nats::HeaderMap::new();
+  headers.insert(MSG_ID_HEADER, format!("prompt-change:{}", domain).as_str());
+  client
+    .publish_with_headers(low_subject.to_string(), headers, payload.into())
+    .await
+    .map_err(|e| e.to_string())
+}
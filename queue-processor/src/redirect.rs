@@ -0,0 +1,104 @@
+//! Reuse of a redirect target's classification.
+//!
+//! Parked, tracking and vanity domains often just redirect to a site that is
+//! already classified.  Classifiers with `reuse_redirect_target` let such a
+//! domain take over the current classification of the host its landing page
+//! redirected to the last time it was classified, instead of fetching it and
+//! asking the LLM again.  The target's classification is only reused while
+//! it was made with the classifier's current prompt and models.  The first
+//! classification of a domain always runs, since that is when its redirect is
+//! discovered.  A reused classification records no final host, so the next
+//! one fetches the domain again and notices when its redirect has changed.
+
+use crate::config::{ClassifierConfig, Config};
+use crate::{ClassifierResult, Result, store_result};
+use dns_smart_block_common::db::{
+  ClassificationSource, PromptInsert, RedirectTarget, insert_event,
+};
+use serde_json::json;
+use sqlx::PgPool;
+use tracing::info;
+
+/// Classify `domain` with the current classification of its redirect
+/// target, if there is one to reuse.  Returns whether it was reused.
+pub async fn reuse_redirect_target(
+  pool: &PgPool,
+  domain: &str,
+  classifier: &ClassifierConfig,
+  config: &Config,
+  prompt_hash: &str,
+) -> Result<bool> {
+  let Some(target) =
+    RedirectTarget::find(pool, domain, &classifier.name).await?
+  else {
+    return Ok(false);
+  };
+  let models = classifier.recorded_models(classifier.effective_backend(config));
+  if target.prompt_hash != prompt_hash || !models.contains(&target.model) {
+    info!(
+      "Not reusing classification of {} for {} ({}): made with a previous prompt or model",
+      target.host, domain, classifier.name
+    );
+    return Ok(false);
+  }
+
+  info!(
+    "Reusing classification of redirect target {} for {} ({}): is_matching={}, confidence={}",
+    target.host,
+    domain,
+    classifier.name,
+    target.is_matching_site,
+    target.confidence
+  );
+  let reasoning = format!(
+    "Redirects to {}: {}",
+    target.host,
+    target.reasoning.as_deref().unwrap_or_default()
+  );
+
+  let mut tx = pool.begin().await?;
+  let prompt_id = PromptInsert {
+    content: target.prompt_content.clone(),
+    hash: target.prompt_hash.clone(),
+  }
+  .ensure(&mut tx)
+  .await?;
+  let source_id =
+    ClassificationSource::ensure_for_prompt(prompt_id, &mut tx).await?;
+  insert_event(
+    &mut *tx,
+    domain,
+    "classified",
+    json!({
+        "classification_type": classifier.name,
+        "is_matching_site": target.is_matching_site,
+        "confidence": target.confidence,
+        "reasoning": reasoning,
+        "model": target.model,
+        "reused_from": target.host,
+    }),
+    Some(source_id),
+  )
+  .await?;
+  tx.commit().await?;
+
+  store_result(
+    pool,
+    domain,
+    classifier,
+    config,
+    ClassifierResult {
+      is_matching_site: target.is_matching_site,
+      confidence: f64::from(target.confidence),
+      reasoning: &reasoning,
+      model: &target.model,
+      prompt_content: &target.prompt_content,
+      prompt_hash: &target.prompt_hash,
+      fetch_failed: false,
+      source_id,
+    },
+  )
+  .await?;
+
+  Ok(true)
+}
@@ -0,0 +1,104 @@
+//! Proactive refresh of expiring classifications.
+//!
+//! A matching classification that expires drops its domain out of
+//! `/blocklist` until the next DNS query re-enqueues it and the classifier
+//! finishes, leaving a window where a known-bad domain resolves.  The refresh
+//! scheduler closes that window: it periodically finds matching
+//! classifications that expire within `refresh.window_hours` for domains
+//! still seen in DNS traffic, and re-enqueues them on the low-priority
+//! subject so they are reclassified ahead of expiry without delaying domains
+//! seen for the first time.
+
+use crate::DomainMessage;
+use crate::config::RefreshConfig;
+use chrono::{Duration as ChronoDuration, Utc};
+use dns_smart_block_common::db::ExpiringClassification;
+use dns_smart_block_common::jetstream::{MSG_ID_HEADER, Origin, Priority};
+use sqlx::PgPool;
+use std::collections::BTreeSet;
+use std::time::Duration;
+use tracing::{error, info};
+
+/// Run the refresh scheduler until the process exits.
+pub async fn run_refresh_scheduler(
+  pool: PgPool,
+  client: async_nats::Client,
+  subject: String,
+  config: RefreshConfig,
+  classification_types: Vec<String>,
+) {
+  let low_subject = Priority::Low.subject(&subject);
+  let mut interval =
+    tokio::time::interval(Duration::from_secs(config.interval_sec));
+
+  loop {
+    interval.tick().await;
+    match refresh_expiring(
+      &pool,
+      &client,
+      &low_subject,
+      &config,
+      &classification_types,
+    )
+    .await
+    {
+      Ok(0) => {}
+      Ok(count) => info!(
+        "Re-enqueued {} domain(s) with expiring classifications",
+        count
+      ),
+      Err(e) => error!("Refresh scheduler run failed: {}", e),
+    }
+  }
+}
+
+/// Enqueue one refresh message per domain with an expiring classification.
+/// Returns the number of domains enqueued.
+async fn refresh_expiring(
+  pool: &PgPool,
+  client: &async_nats::Client,
+  low_subject: &str,
+  config: &RefreshConfig,
+  classification_types: &[String],
+) -> Result<usize, String> {
+  let now = Utc::now();
+  let expiring_before = now + ChronoDuration::hours(config.window_hours);
+  let seen_since = now - ChronoDuration::days(config.seen_within_days);
+
+  let expiring = ExpiringClassification::find(
+    pool,
+    classification_types,
+    expiring_before,
+    seen_since,
+    config.batch_size,
+  )
+  .await
+  .map_err(|e| e.to_string())?;
+
+  let domains: BTreeSet<String> =
+    expiring.into_iter().map(|e| e.domain).collect();
+
+  for domain in &domains {
+    let message = DomainMessage {
+      domain: domain.clone(),
+      timestamp: now.timestamp(),
+      resolved_ip: None,
+      resolved_ips: Vec::new(),
+      priority: Priority::Low,
+      origin: Origin::Refresh,
+      refresh_before: Some(expiring_before.timestamp()),
+    };
+    let payload = serde_json::to_vec(&message).map_err(|e| e.to_string())?;
+    // Deduplicate refreshes separately from regular publishes, so a refresh
+    // never swallows (or is swallowed by) a DNS-driven message for the same
+    // domain.
+    let mut headers = async_nats::HeaderMap::new();
+    headers.insert(MSG_ID_HEADER, format!("refresh:{}", domain).as_str());
+    client
+      .publish_with_headers(low_subject.to_string(), headers, payload.into())
+      .await
+      .map_err(|e| e.to_string())?;
+  }
+
+  Ok(domains.len())
+}
@@ -1,6 +1,10 @@
 use dns_smart_block_common::db::{
-  ClassificationSource, ClassifierState, PromptInsert, classification_store,
+  CachedLlmResult, ClassificationReview, ClassificationSource, ClassifierState,
+  CurrentLlmClassification, DomainSeen, ExpiringClassification,
+  LlmBackendStatus, PromptInsert, RedirectTarget, ReviewFilter, ReviewInsert,
+  StaleClassification, apply_admin_classification, classification_store,
 };
+use dns_smart_block_common::ttl::TtlPolicy;
 use dns_smart_block_queue_processor::db::insert_event;
 use serde_json::json;
 use serial_test::serial;
@@ -31,6 +35,10 @@ async fn setup_test_db() -> (dns_smart_block_common::test_db::TestDb, PgPool) {
     .execute(&pool)
     .await
     .expect("Failed to clean prompts");
+  sqlx::query("DELETE FROM llm_backend_status")
+    .execute(&pool)
+    .await
+    .expect("Failed to clean llm_backend_status");
 
   (test_db, pool)
 }
@@ -175,7 +183,8 @@ async fn test_classification_store() {
     model,
     prompt_content,
     prompt_hash,
-    ttl_days,
+    &TtlPolicy::fixed(ttl_days),
+    false,
   )
   .await
   .expect("Failed to store classification");
@@ -277,7 +286,8 @@ async fn test_update_projections_deduplicates_prompts() {
     "llama2",
     prompt_content,
     prompt_hash,
-    10,
+    &TtlPolicy::fixed(10),
+    false,
   )
   .await
   .expect("Failed to store classification for domain1");
@@ -293,7 +303,8 @@ async fn test_update_projections_deduplicates_prompts() {
     "llama2",
     prompt_content,
     prompt_hash,
-    10,
+    &TtlPolicy::fixed(10),
+    false,
   )
   .await
   .expect("Failed to store classification for domain2");
@@ -370,7 +381,8 @@ async fn test_upsert_domain_updates_timestamp() {
     "llama2",
     "test prompt",
     "sha256:test1",
-    10,
+    &TtlPolicy::fixed(10),
+    false,
   )
   .await
   .expect("Failed first insert");
@@ -399,7 +411,8 @@ async fn test_upsert_domain_updates_timestamp() {
     "llama2",
     "test prompt 2",
     "sha256:test2",
-    10,
+    &TtlPolicy::fixed(10),
+    false,
   )
   .await
   .expect("Failed second insert");
@@ -475,7 +488,8 @@ async fn test_get_classifier_states_current() {
     "llama2",
     "test prompt",
     "sha256:test",
-    10,
+    &TtlPolicy::fixed(10),
+    false,
   )
   .await
   .expect("Failed to store classification");
@@ -616,7 +630,8 @@ async fn test_get_classifier_states_mixed() {
     "llama2",
     "gaming prompt",
     "sha256:gaming",
-    10,
+    &TtlPolicy::fixed(10),
+    false,
   )
   .await
   .expect("Failed to store gaming classification");
@@ -849,3 +864,586 @@ async fn test_classified_event_rolls_back_with_source() {
 
   assert_eq!(event_count, 0, "Rolled-back event must not be persisted");
 }
+
+/// Insert a domain last seen `seen_days_ago` days ago with a classification
+/// of `classification_type` that expires in `expires_in_hours` hours.
+async fn insert_expiring(
+  pool: &PgPool,
+  source_id: i32,
+  domain: &str,
+  classification_type: &str,
+  is_matching_site: bool,
+  seen_days_ago: i32,
+  expires_in_hours: i32,
+) {
+  sqlx::query(
+    r#"
+        INSERT INTO domains (domain, last_updated, last_seen)
+        VALUES ($1, NOW(), NOW() - make_interval(days => $2))
+        ON CONFLICT (domain) DO NOTHING
+        "#,
+  )
+  .bind(domain)
+  .bind(seen_days_ago)
+  .execute(pool)
+  .await
+  .expect("Failed to insert domain");
+
+  sqlx::query(
+    r#"
+        INSERT INTO domain_classifications
+        (domain, classification_type, is_matching_site, confidence, valid_on,
+         valid_until, model, source_id)
+        VALUES ($1, $2, $3, 0.9, NOW() - INTERVAL '9 days',
+                NOW() + make_interval(hours => $4), 'llama2', $5)
+        "#,
+  )
+  .bind(domain)
+  .bind(classification_type)
+  .bind(is_matching_site)
+  .bind(expires_in_hours)
+  .bind(source_id)
+  .execute(pool)
+  .await
+  .expect("Failed to insert classification");
+}
+
+#[tokio::test]
+#[serial]
+
+async fn test_expiring_classifications_for_seen_matching_domains() {
+  let (_db, pool) = setup_test_db().await;
+  let source_id =
+    ensure_test_source(&pool, "test prompt", "sha256:refresh").await;
+
+  // Due: matching, seen recently, expires within the window.
+  insert_expiring(&pool, source_id, "due.com", "gaming", true, 1, 6).await;
+  // Not matching: an allowed domain dropping out of the cache is harmless.
+  insert_expiring(&pool, source_id, "allowed.com", "gaming", false, 1, 6).await;
+  // Not seen recently.
+  insert_expiring(&pool, source_id, "stale.com", "gaming", true, 30, 6).await;
+  // Expires after the window.
+  insert_expiring(&pool, source_id, "later.com", "gaming", true, 1, 72).await;
+  // Already expired: the regular DNS-driven path handles it.
+  insert_expiring(&pool, source_id, "gone.com", "gaming", true, 1, -1).await;
+  // Not a configured classifier.
+  insert_expiring(&pool, source_id, "other.com", "news", true, 1, 6).await;
+
+  let now = chrono::Utc::now();
+  let expiring = ExpiringClassification::find(
+    &pool,
+    &["gaming".to_string()],
+    now + chrono::Duration::hours(24),
+    now - chrono::Duration::days(7),
+    100,
+  )
+  .await
+  .expect("Failed to find expiring classifications");
+
+  let domains: Vec<&str> = expiring.iter().map(|e| e.domain.as_str()).collect();
+  assert_eq!(domains, vec!["due.com"]);
+  assert_eq!(expiring[0].classification_type, "gaming");
+
+  let due_types = ExpiringClassification::types_for_domain(
+    &pool,
+    "due.com",
+    now + chrono::Duration::hours(24),
+  )
+  .await
+  .expect("Failed to find due types");
+  assert_eq!(due_types, vec!["gaming".to_string()]);
+
+  // Once reclassified with a fresh TTL the refresh is no longer due.
+  insert_expiring(&pool, source_id, "due.com", "gaming", true, 1, 240).await;
+  let due_types = ExpiringClassification::types_for_domain(
+    &pool,
+    "due.com",
+    now + chrono::Duration::hours(24),
+  )
+  .await
+  .expect("Failed to find due types");
+  assert!(due_types.is_empty());
+}
+
+#[tokio::test]
+#[serial]
+
+async fn test_domain_seen_touch_leaves_last_updated() {
+  let (_db, pool) = setup_test_db().await;
+
+  sqlx::query(
+    r#"
+        INSERT INTO domains (domain, last_updated, last_seen)
+        VALUES ('seen.com', NOW() - INTERVAL '3 days', NOW() - INTERVAL '3 days')
+        "#,
+  )
+  .execute(&pool)
+  .await
+  .expect("Failed to insert domain");
+
+  DomainSeen {
+    domain: "seen.com".to_string(),
+  }
+  .touch(&pool)
+  .await
+  .expect("Failed to touch domain");
+
+  let row = sqlx::query(
+    r#"
+        SELECT last_updated < NOW() - INTERVAL '2 days' AS updated_is_old,
+               last_seen > NOW() - INTERVAL '1 minute' AS seen_is_recent
+        FROM domains WHERE domain = 'seen.com'
+        "#,
+  )
+  .fetch_one(&pool)
+  .await
+  .expect("Failed to fetch domain");
+  assert!(row.get::<bool, _>("updated_is_old"));
+  assert!(row.get::<bool, _>("seen_is_recent"));
+}
+
+#[tokio::test]
+#[serial]
+
+async fn test_llm_backend_status_record_upserts() {
+  let (_db, pool) = setup_test_db().await;
+
+  LlmBackendStatus::record(&pool, "http://ollama:11434", false)
+    .await
+    .expect("Failed to record closed circuit");
+  LlmBackendStatus::record(&pool, "http://ollama:11434", true)
+    .await
+    .expect("Failed to record open circuit");
+
+  let statuses = LlmBackendStatus::fetch_all(&pool)
+    .await
+    .expect("Failed to fetch backend status");
+  assert_eq!(statuses.len(), 1);
+  assert_eq!(statuses[0].backend, "http://ollama:11434");
+  assert!(statuses[0].circuit_open);
+}
+
+#[tokio::test]
+#[serial]
+
+async fn test_review_upsert_replaces_pending_and_supersede_closes_it() {
+  let (_db, pool) = setup_test_db().await;
+
+  sqlx::query("INSERT INTO domains (domain) VALUES ('unsure.com')")
+    .execute(&pool)
+    .await
+    .expect("Failed to insert domain");
+
+  let review = |confidence: f32| ReviewInsert {
+    domain: "unsure.com".to_string(),
+    classification_type: "gaming".to_string(),
+    is_matching_site: true,
+    confidence,
+    reasoning: None,
+    model: "test-model".to_string(),
+    source_id: None,
+  };
+  let first = review(0.4).upsert(&pool).await.expect("Failed to upsert");
+  let second = review(0.6).upsert(&pool).await.expect("Failed to upsert");
+  assert_eq!(first, second, "a newer result replaces the pending review");
+
+  let pending =
+    ClassificationReview::find_pending(&pool, &ReviewFilter::default())
+      .await
+      .expect("Failed to find reviews");
+  assert_eq!(pending.len(), 1);
+  assert_eq!(pending[0].confidence, 0.6);
+
+  let closed = ClassificationReview::supersede(&pool, "unsure.com", "gaming")
+    .await
+    .expect("Failed to supersede");
+  assert_eq!(closed, 1);
+  let pending =
+    ClassificationReview::find_pending(&pool, &ReviewFilter::default())
+      .await
+      .expect("Failed to find reviews");
+  assert!(pending.is_empty());
+}
+
+#[tokio::test]
+#[serial]
+
+async fn test_shadow_states_come_from_events() {
+  let (_db, pool) = setup_test_db().await;
+
+  let domain = "shadowed.com";
+  insert_event(
+    &pool,
+    domain,
+    "classified",
+    json!({
+        "classification_type": "shadow:gaming",
+        "is_matching_site": true,
+        "confidence": 0.9
+    }),
+    None,
+  )
+  .await
+  .expect("Failed to insert classified event");
+
+  let now = chrono::Utc::now();
+  let shadow_types =
+    vec!["shadow:gaming".to_string(), "shadow:news".to_string()];
+
+  let states = ClassifierState::shadow_states(
+    &pool,
+    domain,
+    &shadow_types,
+    &[
+      now - chrono::Duration::days(1),
+      now - chrono::Duration::days(1),
+    ],
+  )
+  .await
+  .expect("Failed to get shadow states");
+  assert!(
+    states.contains(&("shadow:gaming".to_string(), ClassifierState::Current))
+  );
+  assert!(
+    states.contains(&("shadow:news".to_string(), ClassifierState::Missing))
+  );
+
+  // A result older than the TTL window is due again.
+  let states = ClassifierState::shadow_states(
+    &pool,
+    domain,
+    &shadow_types[..1],
+    &[now + chrono::Duration::seconds(1)],
+  )
+  .await
+  .expect("Failed to get shadow states");
+  assert_eq!(
+    states,
+    vec![("shadow:gaming".to_string(), ClassifierState::Expired)]
+  );
+
+  // Shadow results are never projected.
+  let live =
+    ClassifierState::domain_states(&pool, domain, &["gaming".to_string()])
+      .await
+      .expect("Failed to get classifier states");
+  assert_eq!(live[0].1, ClassifierState::Missing);
+}
+
+#[tokio::test]
+#[serial]
+
+async fn test_stale_classifications_follow_prompt_and_model() {
+  let (_db, pool) = setup_test_db().await;
+
+  for (domain, model, hash) in [
+    ("same.com", "llama3.2:3b", "sha256:new"),
+    ("old-prompt.com", "llama3.2:3b", "sha256:old"),
+    ("old-model.com", "llama2", "sha256:new"),
+    ("escalated.com", "llama3.1:8b", "sha256:new"),
+  ] {
+    classification_store(
+      &pool,
+      domain,
+      "gaming",
+      true,
+      0.9,
+      "reasoning",
+      model,
+      hash,
+      hash,
+      &TtlPolicy::fixed(10),
+      false,
+    )
+    .await
+    .expect("Failed to store classification");
+  }
+
+  // Admin decisions are never reclassified, whatever came before them.
+  classification_store(
+    &pool,
+    "admin.com",
+    "gaming",
+    true,
+    0.9,
+    "reasoning",
+    "llama2",
+    "sha256:old",
+    "sha256:old",
+    &TtlPolicy::fixed(10),
+    false,
+  )
+  .await
+  .expect("Failed to store classification");
+  apply_admin_classification(
+    &pool,
+    "admin.com",
+    "gaming",
+    false,
+    1.0,
+    "Manually classified",
+    1,
+    None,
+  )
+  .await
+  .expect("apply_admin_classification failed");
+
+  let models = vec!["llama3.2:3b".to_string(), "llama3.1:8b".to_string()];
+  let stale =
+    StaleClassification::find_domains(&pool, "gaming", "sha256:new", &models)
+      .await
+      .expect("Failed to find stale classifications");
+  assert_eq!(stale, vec!["old-model.com", "old-prompt.com"]);
+
+  let types = vec!["gaming".to_string(), "video".to_string()];
+  let current = |domain: &'static str| {
+    let pool = pool.clone();
+    let types = types.clone();
+    async move {
+      StaleClassification::current_for_domain(&pool, domain, &types)
+        .await
+        .expect("Failed to find current classifications")
+    }
+  };
+
+  let old_prompt = current("old-prompt.com").await;
+  assert_eq!(
+    old_prompt,
+    vec![CurrentLlmClassification {
+      classification_type: "gaming".to_string(),
+      prompt_hash: Some("sha256:old".to_string()),
+      model: "llama3.2:3b".to_string(),
+    }]
+  );
+  assert!(old_prompt[0].is_stale("sha256:new", &models));
+  assert!(!current("same.com").await[0].is_stale("sha256:new", &models));
+  assert!(current("old-model.com").await[0].is_stale("sha256:new", &models));
+  assert!(current("admin.com").await.is_empty());
+}
+
+#[tokio::test]
+#[serial]
+
+async fn test_classification_store_applies_ttl_policy() {
+  let (_db, pool) = setup_test_db().await;
+
+  let policy = TtlPolicy {
+    matching_days: 30,
+    not_matching_days: 10,
+    scale_by_confidence: true,
+    fetch_error_days: Some(1),
+  };
+  for (domain, is_matching_site, fetch_failed) in [
+    ("blocked.com", true, false),
+    ("allowed.com", false, false),
+    ("unreachable.com", true, true),
+  ] {
+    classification_store(
+      &pool,
+      domain,
+      "gaming",
+      is_matching_site,
+      0.5,
+      "reasoning",
+      "llama2",
+      "test prompt",
+      "sha256:test",
+      &policy,
+      fetch_failed,
+    )
+    .await
+    .expect("Failed to store classification");
+  }
+
+  let lifetimes: Vec<(String, f64)> = sqlx::query_as(
+    r#"
+        SELECT domain,
+               EXTRACT(EPOCH FROM valid_until - valid_on)::float8 / 86400
+        FROM domain_classifications
+        ORDER BY domain
+        "#,
+  )
+  .fetch_all(&pool)
+  .await
+  .expect("Failed to fetch lifetimes");
+
+  let days = |domain: &str| {
+    lifetimes
+      .iter()
+      .find(|(d, _)| d == domain)
+      .map(|(_, days)| *days)
+      .unwrap()
+  };
+  // Half confidence halves the verdict's lifetime; a fetch error caps it.
+  assert!((days("blocked.com") - 15.0).abs() < 1e-3);
+  assert!((days("allowed.com") - 5.0).abs() < 1e-3);
+  assert!((days("unreachable.com") - 1.0).abs() < 1e-3);
+}
+
+#[tokio::test]
+#[serial]
+
+async fn test_classification_store_supersedes_previous_llm_result() {
+  let (_db, pool) = setup_test_db().await;
+
+  for (is_matching_site, confidence) in [(true, 0.95), (false, 0.6)] {
+    classification_store(
+      &pool,
+      "reclassified.com",
+      "gaming",
+      is_matching_site,
+      confidence,
+      "reasoning",
+      "llama2",
+      "test prompt",
+      "sha256:test",
+      &TtlPolicy::fixed(10),
+      false,
+    )
+    .await
+    .expect("Failed to store classification");
+  }
+
+  // Both results are kept, whatever their confidence, but only the latest
+  // is current.
+  let current: Vec<(bool, f32)> = sqlx::query_as(
+    r#"
+        SELECT is_matching_site, confidence
+        FROM domain_classifications
+        WHERE domain = 'reclassified.com' AND valid_until > NOW()
+        "#,
+  )
+  .fetch_all(&pool)
+  .await
+  .expect("Failed to fetch classifications");
+  assert_eq!(current, vec![(false, 0.6)]);
+
+  let total: i64 = sqlx::query_scalar(
+    "SELECT COUNT(*) FROM domain_classifications WHERE domain = 'reclassified.com'",
+  )
+  .fetch_one(&pool)
+  .await
+  .unwrap();
+  assert_eq!(total, 2);
+}
+
+#[tokio::test]
+#[serial]
+
+async fn test_redirect_target_reuses_final_host_classification() {
+  let (_db, pool) = setup_test_db().await;
+
+  classification_store(
+    &pool,
+    "store.example",
+    "gaming",
+    true,
+    0.9,
+    "A game store",
+    "llama2",
+    "test prompt",
+    "sha256:test",
+    &TtlPolicy::fixed(10),
+    false,
+  )
+  .await
+  .expect("Failed to store classification");
+
+  for (domain, final_host) in [
+    ("parked.example", "store.example"),
+    ("store.example", "store.example"),
+  ] {
+    insert_event(
+      &pool,
+      domain,
+      "classified",
+      json!({"classification_type": "gaming", "final_host": final_host}),
+      None,
+    )
+    .await
+    .expect("Failed to insert classified event");
+  }
+
+  let target = RedirectTarget::find(&pool, "parked.example", "gaming")
+    .await
+    .expect("Failed to find redirect target")
+    .expect("Expected a redirect target");
+  assert_eq!(target.host, "store.example");
+  assert!(target.is_matching_site);
+  assert_eq!(target.confidence, 0.9);
+  assert_eq!(target.reasoning.as_deref(), Some("A game store"));
+  assert_eq!(target.model, "llama2");
+  assert_eq!(target.prompt_hash, "sha256:test");
+
+  // Another classifier, a domain that redirects to itself and one that was
+  // never classified have nothing to reuse.
+  for (domain, classification_type) in [
+    ("parked.example", "adult"),
+    ("store.example", "gaming"),
+    ("unseen.example", "gaming"),
+  ] {
+    let target = RedirectTarget::find(&pool, domain, classification_type)
+      .await
+      .expect("Failed to find redirect target");
+    assert!(target.is_none(), "{domain} ({classification_type})");
+  }
+
+  // A reuse records no final host, so the next classification fetches.
+  insert_event(
+    &pool,
+    "parked.example",
+    "classified",
+    json!({"classification_type": "gaming", "reused_from": "store.example"}),
+    None,
+  )
+  .await
+  .expect("Failed to insert classified event");
+  let target = RedirectTarget::find(&pool, "parked.example", "gaming")
+    .await
+    .expect("Failed to find redirect target");
+  assert!(target.is_none());
+}
+
+#[tokio::test]
+#[serial]
+
+async fn test_llm_result_cache_keeps_one_answer_per_model() {
+  let (_db, pool) = setup_test_db().await;
+
+  sqlx::query("INSERT INTO domains (domain) VALUES ('game.com')")
+    .execute(&pool)
+    .await
+    .expect("Failed to insert domain");
+
+  let answer = |model: &str, key: &str, confidence: f64| CachedLlmResult {
+    model: model.to_string(),
+    cache_key: key.to_string(),
+    is_matching_site: true,
+    confidence,
+    reasoning: "Game store".to_string(),
+  };
+  for result in [
+    answer("llama3.2:3b", "sha256:aa", 0.7),
+    answer("qwen2.5:7b", "sha256:aa", 0.9),
+    answer("llama3.2:3b", "sha256:bb", 0.8),
+  ] {
+    result
+      .upsert(&pool, "game.com", "gaming")
+      .await
+      .expect("Failed to cache result");
+  }
+
+  let cached = CachedLlmResult::find(&pool, "game.com", "gaming")
+    .await
+    .expect("Failed to find cached results");
+  assert_eq!(cached.len(), 2);
+  assert_eq!(cached[0].model, "llama3.2:3b");
+  assert_eq!(cached[0].cache_key, "sha256:bb");
+  assert_eq!(cached[0].confidence, 0.8);
+  assert_eq!(cached[1].model, "qwen2.5:7b");
+
+  let other = CachedLlmResult::find(&pool, "game.com", "video")
+    .await
+    .expect("Failed to find cached results");
+  assert!(other.is_empty());
+}
@@ -6,6 +6,7 @@ use dns_smart_block_common::db::{
   reconcile_all_provisioned, reconcile_provisioned_classifications,
   reconcile_provisioned_patterns,
 };
+use dns_smart_block_common::ttl::TtlPolicy;
 use dns_smart_block_queue_processor::db::{
   exclude_rule_classify, insert_event,
 };
@@ -304,7 +305,8 @@ async fn test_llm_classification_source_chain() {
     "llama2",
     prompt_content,
     prompt_hash,
-    10,
+    &TtlPolicy::fixed(10),
+    false,
   )
   .await
   .expect("classification_store failed");
@@ -864,7 +866,8 @@ async fn test_apply_pattern_expires_llm_classifications() {
     "test-model",
     "test prompt",
     "sha256:test",
-    30,
+    &TtlPolicy::fixed(30),
+    false,
   )
   .await
   .expect("classification_store failed");